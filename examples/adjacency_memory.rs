@@ -0,0 +1,118 @@
+//! Reports heap bytes per node held by the neighbor lists of an HNSW
+//! graph, in the per-node layout HNSW used to have and in `Adjacency`.
+//! Both are filled with the same lists, read back from one built graph.
+//!
+//! cargo run --release --example adjacency_memory [num_vectors]
+//!
+//! The old layout gets every list at its exact length, while the old
+//! insert path grew lists by pushing, so its figure is a lower bound.
+
+use dashmap::DashMap;
+use embeded_db::application::hnsw::{BuildConfig, BuildControl, HNSW};
+use embeded_db::linalg::vector::Vector;
+use embeded_db::storage::adjacency::Adjacency;
+use embeded_db::storage::node::{LayerNum, NodeId};
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
+use std::alloc::{GlobalAlloc, Layout, System};
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Arc, RwLock};
+
+struct CountingAllocator;
+
+static ALLOCATED: AtomicUsize = AtomicUsize::new(0);
+
+unsafe impl GlobalAlloc for CountingAllocator {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        ALLOCATED.fetch_add(layout.size(), Ordering::Relaxed);
+        unsafe { System.alloc(layout) }
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        ALLOCATED.fetch_sub(layout.size(), Ordering::Relaxed);
+        unsafe { System.dealloc(ptr, layout) }
+    }
+}
+
+#[global_allocator]
+static GLOBAL: CountingAllocator = CountingAllocator;
+
+/// Per-node links as HNSW stored them before `Adjacency`.
+type PerNodeLinks = DashMap<NodeId, Arc<RwLock<HashMap<LayerNum, Vec<NodeId>>>>>;
+
+/// Heap bytes still held by the value `build` returns.
+fn measure<R>(build: impl FnOnce() -> R) -> (R, usize) {
+    let before = ALLOCATED.load(Ordering::Relaxed);
+    let value = build();
+    (value, ALLOCATED.load(Ordering::Relaxed) - before)
+}
+
+fn main() {
+    let num_vectors: usize = std::env::args()
+        .nth(1)
+        .and_then(|arg| arg.parse().ok())
+        .unwrap_or(20_000);
+    let mut rng = StdRng::seed_from_u64(0);
+    let vectors: Vec<Vector<f32>> = (0..num_vectors)
+        .map(|_| Vector::new((0..32).map(|_| rng.r#gen::<f32>()).collect()))
+        .collect();
+    let config = BuildConfig::default();
+    let hnsw =
+        HNSW::build_from(vectors.iter().cloned(), 1, &config, BuildControl::default()).unwrap();
+
+    // Every node's lists from layer 0 up to its first empty one.
+    let lists: Vec<Vec<Vec<NodeId>>> = (0..num_vectors)
+        .map(|node_id| {
+            let mut layers = vec![hnsw.neighbor_ids(&node_id, 0)];
+            loop {
+                let ids = hnsw.neighbor_ids(&node_id, layers.len());
+                if ids.is_empty() {
+                    break layers;
+                }
+                layers.push(ids);
+            }
+        })
+        .collect();
+
+    let (per_node, per_node_bytes) = measure(|| {
+        let links = PerNodeLinks::new();
+        for (node_id, layers) in lists.iter().enumerate() {
+            let layers = layers.iter().cloned().enumerate().collect();
+            links.insert(node_id, Arc::new(RwLock::new(layers)));
+        }
+        links
+    });
+    let (adjacency, adjacency_bytes) = measure(|| {
+        let adjacency = Adjacency::new();
+        adjacency.fix_capacity(config.m_max);
+        for (node_id, layers) in lists.iter().enumerate() {
+            for (layer, ids) in layers.iter().enumerate() {
+                adjacency.update(node_id, layer, |list| list.clone_from(ids));
+            }
+        }
+        adjacency
+    });
+    assert_eq!(per_node.len(), num_vectors);
+    assert_eq!(adjacency.neighbor_ids(0, 0), lists[0][0]);
+
+    println!(
+        "{} nodes, m = {}, m_max = {}",
+        num_vectors, config.m, config.m_max
+    );
+    for (layout, bytes) in [
+        (
+            "DashMap<NodeId, Arc<RwLock<HashMap<_, Vec<usize>>>>>",
+            per_node_bytes,
+        ),
+        (
+            "Adjacency: flat u32 layer 0, sparse upper layers",
+            adjacency_bytes,
+        ),
+    ] {
+        println!(
+            "{layout:<55} {bytes:>10} heap bytes, {:>7.1} bytes per node",
+            bytes as f64 / num_vectors as f64
+        );
+    }
+}
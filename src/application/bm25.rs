@@ -0,0 +1,284 @@
+use crate::storage::node::NodeId;
+use crate::storage::{self, Storage};
+use std::collections::{BTreeSet, HashMap};
+use std::io;
+
+/// BM25 term-saturation (`k1`) and length-normalization (`b`) parameters.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct BM25Config {
+    pub k1: f64,
+    pub b: f64,
+}
+
+impl Default for BM25Config {
+    fn default() -> Self {
+        Self { k1: 1.2, b: 0.75 }
+    }
+}
+
+/// Inverted index over document text with BM25 scoring. Postings and
+/// document records live in a `Storage` under `prefix`, so they go through
+/// the WAL and are flushed into SSTables like any other key:
+///
+/// - `{prefix}p/{term}\0{doc_id}` holds the term frequency and the document
+///   length, so scoring a posting needs no extra lookup.
+/// - `{prefix}d/{doc_id}` holds the document's distinct terms, for deletes.
+/// - `{prefix}stats` holds the document count and total length.
+pub struct BM25Index {
+    prefix: Vec<u8>,
+    config: BM25Config,
+    num_docs: u64,
+    total_length: u64,
+}
+
+/// Lowercased alphanumeric runs of `text`. Product codes like `AB-1234`
+/// become `ab` and `1234`, which still match the same code in a query.
+pub fn tokenize(text: &str) -> Vec<String> {
+    text.split(|c: char| !c.is_alphanumeric())
+        .filter(|token| !token.is_empty())
+        .map(|token| token.to_lowercase())
+        .collect()
+}
+
+impl BM25Index {
+    /// Loads the index statistics stored under `prefix`, or starts an empty
+    /// index if there are none.
+    pub fn open(storage: &Storage, prefix: &[u8], config: BM25Config) -> io::Result<Self> {
+        let mut index = Self {
+            prefix: prefix.to_vec(),
+            config,
+            num_docs: 0,
+            total_length: 0,
+        };
+        if let Some(entry) = storage.get(&index.stats_key())? {
+            let value = entry.value.unwrap_or_default();
+            if value.len() != 16 {
+                return Err(io::Error::new(
+                    io::ErrorKind::InvalidData,
+                    "Corrupt BM25 statistics",
+                ));
+            }
+            index.num_docs = u64::from_le_bytes(value[0..8].try_into().unwrap());
+            index.total_length = u64::from_le_bytes(value[8..16].try_into().unwrap());
+        }
+        Ok(index)
+    }
+
+    pub fn len(&self) -> usize {
+        self.num_docs as usize
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.num_docs == 0
+    }
+
+    fn stats_key(&self) -> Vec<u8> {
+        [&self.prefix[..], b"stats"].concat()
+    }
+
+    fn doc_key(&self, doc_id: NodeId) -> Vec<u8> {
+        [&self.prefix[..], b"d/", &(doc_id as u64).to_be_bytes()].concat()
+    }
+
+    fn term_prefix(&self, term: &str) -> Vec<u8> {
+        [&self.prefix[..], b"p/", term.as_bytes(), b"\0"].concat()
+    }
+
+    fn posting_key(&self, term: &str, doc_id: NodeId) -> Vec<u8> {
+        [
+            self.term_prefix(term),
+            (doc_id as u64).to_be_bytes().to_vec(),
+        ]
+        .concat()
+    }
+
+    fn write_stats(&self, storage: &mut Storage, timestamp: u128) -> io::Result<()> {
+        let mut value = Vec::with_capacity(16);
+        value.extend_from_slice(&self.num_docs.to_le_bytes());
+        value.extend_from_slice(&self.total_length.to_le_bytes());
+        storage.set(&self.stats_key(), &value, timestamp)
+    }
+
+    /// Indexes `text` under `doc_id`, replacing any previous text.
+    pub fn insert(&mut self, storage: &mut Storage, doc_id: NodeId, text: &str) -> io::Result<()> {
+        self.delete(storage, doc_id)?;
+        let timestamp = storage::timestamp();
+
+        let tokens = tokenize(text);
+        let length = tokens.len() as u32;
+        let mut frequencies: HashMap<String, u32> = HashMap::new();
+        for token in tokens {
+            *frequencies.entry(token).or_default() += 1;
+        }
+
+        for (term, frequency) in &frequencies {
+            let mut posting = Vec::with_capacity(8);
+            posting.extend_from_slice(&frequency.to_le_bytes());
+            posting.extend_from_slice(&length.to_le_bytes());
+            storage.set(&self.posting_key(term, doc_id), &posting, timestamp)?;
+        }
+        let terms: BTreeSet<&str> = frequencies.keys().map(String::as_str).collect();
+        let record = terms.into_iter().collect::<Vec<_>>().join("\0");
+        let mut value = length.to_le_bytes().to_vec();
+        value.extend_from_slice(record.as_bytes());
+        storage.set(&self.doc_key(doc_id), &value, timestamp)?;
+
+        self.num_docs += 1;
+        self.total_length += length as u64;
+        self.write_stats(storage, timestamp)
+    }
+
+    /// Removes `doc_id` and its postings. Returns `false` if it was not
+    /// indexed.
+    pub fn delete(&mut self, storage: &mut Storage, doc_id: NodeId) -> io::Result<bool> {
+        let Some(entry) = storage.get(&self.doc_key(doc_id))? else {
+            return Ok(false);
+        };
+        let value = entry.value.unwrap_or_default();
+        if value.len() < 4 {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                "Corrupt BM25 document record",
+            ));
+        }
+        let length = u32::from_le_bytes(value[0..4].try_into().unwrap());
+        let terms = String::from_utf8_lossy(&value[4..]).into_owned();
+
+        let timestamp = storage::timestamp();
+        for term in terms.split('\0').filter(|term| !term.is_empty()) {
+            storage.delete(&self.posting_key(term, doc_id), timestamp)?;
+        }
+        storage.delete(&self.doc_key(doc_id), timestamp)?;
+
+        self.num_docs = self.num_docs.saturating_sub(1);
+        self.total_length = self.total_length.saturating_sub(length as u64);
+        self.write_stats(storage, timestamp)?;
+        Ok(true)
+    }
+
+    /// Returns up to `k` documents matching any term of `text`, by
+    /// descending BM25 score.
+    pub fn search(
+        &self,
+        storage: &Storage,
+        text: &str,
+        k: usize,
+    ) -> io::Result<Vec<(NodeId, f64)>> {
+        if self.num_docs == 0 {
+            return Ok(Vec::new());
+        }
+        let num_docs = self.num_docs as f64;
+        let average_length = self.total_length as f64 / num_docs;
+        let BM25Config { k1, b } = self.config;
+
+        let mut terms = tokenize(text);
+        terms.sort();
+        terms.dedup();
+
+        let mut scores: HashMap<NodeId, f64> = HashMap::new();
+        for term in terms {
+            let term_prefix = self.term_prefix(&term);
+            let postings = storage.scan_prefix(&term_prefix)?;
+            let doc_frequency = postings.len() as f64;
+            let idf = (1.0 + (num_docs - doc_frequency + 0.5) / (doc_frequency + 0.5)).ln();
+
+            for posting in postings {
+                let value = posting.value.unwrap_or_default();
+                let doc_id = posting.key[term_prefix.len()..]
+                    .try_into()
+                    .map(u64::from_be_bytes);
+                let (Ok(doc_id), 8) = (doc_id, value.len()) else {
+                    return Err(io::Error::new(
+                        io::ErrorKind::InvalidData,
+                        "Corrupt BM25 posting",
+                    ));
+                };
+                let frequency = u32::from_le_bytes(value[0..4].try_into().unwrap()) as f64;
+                let length = u32::from_le_bytes(value[4..8].try_into().unwrap()) as f64;
+                let relative_length = if average_length > 0.0 {
+                    length / average_length
+                } else {
+                    1.0
+                };
+                let normalization = k1 * (1.0 - b + b * relative_length);
+                *scores.entry(doc_id as NodeId).or_default() +=
+                    idf * frequency * (k1 + 1.0) / (frequency + normalization);
+            }
+        }
+
+        let mut ranked: Vec<(NodeId, f64)> = scores.into_iter().collect();
+        ranked.sort_by(|a, b| b.1.total_cmp(&a.1).then(a.0.cmp(&b.0)));
+        ranked.truncate(k);
+        Ok(ranked)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::tempdir;
+
+    #[test]
+    fn test_tokenize() {
+        assert_eq!(
+            tokenize("Blue T-Shirt, SKU AB-1234!"),
+            vec!["blue", "t", "shirt", "sku", "ab", "1234"]
+        );
+        assert!(tokenize("  --  ").is_empty());
+    }
+
+    #[test]
+    fn test_bm25_ranks_rare_terms_higher() -> io::Result<()> {
+        let dir = tempdir()?;
+        let mut storage = Storage::open(dir.path())?;
+        let mut index = BM25Index::open(&storage, b"text/", BM25Config::default())?;
+        index.insert(&mut storage, 0, "red running shoes")?;
+        index.insert(&mut storage, 1, "blue running shoes XK-9001")?;
+        index.insert(&mut storage, 2, "red wool socks")?;
+
+        let results = index.search(&storage, "xk-9001 shoes", 10)?;
+
+        assert_eq!(results[0].0, 1, "The product code match should rank first.");
+        assert_eq!(results.len(), 2);
+        assert!(index.search(&storage, "umbrella", 10)?.is_empty());
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_bm25_persists_and_deletes() -> io::Result<()> {
+        let dir = tempdir()?;
+        {
+            let mut storage = Storage::open(dir.path())?;
+            let mut index = BM25Index::open(&storage, b"text/", BM25Config::default())?;
+            index.insert(&mut storage, 0, "alpha beta")?;
+            index.insert(&mut storage, 1, "beta gamma")?;
+            storage.checkpoint()?;
+            index.insert(&mut storage, 1, "delta")?;
+            index.insert(&mut storage, 2, "beta")?;
+            assert!(index.delete(&mut storage, 2)?);
+            assert!(!index.delete(&mut storage, 7)?);
+            storage.flush()?;
+        }
+
+        let storage = Storage::open(dir.path())?;
+        let index = BM25Index::open(&storage, b"text/", BM25Config::default())?;
+
+        assert_eq!(index.len(), 2);
+        let ids = |text: &str| -> io::Result<Vec<NodeId>> {
+            Ok(index
+                .search(&storage, text, 10)?
+                .into_iter()
+                .map(|(doc_id, _)| doc_id)
+                .collect())
+        };
+        assert_eq!(
+            ids("beta")?,
+            vec![0],
+            "Replaced and deleted text should not match."
+        );
+        assert_eq!(ids("delta")?, vec![1]);
+
+        Ok(())
+    }
+}
@@ -0,0 +1,624 @@
+use super::disk_ann::DiskANN;
+use super::index::IndexConfig;
+use super::vector_index::VectorIndex;
+use crate::error::Result;
+use crate::linalg::element::Element;
+use crate::linalg::metric::Metric;
+use crate::linalg::vector::Vector;
+use crate::storage::node::NodeId;
+use crate::storage::{self, Storage};
+use std::collections::BTreeMap;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+const CATALOG_PREFIX: &[u8] = b"catalog/";
+const COLLECTION_PREFIX: &[u8] = b"col/";
+
+/// Fixed properties of a collection, chosen when it is created.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct CollectionSchema {
+    pub dimension: usize,
+    pub metric: Metric,
+    pub index: IndexConfig,
+}
+
+impl CollectionSchema {
+    /// Encodes as `[dimension u64 LE][metric tag u8][index config]`.
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut bytes = (self.dimension as u64).to_le_bytes().to_vec();
+        bytes.push(self.metric.tag());
+        bytes.extend_from_slice(&self.index.to_bytes());
+        bytes
+    }
+
+    pub fn from_bytes(bytes: &[u8]) -> Option<Self> {
+        let dimension = u64::from_le_bytes(bytes.get(..8)?.try_into().ok()?) as usize;
+        Some(Self {
+            dimension,
+            metric: Metric::from_tag(*bytes.get(8)?)?,
+            index: IndexConfig::from_bytes(bytes.get(9..)?)?,
+        })
+    }
+}
+
+/// One named vector space with its own schema and index.
+pub struct Collection<T: Element = f64> {
+    schema: CollectionSchema,
+    index: Box<dyn VectorIndex<T>>,
+    len: usize,
+    /// Set once a DiskANN graph replaced the index; it takes no inserts.
+    built: bool,
+}
+
+impl<T: Element> Collection<T> {
+    fn new(schema: CollectionSchema) -> Result<Self> {
+        Ok(Self {
+            schema,
+            index: schema.index.new_index(schema.metric)?,
+            len: 0,
+            built: false,
+        })
+    }
+
+    /// Whether the DiskANN graph has been built; see `Database::build_index`.
+    pub fn is_built(&self) -> bool {
+        self.built
+    }
+
+    pub fn schema(&self) -> &CollectionSchema {
+        &self.schema
+    }
+
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    /// Up to `k` nearest node ids with their distances, closest first.
+    pub fn search(&self, query: &Vector<T>, k: usize) -> Result<Vec<(NodeId, f64)>> {
+        self.index.search(query, k)
+    }
+}
+
+/// Named collections sharing one `Storage`, and with it one WAL and one set
+/// of SSTables. Each collection's schema is kept under `catalog/{name}` and
+/// its vectors under `col/{name}/{node_id}`; indexes are rebuilt from the
+/// stored vectors on open. A built DiskANN graph is kept next to the
+/// storage files as `{name}.dann` and reopened instead.
+pub struct Database<T: Element = f64> {
+    dir: PathBuf,
+    storage: Storage,
+    collections: BTreeMap<String, Collection<T>>,
+}
+
+fn invalid_input(message: String) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidInput, message)
+}
+
+fn invalid_data(message: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message)
+}
+
+fn disk_index_path(dir: &Path, name: &str) -> PathBuf {
+    dir.join(format!("{name}.dann"))
+}
+
+fn vectors_prefix(name: &str) -> Vec<u8> {
+    [COLLECTION_PREFIX, name.as_bytes(), b"/"].concat()
+}
+
+fn vector_key_id(prefix: &[u8], key: &[u8]) -> Option<NodeId> {
+    let id = key.strip_prefix(prefix)?;
+    Some(u64::from_be_bytes(id.try_into().ok()?) as NodeId)
+}
+
+/// The vectors stored for collection `name`, in id order.
+fn stored_vectors<T: Element>(storage: &Storage, name: &str) -> io::Result<Vec<Vector<T>>> {
+    let prefix = vectors_prefix(name);
+    let entries = storage.scan_prefix(&prefix)?;
+    let mut vectors = Vec::with_capacity(entries.len());
+    for (expected_id, entry) in entries.into_iter().enumerate() {
+        let vector = entry.value.as_deref().and_then(Vector::<T>::from_bytes);
+        let node_id = vector_key_id(&prefix, &entry.key);
+        let (Some(vector), Some(node_id)) = (vector, node_id) else {
+            return Err(invalid_data("Corrupt stored vector"));
+        };
+        if node_id != expected_id {
+            return Err(invalid_data("Stored vector ids are not contiguous"));
+        }
+        vectors.push(vector);
+    }
+    Ok(vectors)
+}
+
+fn remove_if_exists(path: &Path) -> io::Result<()> {
+    match fs::remove_file(path) {
+        Err(error) if error.kind() != io::ErrorKind::NotFound => Err(error),
+        _ => Ok(()),
+    }
+}
+
+impl<T: Element> Database<T> {
+    pub fn open(dir: &Path) -> io::Result<Self> {
+        let storage = Storage::open(dir)?;
+        let mut collections = BTreeMap::new();
+        for entry in storage.scan_prefix(CATALOG_PREFIX)? {
+            let name = String::from_utf8(entry.key[CATALOG_PREFIX.len()..].to_vec())
+                .map_err(|_| invalid_data("Corrupt collection name"))?;
+            let schema = entry
+                .value
+                .as_deref()
+                .and_then(CollectionSchema::from_bytes)
+                .ok_or_else(|| invalid_data("Corrupt collection schema"))?;
+
+            let mut collection = Collection::new(schema)?;
+            for vector in stored_vectors(&storage, &name)? {
+                collection.index.insert(vector)?;
+                collection.len += 1;
+            }
+            let path = disk_index_path(dir, &name);
+            if let IndexConfig::DiskANN { .. } = schema.index
+                && path.exists()
+            {
+                let (_, params) = schema.index.disk_ann(schema.metric)?;
+                let mut index = DiskANN::open(&path)?;
+                if index.len() != collection.len {
+                    return Err(invalid_data("Disk index does not match the stored vectors"));
+                }
+                index.set_params(params);
+                collection.index = Box::new(index);
+                collection.built = true;
+            }
+            collections.insert(name, collection);
+        }
+        Ok(Self {
+            dir: dir.to_owned(),
+            storage,
+            collections,
+        })
+    }
+
+    /// Collection names in sorted order.
+    pub fn list_collections(&self) -> Vec<&str> {
+        self.collections.keys().map(String::as_str).collect()
+    }
+
+    pub fn collection(&self, name: &str) -> Option<&Collection<T>> {
+        self.collections.get(name)
+    }
+
+    fn collection_or_err(&self, name: &str) -> io::Result<&Collection<T>> {
+        self.collections.get(name).ok_or_else(|| {
+            io::Error::new(
+                io::ErrorKind::NotFound,
+                format!("No collection named {name:?}"),
+            )
+        })
+    }
+
+    /// Names may not be empty or contain `/`, which separates key parts.
+    pub fn create_collection(&mut self, name: &str, schema: CollectionSchema) -> io::Result<()> {
+        if name.is_empty() || name.contains('/') {
+            return Err(invalid_input(format!("Invalid collection name {name:?}")));
+        }
+        if self.collections.contains_key(name) {
+            return Err(io::Error::new(
+                io::ErrorKind::AlreadyExists,
+                format!("Collection {name:?} already exists"),
+            ));
+        }
+        let collection = Collection::new(schema)?;
+        // A drop interrupted after its catalog delete leaves vectors behind.
+        self.delete_vectors(name)?;
+        remove_if_exists(&disk_index_path(&self.dir, name))?;
+        let key = [CATALOG_PREFIX, name.as_bytes()].concat();
+        self.storage
+            .set(&key, &schema.to_bytes(), storage::timestamp())?;
+        self.collections.insert(name.to_string(), collection);
+        Ok(())
+    }
+
+    /// Deletes the collection and all of its vectors. Returns `false` if it
+    /// does not exist. The catalog entry goes first, so a crash part way
+    /// through leaves no collection with only some of its vectors.
+    pub fn drop_collection(&mut self, name: &str) -> io::Result<bool> {
+        if self.collections.remove(name).is_none() {
+            return Ok(false);
+        }
+        let key = [CATALOG_PREFIX, name.as_bytes()].concat();
+        self.storage.delete(&key, storage::timestamp())?;
+        self.delete_vectors(name)?;
+        remove_if_exists(&disk_index_path(&self.dir, name))?;
+        Ok(true)
+    }
+
+    /// Builds the DiskANN graph of the named collection from its stored
+    /// vectors and searches it from then on. The collection takes no more
+    /// inserts afterwards; building again does nothing. Fails for other
+    /// index types and for empty collections.
+    pub fn build_index(&mut self, name: &str) -> io::Result<()> {
+        let collection = self.collection_or_err(name)?;
+        if collection.built {
+            return Ok(());
+        }
+        let schema = collection.schema;
+        let (config, params) = schema.index.disk_ann(schema.metric)?;
+        // The graph must not outlive the vectors it was built from.
+        self.storage.flush()?;
+        let vectors = stored_vectors::<T>(&self.storage, name)?;
+        // Built under another name first, so `open` never finds half a file.
+        let path = disk_index_path(&self.dir, name);
+        let mut staging = path.clone().into_os_string();
+        staging.push(".tmp");
+        let mut index = DiskANN::build(&vectors, &config, Path::new(&staging))?;
+        fs::rename(&staging, &path)?;
+        index.set_params(params);
+
+        let collection = self.collections.get_mut(name).unwrap();
+        collection.index = Box::new(index);
+        collection.built = true;
+        Ok(())
+    }
+
+    fn delete_vectors(&mut self, name: &str) -> io::Result<()> {
+        let timestamp = storage::timestamp();
+        for entry in self.storage.scan_prefix(&vectors_prefix(name))? {
+            self.storage.delete(&entry.key, timestamp)?;
+        }
+        Ok(())
+    }
+
+    /// Adds `vector` to the named collection, rejecting vectors whose length
+    /// differs from the collection's dimension.
+    pub fn insert(&mut self, name: &str, vector: Vector<T>) -> io::Result<NodeId> {
+        let collection = self.collection_or_err(name)?;
+        if collection.built {
+            return Err(io::Error::new(
+                io::ErrorKind::Unsupported,
+                format!("Collection {name:?} has a built disk index and takes no inserts"),
+            ));
+        }
+        vector.validate(Some(collection.schema.dimension))?;
+        let collection = self.collections.get_mut(name).unwrap();
+        let node_id = collection.len;
+        let key = [
+            vectors_prefix(name),
+            (node_id as u64).to_be_bytes().to_vec(),
+        ]
+        .concat();
+        self.storage
+            .set(&key, &vector.to_bytes(), storage::timestamp())?;
+        collection.index.insert(vector)?;
+        collection.len += 1;
+        Ok(node_id)
+    }
+
+    /// Up to `k` nearest node ids in the named collection, with their
+    /// distances, closest first.
+    pub fn search(
+        &self,
+        name: &str,
+        query: &Vector<T>,
+        k: usize,
+    ) -> io::Result<Vec<(NodeId, f64)>> {
+        let collection = self.collection_or_err(name)?;
+        query.validate(Some(collection.schema.dimension))?;
+        Ok(collection.search(query, k)?)
+    }
+
+    pub fn flush(&mut self) -> io::Result<()> {
+        self.storage.flush()
+    }
+
+    pub fn checkpoint(&mut self) -> io::Result<()> {
+        self.storage.checkpoint()
+    }
+
+    pub fn compact(&mut self) -> io::Result<()> {
+        self.storage.compact()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::tempdir;
+
+    fn schema(dimension: usize, metric: Metric, index: IndexConfig) -> CollectionSchema {
+        CollectionSchema {
+            dimension,
+            metric,
+            index,
+        }
+    }
+
+    fn ivf_pq() -> IndexConfig {
+        IndexConfig::IVFPQ {
+            num_lists: 2,
+            nprobe: 2,
+            num_subspaces: 2,
+            num_centroids: 16,
+        }
+    }
+
+    fn disk_ann() -> IndexConfig {
+        IndexConfig::DiskANN {
+            max_degree: 8,
+            build_list_size: 32,
+            num_subspaces: 2,
+            list_size: 32,
+            beam_width: 2,
+        }
+    }
+
+    fn grid(count: usize) -> Vec<Vector> {
+        (0..count)
+            .map(|i| Vector::new(vec![(i % 10) as f64, (i / 10) as f64]))
+            .collect()
+    }
+
+    #[test]
+    fn test_schema_round_trips_through_bytes() {
+        let schema = schema(
+            384,
+            Metric::Cosine,
+            IndexConfig::IVFFlat {
+                num_lists: 16,
+                nprobe: 4,
+            },
+        );
+
+        assert_eq!(
+            CollectionSchema::from_bytes(&schema.to_bytes()),
+            Some(schema)
+        );
+        for index in [ivf_pq(), disk_ann()] {
+            let schema = CollectionSchema { index, ..schema };
+            assert_eq!(
+                CollectionSchema::from_bytes(&schema.to_bytes()),
+                Some(schema)
+            );
+        }
+        assert_eq!(CollectionSchema::from_bytes(&[0; 8]), None);
+    }
+
+    #[test]
+    fn test_collections_are_independent() -> io::Result<()> {
+        let dir = tempdir()?;
+        let mut db: Database = Database::open(dir.path())?;
+        db.create_collection("text", schema(2, Metric::L2, IndexConfig::default()))?;
+        db.create_collection("images", schema(3, Metric::Dot, IndexConfig::Flat))?;
+
+        db.insert("text", Vector::new(vec![0.0, 0.0]))?;
+        db.insert("text", Vector::new(vec![5.0, 5.0]))?;
+        db.insert("images", Vector::new(vec![1.0, 0.0, 0.0]))?;
+        db.insert("images", Vector::new(vec![4.0, 4.0, 4.0]))?;
+
+        assert_eq!(db.list_collections(), vec!["images", "text"]);
+        assert_eq!(
+            db.search("text", &Vector::new(vec![1.0, 1.0]), 1)?,
+            vec![(0, 2.0)]
+        );
+        assert_eq!(
+            db.search("images", &Vector::new(vec![1.0, 1.0, 1.0]), 1)?,
+            vec![(1, -12.0)],
+            "The images collection should rank by inner product."
+        );
+
+        let mismatch = db.insert("text", Vector::new(vec![1.0, 2.0, 3.0]));
+        assert_eq!(mismatch.unwrap_err().kind(), io::ErrorKind::InvalidInput);
+        let missing = db.search("audio", &Vector::new(vec![1.0]), 1);
+        assert_eq!(missing.unwrap_err().kind(), io::ErrorKind::NotFound);
+        let duplicate = db.create_collection("text", schema(2, Metric::L2, IndexConfig::Flat));
+        assert_eq!(duplicate.unwrap_err().kind(), io::ErrorKind::AlreadyExists);
+        let ivf_dot = schema(
+            2,
+            Metric::Dot,
+            IndexConfig::IVFFlat {
+                num_lists: 2,
+                nprobe: 2,
+            },
+        );
+        let unsupported = db.create_collection("bad", ivf_dot);
+        assert_eq!(unsupported.unwrap_err().kind(), io::ErrorKind::InvalidInput);
+        assert_eq!(db.list_collections(), vec!["images", "text"]);
+        assert!(
+            db.create_collection("a/b", schema(2, Metric::L2, IndexConfig::Flat))
+                .is_err()
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_ivf_collection_trains_its_lists() -> io::Result<()> {
+        let dir = tempdir()?;
+        let config = IndexConfig::IVFFlat {
+            num_lists: 4,
+            nprobe: 1,
+        };
+        // With one probe, a trained index only scans the cluster around the
+        // query; an untrained one scans everything.
+        let scanned = |db: &Database| db.search("ivf", &Vector::new(vec![0.0, 0.0]), 200);
+        {
+            let mut db: Database = Database::open(dir.path())?;
+            db.create_collection("ivf", schema(2, Metric::L2, config))?;
+            for i in 0..200 {
+                let center = (i % 4) as f64 * 100.0;
+                db.insert("ivf", Vector::new(vec![center, i as f64 * 0.01]))?;
+            }
+            assert_eq!(scanned(&db)?.len(), 50);
+            db.flush()?;
+        }
+
+        let db: Database = Database::open(dir.path())?;
+
+        assert_eq!(
+            scanned(&db)?.len(),
+            50,
+            "Reopening should train the rebuilt index."
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_collections_persist_and_drop() -> io::Result<()> {
+        let dir = tempdir()?;
+        {
+            let mut db: Database = Database::open(dir.path())?;
+            db.create_collection("kept", schema(2, Metric::L2, IndexConfig::Flat))?;
+            db.create_collection("dropped", schema(2, Metric::L2, IndexConfig::Flat))?;
+            db.insert("kept", Vector::new(vec![1.0, 1.0]))?;
+            db.insert("dropped", Vector::new(vec![2.0, 2.0]))?;
+            db.checkpoint()?;
+            db.insert("kept", Vector::new(vec![3.0, 3.0]))?;
+            assert!(db.drop_collection("dropped")?);
+            assert!(!db.drop_collection("dropped")?);
+            db.flush()?;
+        }
+
+        let mut db: Database = Database::open(dir.path())?;
+
+        assert_eq!(db.list_collections(), vec!["kept"]);
+        assert_eq!(db.collection("kept").unwrap().len(), 2);
+        assert_eq!(
+            db.search("kept", &Vector::new(vec![3.0, 3.0]), 1)?,
+            vec![(1, 0.0)]
+        );
+
+        db.create_collection("dropped", schema(2, Metric::L2, IndexConfig::Flat))?;
+        assert!(
+            db.collection("dropped").unwrap().is_empty(),
+            "A recreated collection should not see the old vectors."
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_interrupted_drop_leaves_no_partial_collection() -> io::Result<()> {
+        let dir = tempdir()?;
+        {
+            let mut db: Database = Database::open(dir.path())?;
+            db.create_collection("items", schema(2, Metric::L2, IndexConfig::Flat))?;
+            db.insert("items", Vector::new(vec![1.0, 1.0]))?;
+            db.insert("items", Vector::new(vec![2.0, 2.0]))?;
+            // A drop that stopped right after deleting the catalog entry.
+            db.storage.delete(b"catalog/items", storage::timestamp())?;
+            db.flush()?;
+        }
+
+        let mut db: Database = Database::open(dir.path())?;
+        assert!(db.list_collections().is_empty());
+        db.create_collection("items", schema(2, Metric::L2, IndexConfig::Flat))?;
+        assert_eq!(db.insert("items", Vector::new(vec![3.0, 3.0]))?, 0);
+        db.flush()?;
+        drop(db);
+
+        let db: Database = Database::open(dir.path())?;
+        assert_eq!(db.collection("items").unwrap().len(), 1);
+        assert_eq!(
+            db.search("items", &Vector::new(vec![1.0, 1.0]), 5)?,
+            vec![(0, 8.0)]
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_open_rejects_gaps_in_vector_ids() -> io::Result<()> {
+        let dir = tempdir()?;
+        {
+            let mut db: Database = Database::open(dir.path())?;
+            db.create_collection("items", schema(2, Metric::L2, IndexConfig::Flat))?;
+            db.insert("items", Vector::new(vec![1.0, 1.0]))?;
+            db.insert("items", Vector::new(vec![2.0, 2.0]))?;
+            let key = [vectors_prefix("items"), 0u64.to_be_bytes().to_vec()].concat();
+            db.storage.delete(&key, storage::timestamp())?;
+            db.flush()?;
+        }
+
+        let error = Database::<f64>::open(dir.path()).err().unwrap();
+        assert_eq!(error.kind(), io::ErrorKind::InvalidData);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_ivf_pq_collection_trains_and_reopens() -> io::Result<()> {
+        let dir = tempdir()?;
+        let vectors = grid(100);
+        // Codes only estimate distances, so a vector need only be among the
+        // nearest few to itself.
+        let finds = |db: &Database, node_id: NodeId| -> io::Result<bool> {
+            let results = db.search("pq", &vectors[node_id], 5)?;
+            Ok(results.iter().any(|(id, _)| *id == node_id))
+        };
+        {
+            let mut db: Database = Database::open(dir.path())?;
+            db.create_collection("pq", schema(2, Metric::L2, ivf_pq()))?;
+            for vector in &vectors {
+                db.insert("pq", vector.clone())?;
+            }
+            assert!(finds(&db, 42)?);
+            db.flush()?;
+        }
+
+        let mut db: Database = Database::open(dir.path())?;
+
+        assert_eq!(db.collection("pq").unwrap().len(), 100);
+        assert!(
+            finds(&db, 42)?,
+            "The rebuilt index should find stored vectors."
+        );
+        let dot = db.create_collection("dot", schema(2, Metric::Dot, ivf_pq()));
+        assert_eq!(dot.unwrap_err().kind(), io::ErrorKind::InvalidInput);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_disk_ann_collection_builds_from_stored_vectors() -> io::Result<()> {
+        let dir = tempdir()?;
+        let vectors = grid(100);
+        let query = Vector::new(vec![4.1, 6.9]);
+        {
+            let mut db: Database = Database::open(dir.path())?;
+            db.create_collection("disk", schema(2, Metric::L2, disk_ann()))?;
+            for vector in &vectors {
+                db.insert("disk", vector.clone())?;
+            }
+            // Until it is built the collection is searched exactly.
+            assert_eq!(db.search("disk", &query, 1)?[0].0, 74);
+            assert!(!db.collection("disk").unwrap().is_built());
+
+            db.build_index("disk")?;
+            assert!(db.collection("disk").unwrap().is_built());
+            assert_eq!(db.search("disk", &query, 1)?[0].0, 74);
+            let insert = db.insert("disk", Vector::new(vec![0.5, 0.5]));
+            assert_eq!(insert.unwrap_err().kind(), io::ErrorKind::Unsupported);
+            assert_eq!(db.collection("disk").unwrap().len(), 100);
+            db.flush()?;
+        }
+
+        let mut db: Database = Database::open(dir.path())?;
+        assert!(
+            db.collection("disk").unwrap().is_built(),
+            "Reopening should find the built graph."
+        );
+        assert_eq!(db.search("disk", &query, 1)?[0].0, 74);
+
+        assert!(db.drop_collection("disk")?);
+        assert!(!dir.path().join("disk.dann").exists());
+        db.create_collection("flat", schema(2, Metric::L2, IndexConfig::Flat))?;
+        let not_disk = db.build_index("flat");
+        assert_eq!(not_disk.unwrap_err().kind(), io::ErrorKind::InvalidInput);
+
+        Ok(())
+    }
+}
@@ -0,0 +1,1004 @@
+use super::vector_index::VectorIndex;
+use crate::error::{Error, Result};
+use crate::linalg::element::{Element, ElementType};
+use crate::linalg::metric::Metric;
+use crate::linalg::simd::squared_l2_f64;
+use crate::linalg::vector::Vector;
+use crate::numeric::ordered_float::OrderedFloat;
+use crate::quantization::product::ProductQuantizer;
+use crate::storage::node::NodeId;
+use priority_queue::DoublePriorityQueue;
+use rand::SeedableRng;
+use rand::rngs::StdRng;
+use rand::seq::SliceRandom;
+use rand::seq::index::sample;
+use std::collections::{BTreeMap, HashSet};
+use std::fs::{self, File};
+use std::io::{self, BufWriter, Read, Seek, SeekFrom, Write};
+use std::marker::PhantomData;
+use std::os::unix::fs::FileExt;
+use std::path::Path;
+
+pub const SECTOR_LEN: usize = 4096;
+const MAGIC: &[u8; 4] = b"DANN";
+/// Neighbor ids are stored as `u32`.
+const MAX_NODES: usize = u32::MAX as usize + 1;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct DiskANNConfig {
+    /// Fixed out-degree `R` of every node.
+    pub max_degree: usize,
+    /// Candidate list size `L` used while building.
+    pub build_list_size: usize,
+    /// Pruning slack of the second build pass. Values above 1 keep longer
+    /// edges, which shortens search paths; they assume non-negative
+    /// distances, so keep 1.0 for `Metric::Dot`.
+    pub alpha: f64,
+    pub metric: Metric,
+    /// PQ code length in bytes, clamped to the dimension.
+    pub num_subspaces: usize,
+    pub num_centroids: usize,
+    pub iterations: usize,
+    pub seed: u64,
+}
+
+impl Default for DiskANNConfig {
+    fn default() -> Self {
+        Self {
+            max_degree: 32,
+            build_list_size: 64,
+            alpha: 1.2,
+            metric: Metric::L2,
+            num_subspaces: 16,
+            num_centroids: 256,
+            iterations: 10,
+            seed: 0,
+        }
+    }
+}
+
+/// Search parameters for calls made through `VectorIndex`, whose methods
+/// take none of their own. Not saved; `open` starts from the defaults.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct DiskANNParams {
+    pub list_size: usize,
+    pub beam_width: usize,
+}
+
+impl Default for DiskANNParams {
+    fn default() -> Self {
+        Self {
+            list_size: 64,
+            beam_width: 4,
+        }
+    }
+}
+
+/// I/O done by one search: `round_trips` batches of reads covering
+/// `sectors` sectors in total.
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct SearchStats {
+    pub round_trips: usize,
+    pub sectors: usize,
+}
+
+/// Where node records sit in the file. A record is
+/// `[vector LE][degree u32 LE][max_degree neighbor ids u32 LE]`; records
+/// that fit are packed several to a sector and never straddle one, larger
+/// ones start on a sector boundary and span whole sectors. Sector 0 holds
+/// the header.
+#[derive(Debug, Clone, Copy)]
+struct SectorLayout {
+    vector_len: usize,
+    record_len: usize,
+    max_degree: usize,
+}
+
+impl SectorLayout {
+    /// `None` if a record would not fit in `usize`.
+    fn new<T: Element>(dimension: usize, max_degree: usize) -> Option<Self> {
+        let vector_len = dimension.checked_mul(T::ELEMENT_TYPE.size())?;
+        Some(Self {
+            vector_len,
+            record_len: max_degree
+                .checked_add(1)?
+                .checked_mul(4)?
+                .checked_add(vector_len)?,
+            max_degree,
+        })
+    }
+
+    fn records_per_sector(&self) -> usize {
+        SECTOR_LEN / self.record_len
+    }
+
+    fn sectors_per_record(&self) -> usize {
+        self.record_len.div_ceil(SECTOR_LEN)
+    }
+
+    /// First sector of the record and its byte offset within that sector.
+    fn locate(&self, node_id: NodeId) -> (usize, usize) {
+        match self.records_per_sector() {
+            0 => (1 + node_id * self.sectors_per_record(), 0),
+            per_sector => (
+                1 + node_id / per_sector,
+                (node_id % per_sector) * self.record_len,
+            ),
+        }
+    }
+
+    fn num_sectors(&self, len: usize) -> Option<usize> {
+        match self.records_per_sector() {
+            0 => len.checked_mul(self.sectors_per_record()),
+            per_sector => Some(len.div_ceil(per_sector)),
+        }
+    }
+
+    /// Byte offset of the codebooks: the header sector, then the records.
+    fn quantizer_offset(&self, len: usize) -> Option<usize> {
+        self.num_sectors(len)?
+            .checked_add(1)?
+            .checked_mul(SECTOR_LEN)
+    }
+}
+
+/// Disk-resident graph index in the style of DiskANN. Nodes form one flat
+/// Vamana graph of fixed out-degree, and each node's full-precision vector
+/// is stored next to its adjacency list in 4 KiB sectors, so expanding a
+/// node costs one sector read. Only PQ codes stay in memory; they steer
+/// the search, and the vectors read along the way rerank the result.
+///
+/// Building needs every vector in memory; opening and searching only
+/// holds the header, the codebooks and `len * num_subspaces` code bytes.
+#[allow(clippy::upper_case_acronyms)]
+pub struct DiskANN<T: Element = f64> {
+    file: File,
+    layout: SectorLayout,
+    metric: Metric,
+    dimension: usize,
+    len: usize,
+    medoid: NodeId,
+    quantizer: ProductQuantizer,
+    codes: Vec<u8>,
+    params: DiskANNParams,
+    element_type: PhantomData<T>,
+}
+
+fn invalid(message: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message.to_string())
+}
+
+impl<T: Element> DiskANN<T> {
+    /// Builds the graph over `vectors` in memory, writes it to `path`, and
+    /// opens the result. Node ids are input positions.
+    pub fn build(vectors: &[Vector<T>], config: &DiskANNConfig, path: &Path) -> Result<Self> {
+        let dimension = vectors.first().map_or(0, |vector| vector.len());
+        for vector in vectors {
+            vector.validate(Some(dimension))?;
+        }
+        if vectors.is_empty() || dimension == 0 {
+            return Err(Error::Io(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "Cannot build a disk index without vectors",
+            )));
+        }
+        if vectors.len() > MAX_NODES {
+            return Err(Error::CapacityExceeded {
+                capacity: MAX_NODES,
+            });
+        }
+        if config.max_degree == 0 {
+            return Err(Error::InvalidConfig(
+                "max_degree must be positive".to_string(),
+            ));
+        }
+
+        let mut rng = StdRng::seed_from_u64(config.seed);
+        let quantizer = ProductQuantizer::train_vectors(
+            vectors,
+            config.num_subspaces.min(dimension),
+            config.num_centroids,
+            config.iterations,
+            &mut rng,
+        )
+        .ok_or_else(|| Error::InvalidConfig("num_subspaces must be positive".to_string()))?;
+        let medoid = medoid(vectors);
+        let graph = VamanaBuilder {
+            vectors,
+            metric: config.metric,
+            max_degree: config.max_degree,
+            list_size: config.build_list_size.max(config.max_degree),
+        }
+        .build(medoid, config.alpha, &mut rng);
+
+        let (layout, quantizer_offset) = SectorLayout::new::<T>(dimension, config.max_degree)
+            .and_then(|layout| Some((layout, layout.quantizer_offset(vectors.len())?)))
+            .ok_or_else(|| Error::InvalidConfig("max_degree is too large".to_string()))?;
+        let mut writer = BufWriter::new(File::create(path)?);
+        let mut header = Vec::with_capacity(SECTOR_LEN);
+        header.extend_from_slice(MAGIC);
+        header.extend_from_slice(&[T::ELEMENT_TYPE.tag(), config.metric.tag(), 0, 0]);
+        for val in [
+            dimension,
+            vectors.len(),
+            config.max_degree,
+            medoid,
+            quantizer_offset,
+        ] {
+            header.extend_from_slice(&(val as u64).to_le_bytes());
+        }
+        header.resize(SECTOR_LEN, 0);
+        writer.write_all(&header)?;
+
+        let mut sector = Vec::with_capacity(SECTOR_LEN * layout.sectors_per_record());
+        for (node_id, (vector, neighbors)) in vectors.iter().zip(&graph).enumerate() {
+            for val in vector.data() {
+                val.write_le(&mut sector);
+            }
+            sector.extend_from_slice(&(neighbors.len() as u32).to_le_bytes());
+            for slot in 0..layout.max_degree {
+                let neighbor = neighbors.get(slot).copied().unwrap_or(0);
+                sector.extend_from_slice(&(neighbor as u32).to_le_bytes());
+            }
+            let sector_full = match layout.records_per_sector() {
+                0 => true,
+                per_sector => (node_id + 1) % per_sector == 0 || node_id + 1 == vectors.len(),
+            };
+            if sector_full {
+                sector.resize(sector.len().next_multiple_of(SECTOR_LEN), 0);
+                writer.write_all(&sector)?;
+                sector.clear();
+            }
+        }
+
+        let quantizer_bytes = quantizer.to_bytes();
+        writer.write_all(&(quantizer_bytes.len() as u64).to_le_bytes())?;
+        writer.write_all(&quantizer_bytes)?;
+        for vector in vectors {
+            writer.write_all(&quantizer.encode_vector(vector))?;
+        }
+        writer
+            .into_inner()
+            .map_err(|error| error.into_error())?
+            .sync_all()?;
+
+        Ok(Self::open(path)?)
+    }
+
+    /// Opens an index written by `build`, reading the header, codebooks and
+    /// codes into memory. Node records stay on disk.
+    pub fn open(path: &Path) -> io::Result<Self> {
+        let mut file = File::open(path)?;
+        let mut header = vec![0; SECTOR_LEN];
+        file.read_exact(&mut header)?;
+        if &header[..4] != MAGIC {
+            return Err(invalid("not a disk index"));
+        }
+        if ElementType::from_tag(header[4]) != Some(T::ELEMENT_TYPE) {
+            return Err(invalid("index was saved with a different element type"));
+        }
+        let metric = Metric::from_tag(header[5]).ok_or_else(|| invalid("unknown metric"))?;
+        let mut fields = header[8..48]
+            .chunks_exact(8)
+            .map(|chunk| u64::from_le_bytes(chunk.try_into().unwrap()) as usize);
+        let mut field = || fields.next().unwrap();
+        let (dimension, len, max_degree, medoid, quantizer_offset) =
+            (field(), field(), field(), field(), field());
+
+        let layout = SectorLayout::new::<T>(dimension, max_degree)
+            .filter(|layout| {
+                len > 0 && medoid < len && layout.quantizer_offset(len) == Some(quantizer_offset)
+            })
+            .ok_or_else(|| invalid("corrupt header"))?;
+        file.seek(SeekFrom::Start(quantizer_offset as u64))?;
+        let mut rest = Vec::new();
+        file.read_to_end(&mut rest)?;
+        let (quantizer_len, rest) = rest
+            .split_first_chunk::<8>()
+            .ok_or_else(|| invalid("missing codebooks"))?;
+        let quantizer_len = u64::from_le_bytes(*quantizer_len) as usize;
+        if rest.len() < quantizer_len {
+            return Err(invalid("missing codebooks"));
+        }
+        let (quantizer, codes) = rest.split_at(quantizer_len);
+        let quantizer =
+            ProductQuantizer::from_bytes(quantizer).ok_or_else(|| invalid("corrupt codebooks"))?;
+        if quantizer.dimension() != dimension
+            || len.checked_mul(quantizer.num_subspaces()) != Some(codes.len())
+        {
+            return Err(invalid("codes do not match the header"));
+        }
+
+        Ok(Self {
+            file,
+            layout,
+            metric,
+            dimension,
+            len,
+            medoid,
+            quantizer,
+            codes: codes.to_vec(),
+            params: DiskANNParams::default(),
+            element_type: PhantomData,
+        })
+    }
+
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    pub fn dimension(&self) -> usize {
+        self.dimension
+    }
+
+    pub fn metric(&self) -> Metric {
+        self.metric
+    }
+
+    pub fn params(&self) -> &DiskANNParams {
+        &self.params
+    }
+
+    pub fn set_params(&mut self, params: DiskANNParams) {
+        self.params = params;
+    }
+
+    fn code(&self, node_id: NodeId) -> &[u8] {
+        let code_len = self.quantizer.num_subspaces();
+        &self.codes[node_id * code_len..(node_id + 1) * code_len]
+    }
+
+    /// Reads a copy of the vector stored under `node_id` from disk.
+    pub fn get(&self, node_id: NodeId) -> io::Result<Option<Vector<T>>> {
+        if node_id >= self.len {
+            return Ok(None);
+        }
+        let mut stats = SearchStats::default();
+        let mut records = self.read_records(&[node_id], &mut stats)?;
+        Ok(records.pop().map(|(data, _)| Vector::new(data)))
+    }
+
+    /// Reads the records of `node_ids` as one batch: the sectors they need
+    /// are sorted and deduplicated, and each run of adjacent sectors is
+    /// fetched with a single read.
+    fn read_records(
+        &self,
+        node_ids: &[NodeId],
+        stats: &mut SearchStats,
+    ) -> io::Result<Vec<(Vec<T>, Vec<NodeId>)>> {
+        let span = self.layout.sectors_per_record();
+        let mut sectors: Vec<usize> = node_ids
+            .iter()
+            .flat_map(|node_id| {
+                let (first, _) = self.layout.locate(*node_id);
+                first..first + span
+            })
+            .collect();
+        sectors.sort_unstable();
+        sectors.dedup();
+
+        // Positional reads leave no shared cursor, so concurrent searches
+        // read the file without locking it.
+        let mut runs: BTreeMap<usize, Vec<u8>> = BTreeMap::new();
+        let mut index = 0;
+        while index < sectors.len() {
+            let start = sectors[index];
+            let mut end = start + 1;
+            while index + 1 < sectors.len() && sectors[index + 1] == end {
+                index += 1;
+                end += 1;
+            }
+            index += 1;
+            let mut run = vec![0; (end - start) * SECTOR_LEN];
+            self.file
+                .read_exact_at(&mut run, (start * SECTOR_LEN) as u64)?;
+            runs.insert(start, run);
+        }
+        stats.round_trips += 1;
+        stats.sectors += sectors.len();
+
+        let size = T::ELEMENT_TYPE.size();
+        node_ids
+            .iter()
+            .map(|node_id| {
+                let (sector, offset) = self.layout.locate(*node_id);
+                let (start, run) = runs.range(..=sector).next_back().unwrap();
+                let begin = (sector - start) * SECTOR_LEN + offset;
+                let record = &run[begin..begin + self.layout.record_len];
+                let (vector, links) = record.split_at(self.layout.vector_len);
+                let data = vector.chunks_exact(size).map(T::read_le).collect();
+                let mut ids = links
+                    .chunks_exact(4)
+                    .map(|chunk| u32::from_le_bytes(chunk.try_into().unwrap()) as NodeId);
+                let degree = ids.next().unwrap();
+                if degree > self.layout.max_degree {
+                    return Err(invalid("corrupt adjacency list"));
+                }
+                let neighbors: Vec<NodeId> = ids.take(degree).collect();
+                if neighbors.iter().any(|id| *id >= self.len) {
+                    return Err(invalid("corrupt adjacency list"));
+                }
+                Ok((data, neighbors))
+            })
+            .collect()
+    }
+
+    /// Approximate `k` nearest ids with exact distances, closest first.
+    pub fn search(
+        &self,
+        query: &Vector<T>,
+        k: usize,
+        list_size: usize,
+        beam_width: usize,
+    ) -> Result<Vec<(NodeId, f64)>> {
+        self.search_with_stats(query, k, list_size, beam_width)
+            .map(|(results, _)| results)
+    }
+
+    /// Beam search from the medoid. The candidate list keeps the
+    /// `list_size` nodes closest by PQ estimate; each round reads the
+    /// `beam_width` closest unexpanded candidates in one batch, scores them
+    /// exactly and queues their neighbors. A wider beam needs fewer round
+    /// trips at the cost of more sectors per search.
+    pub fn search_with_stats(
+        &self,
+        query: &Vector<T>,
+        k: usize,
+        list_size: usize,
+        beam_width: usize,
+    ) -> Result<(Vec<(NodeId, f64)>, SearchStats)> {
+        query.validate(Some(self.dimension))?;
+        let mut stats = SearchStats::default();
+        let mut results = self.beam_search(query, list_size.max(k), beam_width, &mut stats)?;
+        results.truncate(k);
+        Ok((results, stats))
+    }
+
+    /// Every node `search_with_stats` expands, with its exact distance,
+    /// closest first. Expects a query already validated.
+    fn beam_search(
+        &self,
+        query: &Vector<T>,
+        list_size: usize,
+        beam_width: usize,
+        stats: &mut SearchStats,
+    ) -> io::Result<Vec<(NodeId, f64)>> {
+        let table = self
+            .quantizer
+            .metric_distance_table(&query.to_f64(), self.metric);
+        let estimate = |node_id: NodeId| OrderedFloat(table.distance(self.code(node_id)));
+        let beam_width = beam_width.max(1);
+
+        let mut candidates: DoublePriorityQueue<NodeId, OrderedFloat> = DoublePriorityQueue::new();
+        candidates.push(self.medoid, estimate(self.medoid));
+        let mut seen = HashSet::from([self.medoid]);
+        let mut expanded = HashSet::new();
+        let mut results: Vec<(NodeId, OrderedFloat)> = Vec::new();
+
+        loop {
+            let mut beam: Vec<(NodeId, OrderedFloat)> = candidates
+                .iter()
+                .filter(|(node_id, _)| !expanded.contains(*node_id))
+                .map(|(node_id, dist)| (*node_id, *dist))
+                .collect();
+            if beam.is_empty() {
+                break;
+            }
+            beam.sort_by_key(|(_, dist)| *dist);
+            let beam: Vec<NodeId> = beam
+                .into_iter()
+                .take(beam_width)
+                .map(|(node_id, _)| node_id)
+                .collect();
+
+            let records = self.read_records(&beam, stats)?;
+            for (node_id, (data, neighbors)) in beam.into_iter().zip(records) {
+                expanded.insert(node_id);
+                let dist = self.metric.distance(&data, query.data());
+                results.push((node_id, OrderedFloat(dist)));
+                for neighbor in neighbors {
+                    if seen.insert(neighbor) {
+                        candidates.push(neighbor, estimate(neighbor));
+                        if candidates.len() > list_size {
+                            candidates.pop_max();
+                        }
+                    }
+                }
+            }
+        }
+
+        results.sort_by_key(|(_, dist)| *dist);
+        Ok(results
+            .into_iter()
+            .map(|(node_id, dist)| (node_id, dist.0))
+            .collect())
+    }
+
+    /// Up to `k` ids accepted by `filter`, closest first. Repeats the
+    /// search with a doubled list until `k` pass the filter or the list
+    /// covers the whole index.
+    pub fn search_filtered(
+        &self,
+        query: &Vector<T>,
+        k: usize,
+        filter: impl Fn(NodeId) -> bool,
+    ) -> Result<Vec<(NodeId, f64)>> {
+        query.validate(Some(self.dimension))?;
+        let mut list_size = self.params.list_size.max(k).max(1);
+        loop {
+            let mut stats = SearchStats::default();
+            let results: Vec<(NodeId, f64)> = self
+                .beam_search(query, list_size, self.params.beam_width, &mut stats)?
+                .into_iter()
+                .filter(|(node_id, _)| filter(*node_id))
+                .take(k)
+                .collect();
+            if results.len() == k || list_size >= self.len {
+                return Ok(results);
+            }
+            list_size *= 2;
+        }
+    }
+
+    /// Copies the index file to `path` through a staging file, so saving
+    /// over the file this index was opened from is safe.
+    pub fn save(&self, path: &Path) -> io::Result<()> {
+        let mut staging = path.as_os_str().to_owned();
+        staging.push(".tmp");
+        let mut copy = File::create(&staging)?;
+        let mut buf = vec![0; 16 * SECTOR_LEN];
+        let mut offset = 0;
+        loop {
+            let read = self.file.read_at(&mut buf, offset)?;
+            if read == 0 {
+                break;
+            }
+            copy.write_all(&buf[..read])?;
+            offset += read as u64;
+        }
+        copy.sync_all()?;
+        fs::rename(&staging, path)
+    }
+}
+
+fn read_only() -> Error {
+    Error::Io(io::Error::new(
+        io::ErrorKind::Unsupported,
+        "A disk index is read-only; rebuild it to change its vectors",
+    ))
+}
+
+/// A built index is read-only: `insert` and `delete` fail with
+/// `io::ErrorKind::Unsupported`. Searches use `params`.
+impl<T: Element> VectorIndex<T> for DiskANN<T> {
+    fn insert(&mut self, _vector: Vector<T>) -> Result<NodeId> {
+        Err(read_only())
+    }
+
+    fn delete(&mut self, _node_id: NodeId) -> Result<bool> {
+        Err(read_only())
+    }
+
+    fn search(&self, query: &Vector<T>, k: usize) -> Result<Vec<(NodeId, f64)>> {
+        DiskANN::search(
+            self,
+            query,
+            k,
+            self.params.list_size,
+            self.params.beam_width,
+        )
+    }
+
+    fn search_filtered(
+        &self,
+        query: &Vector<T>,
+        k: usize,
+        filter: &dyn Fn(NodeId) -> bool,
+    ) -> Result<Vec<(NodeId, f64)>> {
+        DiskANN::search_filtered(self, query, k, filter)
+    }
+
+    fn len(&self) -> usize {
+        self.len
+    }
+
+    fn dimension(&self) -> Option<usize> {
+        Some(self.dimension)
+    }
+
+    fn metric(&self) -> Metric {
+        self.metric
+    }
+
+    fn save(&self, path: &Path) -> Result<()> {
+        Ok(DiskANN::save(self, path)?)
+    }
+
+    fn load(path: &Path) -> Result<Self> {
+        Ok(DiskANN::open(path)?)
+    }
+}
+
+/// The vector closest to the mean, used as the entry point of every search.
+fn medoid<T: Element>(vectors: &[Vector<T>]) -> NodeId {
+    let points: Vec<Vec<f64>> = vectors.iter().map(|vector| vector.to_f64()).collect();
+    let mut mean = vec![0.0; points[0].len()];
+    for point in &points {
+        for (sum, val) in mean.iter_mut().zip(point) {
+            *sum += val / points.len() as f64;
+        }
+    }
+    points
+        .iter()
+        .map(|point| OrderedFloat(squared_l2_f64(point, &mean)))
+        .enumerate()
+        .min_by_key(|(_, dist)| *dist)
+        .map_or(0, |(node_id, _)| node_id)
+}
+
+/// In-memory Vamana construction over a fully loaded vector set.
+struct VamanaBuilder<'a, T: Element> {
+    vectors: &'a [Vector<T>],
+    metric: Metric,
+    max_degree: usize,
+    list_size: usize,
+}
+
+impl<T: Element> VamanaBuilder<'_, T> {
+    fn distance(&self, a: NodeId, b: NodeId) -> OrderedFloat {
+        OrderedFloat(
+            self.metric
+                .distance(self.vectors[a].data(), self.vectors[b].data()),
+        )
+    }
+
+    /// Starts from a random graph of out-degree `max_degree` and refines
+    /// every node twice, first with `alpha = 1` and then with `alpha`.
+    fn build(&self, medoid: NodeId, alpha: f64, rng: &mut StdRng) -> Vec<Vec<NodeId>> {
+        let len = self.vectors.len();
+        let mut graph: Vec<Vec<NodeId>> = (0..len)
+            .map(|node_id| {
+                // Sample from the other `len - 1` ids, skipping over `node_id`.
+                sample(rng, len - 1, self.max_degree.min(len - 1))
+                    .into_iter()
+                    .map(|other| if other >= node_id { other + 1 } else { other })
+                    .collect()
+            })
+            .collect();
+
+        let mut order: Vec<NodeId> = (0..len).collect();
+        for alpha in [1.0, alpha] {
+            order.shuffle(rng);
+            for &node_id in &order {
+                let mut pool = self.greedy_search(&graph, medoid, node_id);
+                pool.extend(
+                    graph[node_id]
+                        .iter()
+                        .map(|id| (*id, self.distance(node_id, *id))),
+                );
+                graph[node_id] = self.robust_prune(node_id, pool, alpha);
+
+                for neighbor in graph[node_id].clone() {
+                    if graph[neighbor].contains(&node_id) {
+                        continue;
+                    }
+                    if graph[neighbor].len() < self.max_degree {
+                        graph[neighbor].push(node_id);
+                        continue;
+                    }
+                    let pool = graph[neighbor]
+                        .iter()
+                        .chain([&node_id])
+                        .map(|id| (*id, self.distance(neighbor, *id)))
+                        .collect();
+                    graph[neighbor] = self.robust_prune(neighbor, pool, alpha);
+                }
+            }
+        }
+        graph
+    }
+
+    /// Every node expanded while greedily searching for `target` with a
+    /// list of `list_size`, with its distance to `target`.
+    fn greedy_search(
+        &self,
+        graph: &[Vec<NodeId>],
+        start: NodeId,
+        target: NodeId,
+    ) -> Vec<(NodeId, OrderedFloat)> {
+        let mut candidates: DoublePriorityQueue<NodeId, OrderedFloat> = DoublePriorityQueue::new();
+        candidates.push(start, self.distance(start, target));
+        let mut seen = HashSet::from([start]);
+        let mut expanded = Vec::new();
+        let mut expanded_ids = HashSet::new();
+
+        while let Some((node_id, dist)) = candidates
+            .iter()
+            .filter(|(node_id, _)| !expanded_ids.contains(*node_id))
+            .min_by_key(|(_, dist)| **dist)
+            .map(|(node_id, dist)| (*node_id, *dist))
+        {
+            expanded.push((node_id, dist));
+            expanded_ids.insert(node_id);
+            for &neighbor in &graph[node_id] {
+                if seen.insert(neighbor) {
+                    candidates.push(neighbor, self.distance(neighbor, target));
+                    if candidates.len() > self.list_size {
+                        candidates.pop_max();
+                    }
+                }
+            }
+        }
+        expanded
+    }
+
+    /// Keeps the closest candidate, drops every candidate it covers (one
+    /// `alpha` times closer to it than to `node_id`), and repeats until
+    /// `max_degree` neighbors are chosen.
+    fn robust_prune(
+        &self,
+        node_id: NodeId,
+        mut pool: Vec<(NodeId, OrderedFloat)>,
+        alpha: f64,
+    ) -> Vec<NodeId> {
+        pool.retain(|(id, _)| *id != node_id);
+        pool.sort_by_key(|(id, dist)| (*dist, *id));
+        pool.dedup_by_key(|(id, _)| *id);
+
+        let mut selected = Vec::with_capacity(self.max_degree);
+        while let Some(&(closest, _)) = pool.first() {
+            selected.push(closest);
+            if selected.len() == self.max_degree {
+                break;
+            }
+            pool.retain(|(id, dist)| alpha * self.distance(closest, *id).0 > dist.0);
+        }
+        selected
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::application::flat::FlatIndex;
+    use crate::evaluation::recall_at_k;
+    use rand::Rng;
+    use tempfile::tempdir;
+
+    fn random_vectors(count: usize, dimension: usize) -> Vec<Vector<f32>> {
+        let mut rng = StdRng::seed_from_u64(11);
+        (0..count)
+            .map(|_| Vector::new((0..dimension).map(|_| rng.r#gen::<f32>()).collect()))
+            .collect()
+    }
+
+    fn config() -> DiskANNConfig {
+        DiskANNConfig {
+            max_degree: 24,
+            build_list_size: 48,
+            num_subspaces: 8,
+            num_centroids: 32,
+            ..DiskANNConfig::default()
+        }
+    }
+
+    #[test]
+    fn test_records_share_sectors_without_straddling() {
+        let layout = SectorLayout::new::<f32>(16, 24).unwrap();
+        assert_eq!(layout.record_len, 64 + 4 + 96);
+        assert_eq!(layout.records_per_sector(), 24);
+        assert_eq!(layout.locate(0), (1, 0));
+        assert_eq!(layout.locate(25), (2, 164));
+        assert_eq!(layout.num_sectors(48), Some(2));
+        assert_eq!(layout.num_sectors(49), Some(3));
+
+        let layout = SectorLayout::new::<f64>(1000, 32).unwrap();
+        assert_eq!(layout.sectors_per_record(), 2);
+        assert_eq!(layout.locate(3), (7, 0));
+        assert_eq!(layout.num_sectors(4), Some(8));
+        assert_eq!(layout.num_sectors(usize::MAX), None);
+        assert!(SectorLayout::new::<f64>(usize::MAX, 32).is_none());
+    }
+
+    #[test]
+    fn test_search_recall_against_brute_force() -> Result<()> {
+        let dir = tempdir()?;
+        let vectors = random_vectors(1000, 16);
+        let index = DiskANN::build(&vectors, &config(), &dir.path().join("index.dann"))?;
+        let mut flat = FlatIndex::new();
+        for vector in &vectors {
+            flat.insert(vector.clone())?;
+        }
+
+        let queries = random_vectors(1030, 16).split_off(1000);
+        let mut results = Vec::new();
+        let mut ground_truth = Vec::new();
+        for query in &queries {
+            let found = index.search(query, 10, 64, 4)?;
+            assert!(found.windows(2).all(|pair| pair[0].1 <= pair[1].1));
+            results.push(found.into_iter().map(|(id, _)| id).collect());
+            ground_truth.push(
+                flat.search_ids(query, 10)?
+                    .into_iter()
+                    .map(|(id, _)| id)
+                    .collect(),
+            );
+        }
+
+        let recall = recall_at_k(&results, &ground_truth, 10);
+        assert!(recall >= 0.9, "Disk index recall@10 was {recall}");
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_dot_search_recall_against_brute_force() -> Result<()> {
+        let dir = tempdir()?;
+        let mut rng = StdRng::seed_from_u64(5);
+        // Varied norms make the inner-product neighbors differ from the
+        // L2 ones, so navigating by L2 estimates would miss them.
+        let vectors: Vec<Vector<f32>> = (0..1000)
+            .map(|_| {
+                let scale = rng.gen_range(0.5..2.0);
+                Vector::new((0..16).map(|_| scale * rng.gen_range(-1.0..1.0)).collect())
+            })
+            .collect();
+        let config = DiskANNConfig {
+            metric: Metric::Dot,
+            alpha: 1.0,
+            ..config()
+        };
+        let index = DiskANN::build(&vectors, &config, &dir.path().join("index.dann"))?;
+        let mut flat = FlatIndex::with_metric(Metric::Dot);
+        for vector in &vectors {
+            flat.insert(vector.clone())?;
+        }
+
+        let mut results = Vec::new();
+        let mut ground_truth = Vec::new();
+        for _ in 0..30 {
+            let query = Vector::new((0..16).map(|_| rng.gen_range(-1.0..1.0)).collect());
+            let found = index.search(&query, 10, 64, 4)?;
+            assert!(found.windows(2).all(|pair| pair[0].1 <= pair[1].1));
+            results.push(found.into_iter().map(|(id, _)| id).collect());
+            ground_truth.push(
+                flat.search_ids(&query, 10)?
+                    .into_iter()
+                    .map(|(id, _)| id)
+                    .collect(),
+            );
+        }
+
+        let recall = recall_at_k(&results, &ground_truth, 10);
+        assert!(recall >= 0.9, "Disk index dot recall@10 was {recall}");
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_reopened_index_gives_same_results() -> Result<()> {
+        let dir = tempdir()?;
+        let path = dir.path().join("index.dann");
+        let vectors = random_vectors(300, 16);
+        let built = DiskANN::build(&vectors, &config(), &path)?;
+        let expected = built.search(&vectors[5], 5, 32, 2)?;
+        drop(built);
+
+        let index: DiskANN<f32> = DiskANN::open(&path)?;
+
+        assert_eq!(index.len(), vectors.len());
+        assert_eq!(index.dimension(), 16);
+        assert_eq!(index.get(42)?, Some(vectors[42].clone()));
+        assert_eq!(index.get(300)?, None);
+        assert_eq!(index.search(&vectors[5], 5, 32, 2)?, expected);
+        assert_eq!(expected[0], (5, 0.0));
+        assert!(DiskANN::<f64>::open(&path).is_err());
+
+        // Dimension, len and max_degree sit at bytes 8, 16 and 24.
+        let bytes = fs::read(&path)?;
+        for at in [8, 16, 24] {
+            let mut corrupt = bytes.clone();
+            corrupt[at..at + 8].copy_from_slice(&u64::MAX.to_le_bytes());
+            fs::write(&path, corrupt)?;
+            assert!(
+                DiskANN::<f32>::open(&path).is_err(),
+                "A header field of u64::MAX at byte {at} should be rejected."
+            );
+        }
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_wider_beam_needs_fewer_round_trips() -> Result<()> {
+        let dir = tempdir()?;
+        let vectors = random_vectors(500, 16);
+        let index = DiskANN::build(&vectors, &config(), &dir.path().join("index.dann"))?;
+
+        let (_, narrow) = index.search_with_stats(&vectors[9], 10, 64, 1)?;
+        let (results, wide) = index.search_with_stats(&vectors[9], 10, 64, 8)?;
+
+        assert_eq!(results[0].0, 9);
+        assert!(
+            wide.round_trips < narrow.round_trips,
+            "A beam of 8 took {} round trips, a beam of 1 took {}.",
+            wide.round_trips,
+            narrow.round_trips
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_records_larger_than_a_sector() -> Result<()> {
+        let dir = tempdir()?;
+        let mut rng = StdRng::seed_from_u64(3);
+        // Tiling an 8-dimensional point keeps the data easy to navigate while
+        // each record spans two sectors.
+        let vectors: Vec<Vector> = (0..60)
+            .map(|_| {
+                let point: Vec<f64> = (0..8).map(|_| rng.gen_range(-1.0..1.0)).collect();
+                Vector::new((0..600).map(|i| point[i % 8]).collect())
+            })
+            .collect();
+        let config = DiskANNConfig {
+            max_degree: 8,
+            num_subspaces: 4,
+            num_centroids: 8,
+            ..DiskANNConfig::default()
+        };
+
+        let index = DiskANN::build(&vectors, &config, &dir.path().join("index.dann"))?;
+
+        for (node_id, vector) in vectors.iter().enumerate().take(10) {
+            assert_eq!(index.search(vector, 1, 32, 4)?, vec![(node_id, 0.0)]);
+        }
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_rejects_invalid_vectors() -> Result<()> {
+        let dir = tempdir()?;
+        let path = dir.path().join("index.dann");
+        let mut vectors = random_vectors(50, 4);
+        let index = DiskANN::build(&vectors, &config(), &path)?;
+
+        assert!(matches!(
+            index.search(&Vector::new(vec![1.0; 3]), 1, 8, 1),
+            Err(Error::DimensionMismatch {
+                expected: 4,
+                actual: 3
+            })
+        ));
+        vectors[7] = Vector::new(vec![0.0, f32::NAN, 0.0, 0.0]);
+        assert!(matches!(
+            DiskANN::build(&vectors, &config(), &path),
+            Err(Error::NonFiniteValue { position: 1 })
+        ));
+        assert!(DiskANN::<f32>::build(&[], &config(), &path).is_err());
+        vectors[7] = Vector::new(vec![0.0; 4]);
+        let config = DiskANNConfig {
+            num_subspaces: 0,
+            ..config()
+        };
+        assert!(matches!(
+            DiskANN::build(&vectors, &config, &path),
+            Err(Error::InvalidConfig(_))
+        ));
+        let config = DiskANNConfig {
+            max_degree: 0,
+            ..config
+        };
+        assert!(matches!(
+            DiskANN::build(&vectors, &config, &path),
+            Err(Error::InvalidConfig(_))
+        ));
+
+        Ok(())
+    }
+}
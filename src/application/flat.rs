@@ -0,0 +1,423 @@
+use super::vector_index::VectorIndex;
+use crate::error::Result;
+use crate::linalg::element::{Element, ElementType};
+use crate::linalg::metric::Metric;
+use crate::linalg::vector::Vector;
+use crate::numeric::ordered_float::OrderedFloat;
+use crate::storage::node::NodeId;
+use priority_queue::DoublePriorityQueue;
+use std::collections::HashSet;
+use std::fs;
+use std::io::{self, Read};
+use std::path::Path;
+use std::thread;
+
+/// Exact index: vectors are stored back to back in one buffer and every
+/// search scans all of them. Node ids are insertion positions; deleted
+/// rows stay in the buffer and are skipped by searches.
+pub struct FlatIndex<T: Element = f64> {
+    dimension: Option<usize>,
+    data: Vec<T>,
+    deleted: HashSet<NodeId>,
+    metric: Metric,
+}
+
+impl<T: Element> FlatIndex<T> {
+    pub fn new() -> Self {
+        Self::with_metric(Metric::L2)
+    }
+
+    pub fn with_metric(metric: Metric) -> Self {
+        Self {
+            dimension: None,
+            data: Vec::new(),
+            deleted: HashSet::new(),
+            metric,
+        }
+    }
+
+    pub fn metric(&self) -> Metric {
+        self.metric
+    }
+
+    pub fn dimension(&self) -> Option<usize> {
+        self.dimension
+    }
+
+    /// Number of live vectors.
+    pub fn len(&self) -> usize {
+        self.num_rows() - self.deleted.len()
+    }
+
+    /// Number of stored rows, deleted ones included.
+    fn num_rows(&self) -> usize {
+        match self.dimension {
+            Some(0) | None => 0,
+            Some(dimension) => self.data.len() / dimension,
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Appends `vector`. The first insert fixes the dimension; later
+    /// vectors of another length, and vectors with NaN or infinite
+    /// components, are rejected.
+    pub fn insert(&mut self, vector: Vector<T>) -> Result<NodeId> {
+        vector.validate(self.dimension)?;
+        self.dimension.get_or_insert(vector.len());
+        let node_id = self.num_rows();
+        self.data.extend_from_slice(vector.data());
+        Ok(node_id)
+    }
+
+    /// Removes `node_id` from search results. Returns false if it was not a
+    /// live vector.
+    pub fn delete(&mut self, node_id: NodeId) -> bool {
+        node_id < self.num_rows() && self.deleted.insert(node_id)
+    }
+
+    pub fn vector(&self, node_id: NodeId) -> Option<Vector<T>> {
+        if self.deleted.contains(&node_id) {
+            return None;
+        }
+        let dimension = self.dimension?;
+        let start = node_id.checked_mul(dimension)?;
+        let slice = self.data.get(start..start + dimension)?;
+        Some(Vector::new(slice.to_vec()))
+    }
+
+    pub fn search(&self, query: Vector<T>, k: usize) -> Result<Vec<Vector<T>>> {
+        Ok(self
+            .search_ids(&query, k)?
+            .into_iter()
+            .map(|(node_id, _)| self.vector(node_id).unwrap())
+            .collect())
+    }
+
+    /// Exact `k` nearest ids and distances, closest first.
+    pub fn search_ids(&self, query: &Vector<T>, k: usize) -> Result<Vec<(NodeId, f64)>> {
+        self.search_ids_filtered(query, k, |_| true)
+    }
+
+    /// Exact `k` nearest ids accepted by `filter`, closest first.
+    pub fn search_ids_filtered(
+        &self,
+        query: &Vector<T>,
+        k: usize,
+        filter: impl Fn(NodeId) -> bool,
+    ) -> Result<Vec<(NodeId, f64)>> {
+        query.validate(self.dimension)?;
+        Ok(self
+            .scan(query, k, 0, self.num_rows(), filter)
+            .into_sorted_iter()
+            .map(|(id, dist)| (id, dist.0))
+            .collect())
+    }
+
+    /// Same as `search_ids`, with the scan split across `threads` workers
+    /// whose partial heaps are merged at the end.
+    pub fn search_ids_parallel(
+        &self,
+        query: &Vector<T>,
+        k: usize,
+        threads: usize,
+    ) -> Result<Vec<(NodeId, f64)>> {
+        query.validate(self.dimension)?;
+        let len = self.num_rows();
+        let threads = threads.clamp(1, len.max(1));
+        let chunk = len.div_ceil(threads);
+
+        let partials: Vec<DoublePriorityQueue<NodeId, OrderedFloat>> = thread::scope(|scope| {
+            let handles: Vec<_> = (0..threads)
+                .map(|i| {
+                    let (start, end) = ((i * chunk).min(len), ((i + 1) * chunk).min(len));
+                    scope.spawn(move || self.scan(query, k, start, end, |_| true))
+                })
+                .collect();
+            handles
+                .into_iter()
+                .map(|handle| handle.join().unwrap())
+                .collect()
+        });
+
+        let mut nearest = DoublePriorityQueue::new();
+        for (node_id, dist) in partials.into_iter().flatten() {
+            push_bounded(&mut nearest, node_id, dist, k);
+        }
+        Ok(nearest
+            .into_sorted_iter()
+            .map(|(id, dist)| (id, dist.0))
+            .collect())
+    }
+
+    /// Expects a query already validated against the dimension.
+    fn scan(
+        &self,
+        query: &Vector<T>,
+        k: usize,
+        start: NodeId,
+        end: NodeId,
+        filter: impl Fn(NodeId) -> bool,
+    ) -> DoublePriorityQueue<NodeId, OrderedFloat> {
+        let mut nearest = DoublePriorityQueue::new();
+        let Some(dimension) = self.dimension else {
+            return nearest;
+        };
+
+        for node_id in start..end {
+            if self.deleted.contains(&node_id) || !filter(node_id) {
+                continue;
+            }
+            let slice = &self.data[node_id * dimension..(node_id + 1) * dimension];
+            let dist = OrderedFloat(self.metric.distance(slice, query.data()));
+            push_bounded(&mut nearest, node_id, dist, k);
+        }
+        nearest
+    }
+
+    /// Writes `[element tag][metric tag][dimension][rows][deleted ids][data]`,
+    /// integers as `u64` LE and a dimension of 0 for an empty index.
+    pub fn save(&self, path: &Path) -> io::Result<()> {
+        let mut bytes = vec![T::ELEMENT_TYPE.tag(), self.metric.tag()];
+        bytes.extend_from_slice(&(self.dimension.unwrap_or(0) as u64).to_le_bytes());
+        bytes.extend_from_slice(&(self.num_rows() as u64).to_le_bytes());
+        bytes.extend_from_slice(&(self.deleted.len() as u64).to_le_bytes());
+        for node_id in &self.deleted {
+            bytes.extend_from_slice(&(*node_id as u64).to_le_bytes());
+        }
+        for val in &self.data {
+            val.write_le(&mut bytes);
+        }
+        fs::write(path, bytes)
+    }
+
+    pub fn load(path: &Path) -> io::Result<Self> {
+        let bytes = fs::read(path)?;
+        let mut reader = bytes.as_slice();
+        let invalid = |msg: &str| io::Error::new(io::ErrorKind::InvalidData, msg.to_string());
+
+        let mut tags = [0; 2];
+        reader.read_exact(&mut tags)?;
+        if ElementType::from_tag(tags[0]) != Some(T::ELEMENT_TYPE) {
+            return Err(invalid("index was saved with a different element type"));
+        }
+        let metric = Metric::from_tag(tags[1]).ok_or_else(|| invalid("unknown metric"))?;
+        let dimension = read_u64(&mut reader)? as usize;
+        let num_rows = read_u64(&mut reader)? as usize;
+        if dimension == 0 && num_rows > 0 {
+            return Err(invalid("rows saved without a dimension"));
+        }
+
+        let num_deleted = read_u64(&mut reader)? as usize;
+        let mut deleted = HashSet::with_capacity(num_deleted);
+        for _ in 0..num_deleted {
+            let node_id = read_u64(&mut reader)? as NodeId;
+            if node_id >= num_rows || !deleted.insert(node_id) {
+                return Err(invalid("corrupt deleted ids"));
+            }
+        }
+
+        let size = T::ELEMENT_TYPE.size();
+        let len = dimension
+            .checked_mul(num_rows)
+            .and_then(|len| len.checked_mul(size))
+            .ok_or_else(|| invalid("corrupt header"))?;
+        if reader.len() != len {
+            return Err(invalid("data does not match the saved row count"));
+        }
+
+        Ok(Self {
+            dimension: Some(dimension).filter(|dimension| *dimension > 0),
+            data: reader.chunks_exact(size).map(T::read_le).collect(),
+            deleted,
+            metric,
+        })
+    }
+}
+
+fn read_u64(reader: &mut impl Read) -> io::Result<u64> {
+    let mut buf = [0; 8];
+    reader.read_exact(&mut buf)?;
+    Ok(u64::from_le_bytes(buf))
+}
+
+fn push_bounded(
+    nearest: &mut DoublePriorityQueue<NodeId, OrderedFloat>,
+    node_id: NodeId,
+    dist: OrderedFloat,
+    k: usize,
+) {
+    if nearest.len() < k {
+        nearest.push(node_id, dist);
+    } else if nearest
+        .peek_max()
+        .is_some_and(|(_, furthest)| dist < *furthest)
+    {
+        nearest.pop_max();
+        nearest.push(node_id, dist);
+    }
+}
+
+impl<T: Element> VectorIndex<T> for FlatIndex<T> {
+    fn insert(&mut self, vector: Vector<T>) -> Result<NodeId> {
+        FlatIndex::insert(self, vector)
+    }
+
+    fn delete(&mut self, node_id: NodeId) -> Result<bool> {
+        Ok(FlatIndex::delete(self, node_id))
+    }
+
+    fn search(&self, query: &Vector<T>, k: usize) -> Result<Vec<(NodeId, f64)>> {
+        self.search_ids(query, k)
+    }
+
+    fn search_filtered(
+        &self,
+        query: &Vector<T>,
+        k: usize,
+        filter: &dyn Fn(NodeId) -> bool,
+    ) -> Result<Vec<(NodeId, f64)>> {
+        self.search_ids_filtered(query, k, filter)
+    }
+
+    fn len(&self) -> usize {
+        FlatIndex::len(self)
+    }
+
+    fn dimension(&self) -> Option<usize> {
+        self.dimension
+    }
+
+    fn metric(&self) -> Metric {
+        self.metric
+    }
+
+    fn save(&self, path: &Path) -> Result<()> {
+        Ok(FlatIndex::save(self, path)?)
+    }
+
+    fn load(path: &Path) -> Result<Self> {
+        Ok(FlatIndex::load(path)?)
+    }
+}
+
+impl<T: Element> Default for FlatIndex<T> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::error::Error;
+    use rand::Rng;
+
+    fn setup_flat() -> (FlatIndex, Vec<Vector>) {
+        let mut flat = FlatIndex::new();
+        let vectors = vec![
+            Vector::new(vec![0.0, 0.0]),
+            Vector::new(vec![1.0, 1.0]),
+            Vector::new(vec![8.0, 8.0]),
+            Vector::new(vec![10.0, 10.0]),
+            Vector::new(vec![12.0, 12.0]),
+        ];
+
+        for vector in &vectors {
+            flat.insert(vector.clone()).unwrap();
+        }
+        (flat, vectors)
+    }
+
+    #[test]
+    fn test_search_returns_exact_neighbors_in_order() {
+        let (flat, _) = setup_flat();
+
+        let results = flat.search(Vector::new(vec![9.2, 9.2]), 3).unwrap();
+
+        assert_eq!(
+            results,
+            vec![
+                Vector::new(vec![10.0, 10.0]),
+                Vector::new(vec![8.0, 8.0]),
+                Vector::new(vec![12.0, 12.0]),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_search_with_dot_metric() {
+        let mut flat = FlatIndex::with_metric(Metric::Dot);
+        for point in [[1.0, 0.0], [3.0, 3.0], [-5.0, 0.0]] {
+            flat.insert(Vector::new(point.to_vec())).unwrap();
+        }
+
+        let results = flat.search_ids(&Vector::new(vec![1.0, 1.0]), 3).unwrap();
+
+        assert_eq!(results, vec![(1, -6.0), (0, -1.0), (2, 5.0)]);
+    }
+
+    #[test]
+    fn test_search_on_empty_index() {
+        let flat: FlatIndex = FlatIndex::new();
+        assert!(flat.search(Vector::new(vec![1.0]), 5).unwrap().is_empty());
+        assert!(
+            flat.search_ids_parallel(&Vector::new(vec![1.0]), 5, 4)
+                .unwrap()
+                .is_empty()
+        );
+    }
+
+    #[test]
+    fn test_search_with_k_larger_than_dataset() {
+        let (flat, vectors) = setup_flat();
+        assert_eq!(
+            flat.search(Vector::new(vec![0.0, 0.0]), 10).unwrap().len(),
+            vectors.len()
+        );
+    }
+
+    #[test]
+    fn test_rejects_mismatched_dimension() {
+        let (mut flat, vectors) = setup_flat();
+        let mismatch = Vector::new(vec![1.0, 2.0, 3.0]);
+
+        assert!(matches!(
+            flat.insert(mismatch.clone()),
+            Err(Error::DimensionMismatch {
+                expected: 2,
+                actual: 3
+            })
+        ));
+        assert!(matches!(
+            flat.search_ids(&mismatch, 1),
+            Err(Error::DimensionMismatch { .. })
+        ));
+        assert!(matches!(
+            flat.search_ids_parallel(&mismatch, 1, 2),
+            Err(Error::DimensionMismatch { .. })
+        ));
+        assert_eq!(flat.len(), vectors.len());
+    }
+
+    #[test]
+    fn test_parallel_search_matches_sequential() {
+        let mut rng = rand::thread_rng();
+        let mut flat: FlatIndex<f32> = FlatIndex::new();
+        for _ in 0..1000 {
+            flat.insert(Vector::new((0..16).map(|_| rng.r#gen::<f32>()).collect()))
+                .unwrap();
+        }
+        let query = Vector::new((0..16).map(|_| rng.r#gen::<f32>()).collect());
+
+        let sequential = flat.search_ids(&query, 10).unwrap();
+        for threads in [1, 3, 8, 5000] {
+            assert_eq!(
+                flat.search_ids_parallel(&query, 10, threads).unwrap(),
+                sequential
+            );
+        }
+    }
+}
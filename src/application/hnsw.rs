@@ -1,164 +1,744 @@
-use super::node::{Node, NodeId, LayerNum};
+use super::vector_index::VectorIndex;
+use crate::error::{Error, Result};
+use crate::linalg::element::{Element, ElementType};
+use crate::linalg::metric::Metric;
 use crate::linalg::vector::Vector;
 use crate::numeric::ordered_float::OrderedFloat;
-use crate::storage::memtable::MemTable;
+use crate::quantization::Quantizer;
+use crate::quantization::binary::BinaryQuantizer;
+use crate::quantization::product::ProductQuantizer;
+use crate::quantization::scalar::ScalarQuantizer;
+use crate::storage::adjacency::{Adjacency, MAX_NODES};
+use crate::storage::node::{LayerNum, NodeId};
+use crate::storage::vector_file::VectorFile;
+use dashmap::{DashMap, DashSet};
 use priority_queue::{DoublePriorityQueue, PriorityQueue};
-use rand::Rng;
-use std::cmp;
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
+use std::cmp::{self, Reverse};
 use std::collections::HashSet;
-use std::sync::atomic::{AtomicUsize, Ordering};
-use std::sync::{Arc, RwLock};
+use std::fs;
+use std::io::{self, Read};
+use std::mem;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::sync::mpsc::sync_channel;
+use std::sync::{Mutex, OnceLock, RwLock};
+use std::thread;
 
+/// Beam width used to reach the query's neighborhood in `search_radius`
+/// before the radius expansion takes over.
+const RADIUS_SEARCH_EF: usize = 32;
+
+/// Beam width `search_iter` starts with before it grows.
+const SEARCH_ITER_INITIAL_EF: usize = 16;
+
+/// Entry id saved for an empty index.
+const NO_ENTRY: usize = u64::MAX as usize;
+
+/// Settings owned by the index itself and saved with it.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct HNSWConfig {
+    /// Seeds the RNG used for level assignment, so the same inserts in the
+    /// same order always produce the same levels.
+    pub seed: u64,
+    /// Highest layer a node can be assigned to.
+    pub max_layer: LayerNum,
+    pub metric: Metric,
+    /// Required vector length. When `None`, the first insert fixes it.
+    pub dimension: Option<usize>,
+}
+
+impl Default for HNSWConfig {
+    fn default() -> Self {
+        Self {
+            seed: 0,
+            max_layer: 16,
+            metric: Metric::L2,
+            dimension: None,
+        }
+    }
+}
+
+impl HNSWConfig {
+    /// Encodes as `[seed u64][max_layer u64][metric tag u8][dimension u64]`,
+    /// little-endian, with a dimension of 0 standing for `None`.
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut bytes = Vec::with_capacity(25);
+        bytes.extend_from_slice(&self.seed.to_le_bytes());
+        bytes.extend_from_slice(&(self.max_layer as u64).to_le_bytes());
+        bytes.push(self.metric.tag());
+        bytes.extend_from_slice(&(self.dimension.unwrap_or(0) as u64).to_le_bytes());
+        bytes
+    }
+
+    pub fn from_bytes(bytes: &[u8]) -> Option<Self> {
+        if bytes.len() != 25 {
+            return None;
+        }
+        let dimension = u64::from_le_bytes(bytes[17..25].try_into().ok()?) as usize;
+        Some(Self {
+            seed: u64::from_le_bytes(bytes[0..8].try_into().ok()?),
+            max_layer: u64::from_le_bytes(bytes[8..16].try_into().ok()?) as LayerNum,
+            metric: Metric::from_tag(bytes[16])?,
+            dimension: Some(dimension).filter(|dimension| *dimension > 0),
+        })
+    }
+}
+
+/// Graph parameters shared by every insert of a bulk build.
+#[derive(Debug, Clone, Copy)]
+pub struct BuildConfig {
+    pub m: usize,
+    pub m_max: usize,
+    pub ef_construction: usize,
+    pub ml: usize,
+    /// Config of the index being built. Levels are drawn from its seed, so
+    /// with a single thread the build is fully reproducible.
+    pub index: HNSWConfig,
+}
+
+impl Default for BuildConfig {
+    fn default() -> Self {
+        Self {
+            m: 16,
+            m_max: 32,
+            ef_construction: 200,
+            ml: 1,
+            index: HNSWConfig::default(),
+        }
+    }
+}
+
+/// Graph parameters for calls made through `VectorIndex`, whose methods
+/// take none of their own. Saved with the index.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct HNSWParams {
+    pub m: usize,
+    pub m_max: usize,
+    pub ef_construction: usize,
+    pub ml: usize,
+    pub ef: usize,
+}
+
+impl Default for HNSWParams {
+    fn default() -> Self {
+        Self {
+            m: 16,
+            m_max: 32,
+            ef_construction: 200,
+            ml: 1,
+            ef: 100,
+        }
+    }
+}
+
+/// Optional hooks for `HNSW::build_from`. `progress` receives the number of
+/// vectors inserted so far; setting `cancel` stops the build after the
+/// inserts already in flight.
+#[derive(Default, Clone, Copy)]
+pub struct BuildControl<'a> {
+    pub progress: Option<&'a (dyn Fn(usize) + Sync)>,
+    pub cancel: Option<&'a AtomicBool>,
+}
 
 #[allow(clippy::upper_case_acronyms)]
-pub struct HNSW {
+pub struct HNSW<T: Element = f64> {
     top_layer_num: AtomicUsize,
-    entry_id: RwLock<Option<usize>>,
-    mem_table: Arc<MemTable>,
+    entry_id: RwLock<Option<NodeId>>,
+    vectors: VectorFile<T>,
+    adjacency: Adjacency,
+    quantizer: Option<Quantizer>,
+    codes: DashMap<NodeId, Vec<u8>>,
+    config: HNSWConfig,
+    params: HNSWParams,
+    dimension: OnceLock<usize>,
+    deleted: DashSet<NodeId>,
+    rng: Mutex<Box<StdRng>>,
+    /// Where `flush` writes the graph of a mapped index.
+    graph_path: Option<PathBuf>,
 }
 
-impl HNSW {
+impl<T: Element> HNSW<T> {
     pub fn new() -> Self {
+        Self::with_config(HNSWConfig::default())
+    }
+
+    /// Index whose vectors live in an anonymous mapping, so nothing is
+    /// written to disk.
+    pub fn with_config(config: HNSWConfig) -> Self {
+        let vectors =
+            VectorFile::anonymous().expect("Mapping an empty anonymous vector file cannot fail");
+        Self::with_vector_file(config, vectors)
+    }
+
+    /// Index whose vectors are appended to a new memory-mapped file at
+    /// `path`, so the OS page cache decides which of them stay resident and
+    /// the index can grow past RAM. `flush` writes the graph next to it, at
+    /// `path` with `.graph` appended, for `open_mapped`. Fails if the file
+    /// already exists.
+    pub fn create_mapped(config: HNSWConfig, path: &Path) -> Result<Self> {
+        let mut hnsw = Self::with_vector_file(config, VectorFile::create(path)?);
+        hnsw.graph_path = Some(graph_path(path));
+        Ok(hnsw)
+    }
+
+    fn with_vector_file(config: HNSWConfig, vectors: VectorFile<T>) -> Self {
         Self {
             entry_id: RwLock::new(None),
             top_layer_num: AtomicUsize::new(0),
-            mem_table: Arc::new(MemTable::new()),
+            vectors,
+            adjacency: Adjacency::new(),
+            quantizer: None,
+            codes: DashMap::new(),
+            config,
+            params: HNSWParams::default(),
+            dimension: config.dimension.map_or_else(OnceLock::new, OnceLock::from),
+            deleted: DashSet::new(),
+            rng: Mutex::new(Box::new(StdRng::seed_from_u64(config.seed))),
+            graph_path: None,
         }
     }
 
-    fn random_layer(ml: usize) -> usize {
-        let mut rng = rand::thread_rng();
-        let random_num: f64 = rng.r#gen();
-        (-random_num.ln() * ml as f64) as usize
+    pub fn config(&self) -> &HNSWConfig {
+        &self.config
     }
 
-    pub fn insert(
+    pub fn params(&self) -> &HNSWParams {
+        &self.params
+    }
+
+    pub fn set_params(&mut self, params: HNSWParams) {
+        self.params = params;
+    }
+
+    /// Length every vector must have, once fixed by the config or the first
+    /// insert.
+    pub fn dimension(&self) -> Option<usize> {
+        self.dimension.get().copied()
+    }
+
+    /// Validates `vector` for insertion, fixing the dimension if this is the
+    /// first vector. Fails once every id the adjacency lists can hold is
+    /// taken.
+    fn check_insert(&self, vector: &Vector<T>) -> Result<()> {
+        if self.vectors.len() >= MAX_NODES {
+            return Err(Error::CapacityExceeded {
+                capacity: MAX_NODES,
+            });
+        }
+        vector.validate(None)?;
+        vector.validate(Some(*self.dimension.get_or_init(|| vector.len())))
+    }
+
+    /// Validates `query` against the dimension, if one is fixed yet.
+    fn check_query(&self, query: &Vector<T>) -> Result<()> {
+        query.validate(self.dimension())
+    }
+
+    /// Trains an SQ8 quantizer on the vectors already in the index and
+    /// encodes them. Afterwards `search` traverses the graph on `u8` codes
+    /// and reranks the final candidates against the full-precision vectors.
+    /// Returns `false` if the index is empty and there is nothing to train on.
+    pub fn enable_scalar_quantization(&mut self) -> bool {
+        let node_ids: Vec<NodeId> = (0..self.vectors.len()).collect();
+        let vectors: Vec<Vector<T>> = node_ids.iter().map(|id| self.vector(id)).collect();
+        match ScalarQuantizer::train(&vectors) {
+            Some(quantizer) => self.set_quantizer(Quantizer::Scalar(quantizer), node_ids, &vectors),
+            None => false,
+        }
+    }
+
+    /// Like `enable_scalar_quantization`, but stores PQ codes of
+    /// `num_subspaces` bytes and scores them through per-query distance
+    /// tables.
+    pub fn enable_product_quantization(
+        &mut self,
+        num_subspaces: usize,
+        num_centroids: usize,
+        iterations: usize,
+    ) -> bool {
+        let node_ids: Vec<NodeId> = (0..self.vectors.len()).collect();
+        let vectors: Vec<Vector<T>> = node_ids.iter().map(|id| self.vector(id)).collect();
+        match ProductQuantizer::train_vectors(
+            &vectors,
+            num_subspaces,
+            num_centroids,
+            iterations,
+            self.rng.get_mut().unwrap().as_mut(),
+        ) {
+            Some(quantizer) => {
+                self.set_quantizer(Quantizer::Product(quantizer), node_ids, &vectors)
+            }
+            None => false,
+        }
+    }
+
+    /// Like `enable_scalar_quantization`, but keeps one sign bit per
+    /// dimension and traverses the graph by Hamming distance. Use a larger
+    /// `ef` than for exact search, since only the `ef` candidates found on
+    /// binary codes are rescored with the real metric.
+    pub fn enable_binary_quantization(&mut self) -> bool {
+        let node_ids: Vec<NodeId> = (0..self.vectors.len()).collect();
+        let vectors: Vec<Vector<T>> = node_ids.iter().map(|id| self.vector(id)).collect();
+        match vectors.first() {
+            Some(vector) => {
+                let quantizer = BinaryQuantizer::new(vector.len());
+                self.set_quantizer(Quantizer::Binary(quantizer), node_ids, &vectors)
+            }
+            None => false,
+        }
+    }
+
+    fn set_quantizer(
         &mut self,
-        vector: Vector,
+        quantizer: Quantizer,
+        node_ids: Vec<NodeId>,
+        vectors: &[Vector<T>],
+    ) -> bool {
+        self.codes.clear();
+        for (node_id, vector) in node_ids.into_iter().zip(vectors) {
+            self.codes.insert(node_id, quantizer.encode(vector));
+        }
+        self.quantizer = Some(quantizer);
+        true
+    }
+
+    pub fn disable_quantization(&mut self) {
+        self.quantizer = None;
+        self.codes.clear();
+    }
+
+    pub fn quantizer(&self) -> Option<&Quantizer> {
+        self.quantizer.as_ref()
+    }
+
+    /// Draws a level from the index RNG, capped at `config.max_layer`.
+    fn random_layer(&self, ml: usize) -> LayerNum {
+        let random_num: f64 = self.rng.lock().unwrap().r#gen();
+        cmp::min(
+            (-random_num.ln() * ml as f64) as LayerNum,
+            self.config.max_layer,
+        )
+    }
+
+    /// Distance under the configured metric. Inserts and queries are
+    /// finite, but a metric can still overflow to NaN, such as a dot product
+    /// of huge components summing `inf` and `-inf`. `OrderedFloat` orders
+    /// NaN after every real distance, so such nodes rank last everywhere and
+    /// are never picked as neighbors while a real candidate remains.
+    fn distance(&self, node_id: &NodeId, query_vector: &Vector<T>) -> OrderedFloat {
+        let distance = self
+            .vectors
+            .with_data(*node_id, |data| {
+                self.config.metric.distance(data, query_vector.data())
+            })
+            .expect("Linked nodes are always stored");
+        OrderedFloat(distance)
+    }
+
+    /// Ids linked to `node_id` at `layer_num`, empty if the node does not
+    /// reach that layer. Deleted nodes keep their links.
+    pub fn neighbor_ids(&self, node_id: &NodeId, layer_num: LayerNum) -> Vec<NodeId> {
+        self.adjacency.neighbor_ids(*node_id, layer_num)
+    }
+
+    fn vector(&self, node_id: &NodeId) -> Vector<T> {
+        self.vectors
+            .get(*node_id)
+            .expect("Linked nodes are always stored")
+    }
+
+    pub fn insert(
+        &self,
+        vector: Vector<T>,
         m: usize,
         m_max: usize,
         ef_construction: usize,
         ml: usize,
-    ) {
-        let new_node_id = self.mem_table.insert(vector);
-        let new_node_layer = Self::random_layer(ml);
+    ) -> Result<NodeId> {
+        self.check_insert(&vector)?;
+        let new_node_layer = self.random_layer(ml);
+        self.insert_at_layer(vector, new_node_layer, m, m_max, ef_construction)
+    }
 
-        let entry_id = match self.entry_id {
-            Some(id) => id,
-            None => {
-                self.entry_id = Some(new_node_id);
-                self.top_layer_num = new_node_layer;
-                return;
+    /// Inserts every vector from `vectors` using `threads` workers. Node ids
+    /// and levels are assigned on the calling thread in input order, so ids
+    /// always match input positions, and levels only depend on
+    /// `config.index.seed`; with `threads == 1` the whole graph is identical
+    /// across runs. A cancelled build
+    /// stops reading `vectors` but links everything already queued. An
+    /// invalid vector stops the build the same way and is returned as the
+    /// error.
+    pub fn build_from(
+        vectors: impl IntoIterator<Item = Vector<T>>,
+        threads: usize,
+        config: &BuildConfig,
+        control: BuildControl<'_>,
+    ) -> Result<Self> {
+        let hnsw = Self {
+            params: HNSWParams {
+                m: config.m,
+                m_max: config.m_max,
+                ef_construction: config.ef_construction,
+                ml: config.ml,
+                ..HNSWParams::default()
+            },
+            ..Self::with_config(config.index)
+        };
+        let inserted = AtomicUsize::new(0);
+        let cancelled = || {
+            control
+                .cancel
+                .is_some_and(|cancel| cancel.load(Ordering::SeqCst))
+        };
+        let link = |node_id: NodeId, vector: Vector<T>, layer: LayerNum| {
+            hnsw.link(
+                node_id,
+                &vector,
+                layer,
+                config.m,
+                config.m_max,
+                config.ef_construction,
+            );
+            let count = inserted.fetch_add(1, Ordering::SeqCst) + 1;
+            if let Some(progress) = control.progress {
+                progress(count);
             }
         };
 
-        let mut entry_node = self.mem_table.get(&entry_id).unwrap();
+        if threads <= 1 {
+            for vector in vectors {
+                if cancelled() {
+                    break;
+                }
+                hnsw.check_insert(&vector)?;
+                let layer = hnsw.random_layer(config.ml);
+                link(hnsw.store(&vector)?, vector, layer);
+            }
+            return Ok(hnsw);
+        }
 
-        for current_layer_num in ((new_node_layer + 1)..=self.top_layer_num).rev() {
-            let new_node_vector = &self.mem_table.get(&new_node_id).unwrap().vector();
-            let mut nearest_candidate =
-                self.search_layer(new_node_vector, entry_node, 1, current_layer_num);
-            entry_node = nearest_candidate.pop_min().unwrap().0;
+        let (sender, receiver) = sync_channel::<(NodeId, Vector<T>, LayerNum)>(threads * 4);
+        let receiver = Mutex::new(receiver);
+        let mut result = Ok(());
+        thread::scope(|scope| {
+            for _ in 0..threads {
+                scope.spawn(|| {
+                    loop {
+                        let next = receiver.lock().unwrap().recv();
+                        let Ok((node_id, vector, layer)) = next else {
+                            break;
+                        };
+                        link(node_id, vector, layer);
+                    }
+                });
+            }
+
+            for vector in vectors {
+                if cancelled() {
+                    break;
+                }
+                if let Err(error) = hnsw.check_insert(&vector) {
+                    result = Err(error);
+                    break;
+                }
+                let layer = hnsw.random_layer(config.ml);
+                let node_id = match hnsw.store(&vector) {
+                    Ok(node_id) => node_id,
+                    Err(error) => {
+                        result = Err(error);
+                        break;
+                    }
+                };
+                if sender.send((node_id, vector, layer)).is_err() {
+                    break;
+                }
+            }
+            drop(sender);
+        });
+        result.map(|()| hnsw)
+    }
+
+    /// Returns a copy of the vector stored under `node_id`, or `None` if it
+    /// was deleted.
+    pub fn get(&self, node_id: &NodeId) -> Option<Vector<T>> {
+        if self.is_deleted(*node_id) {
+            return None;
+        }
+        self.vectors.get(*node_id)
+    }
+
+    /// Number of vectors not deleted.
+    pub fn len(&self) -> usize {
+        self.vectors.len() - self.deleted.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Marks `node_id` deleted. Its vector and links stay in place so
+    /// searches can still route through it, but no search returns it again,
+    /// so a heavily deleted index may return fewer than `k` results for a
+    /// given `ef`. Returns `false` if there is no such live node.
+    pub fn delete(&self, node_id: NodeId) -> bool {
+        node_id < self.vectors.len() && self.deleted.insert(node_id)
+    }
+
+    fn is_deleted(&self, node_id: NodeId) -> bool {
+        !self.deleted.is_empty() && self.deleted.contains(&node_id)
+    }
+
+    /// Persists a mapped index: writes its dirty vector pages to disk, then
+    /// replaces the graph file, so `open_mapped` sees every vector inserted
+    /// so far. Call it while no insert is running. Does nothing for an
+    /// anonymous index.
+    pub fn flush(&self) -> Result<()> {
+        self.vectors.flush()?;
+        if let Some(graph_path) = &self.graph_path {
+            let mut staging = graph_path.as_os_str().to_owned();
+            staging.push(".tmp");
+            fs::write(&staging, self.graph_bytes())?;
+            fs::rename(&staging, graph_path)?;
         }
+        Ok(())
+    }
+
+    fn insert_at_layer(
+        &self,
+        vector: Vector<T>,
+        new_node_layer: LayerNum,
+        m: usize,
+        m_max: usize,
+        ef_construction: usize,
+    ) -> Result<NodeId> {
+        let new_node_id = self.store(&vector)?;
+        self.link(
+            new_node_id,
+            &vector,
+            new_node_layer,
+            m,
+            m_max,
+            ef_construction,
+        );
+        Ok(new_node_id)
+    }
+
+    /// Appends the vector to the vector file without connecting it to the
+    /// graph. Its record index becomes the node id.
+    fn store(&self, vector: &Vector<T>) -> Result<NodeId> {
+        let new_node_id = self.vectors.push(vector)?;
+        if let Some(quantizer) = &self.quantizer {
+            self.codes.insert(new_node_id, quantizer.encode(vector));
+        }
+        Ok(new_node_id)
+    }
+
+    fn link(
+        &self,
+        new_node_id: NodeId,
+        vector: &Vector<T>,
+        new_node_layer: LayerNum,
+        m: usize,
+        m_max: usize,
+        ef_construction: usize,
+    ) {
+        let exact_distance = |node_id: &NodeId| self.distance(node_id, vector);
+        // Lists can't outgrow the capacity fixed by the first insert.
+        let m_max = m_max.min(self.adjacency.fix_capacity(m_max));
+
+        let (mut entry_id, top_layer_num) = {
+            let mut entry_id = self.entry_id.write().unwrap();
+            match *entry_id {
+                Some(id) => (id, self.top_layer_num.load(Ordering::SeqCst)),
+                None => {
+                    *entry_id = Some(new_node_id);
+                    self.top_layer_num.store(new_node_layer, Ordering::SeqCst);
+                    return;
+                }
+            }
+        };
 
-        for current_layer_num in (0..=cmp::min(self.top_layer_num, new_node_layer)).rev() {
-            let new_node_vector = &self.mem_table.get(&new_node_id).unwrap().vector();
+        for current_layer_num in ((new_node_layer + 1)..=top_layer_num).rev() {
+            let mut nearest_candidate =
+                self.search_layer(&exact_distance, entry_id, 1, current_layer_num);
+            entry_id = nearest_candidate.pop_min().unwrap().0;
+        }
 
+        for current_layer_num in (0..=cmp::min(top_layer_num, new_node_layer)).rev() {
             let candidates = self.search_layer(
-                new_node_vector,
-                entry_node,
+                &exact_distance,
+                entry_id,
                 ef_construction,
                 current_layer_num,
             );
 
-            let new_node_neighbors = self.select_neighbors(
-                new_node_vector,
-                candidates.clone(),
-                m,
-                current_layer_num,
-                true,
-                true,
-            );
-            let new_node_neighbors_ids: Vec<NodeId> =
-                new_node_neighbors.iter().map(|n| n.id()).collect();
+            // Concurrent inserts can already link to the new node, so keep it
+            // out of its own neighbor list.
+            let mut new_node_neighbors_ids =
+                self.select_neighbors(vector, candidates, m, current_layer_num, true, true);
+            new_node_neighbors_ids.retain(|id| *id != new_node_id);
 
-            self.mem_table
-                .get_mut(&new_node_id)
-                .unwrap()
-                .set_neighbor_ids(current_layer_num, new_node_neighbors_ids.clone());
+            // Concurrent inserts that reached the new node through an upper
+            // layer may already have linked back to it here; keep those,
+            // re-selecting over the merged list if it overflows.
+            self.adjacency
+                .update(new_node_id, current_layer_num, |neighbor_ids| {
+                    let existing = mem::take(neighbor_ids);
+                    neighbor_ids.extend(&new_node_neighbors_ids);
+                    neighbor_ids.extend(
+                        existing
+                            .into_iter()
+                            .filter(|id| !new_node_neighbors_ids.contains(id)),
+                    );
+                    self.prune(vector, neighbor_ids, m_max, current_layer_num);
+                });
 
             for neighbor_id in &new_node_neighbors_ids {
-                let neighbor_mut = self.mem_table.get_mut(neighbor_id).unwrap();
-                let neighbor_connections = neighbor_mut
-                    .neighbor_ids(current_layer_num)
-                    .unwrap_or(&Vec::new());
-
-                if neighbor_connections.len() < m_max {
-                    neighbor_mut.add_neighbor(current_layer_num, new_node_id);
-                } else {
+                let added =
+                    self.adjacency
+                        .update(*neighbor_id, current_layer_num, |neighbor_ids| {
+                            let has_room = neighbor_ids.len() < m_max;
+                            if has_room {
+                                neighbor_ids.push(new_node_id);
+                            }
+                            has_room
+                        });
+                if !added {
+                    self.shrink_neighbors(*neighbor_id, new_node_id, current_layer_num, m_max);
                 }
             }
 
-            entry_node = new_node_neighbors[0];
+            if let Some(nearest_id) = new_node_neighbors_ids.first() {
+                entry_id = *nearest_id;
+            }
+        }
+
+        let mut entry_id = self.entry_id.write().unwrap();
+        if new_node_layer > self.top_layer_num.load(Ordering::SeqCst) {
+            self.top_layer_num.store(new_node_layer, Ordering::SeqCst);
+            *entry_id = Some(new_node_id);
         }
+    }
+
+    /// Re-selects the links of a full node once `new_node_id` wants to join
+    /// them, keeping at most `m_max`. Selection runs without holding the
+    /// node's lock, since it reads other nodes that concurrent inserts may
+    /// have locked; links added in the meantime are kept.
+    fn shrink_neighbors(
+        &self,
+        node_id: NodeId,
+        new_node_id: NodeId,
+        layer_num: LayerNum,
+        m_max: usize,
+    ) {
+        let node_vector = self.vector(&node_id);
+        let mut snapshot = self.neighbor_ids(&node_id, layer_num);
+        snapshot.push(new_node_id);
+        let candidate_pool = snapshot
+            .iter()
+            .filter(|id| **id != node_id)
+            .map(|id| (*id, self.distance(id, &node_vector)))
+            .collect();
+        let mut selected =
+            self.select_neighbors(&node_vector, candidate_pool, m_max, layer_num, false, true);
+
+        self.adjacency.update(node_id, layer_num, |neighbor_ids| {
+            selected.extend(neighbor_ids.drain(..).filter(|id| !snapshot.contains(id)));
+            self.prune(&node_vector, &mut selected, m_max, layer_num);
+            *neighbor_ids = selected;
+        });
+    }
 
-        if new_node_layer > self.top_layer_num {
-            self.top_layer_num = new_node_layer;
-            self.entry_id = Some(new_node_id);
+    /// Re-runs neighbor selection over `neighbor_ids` if it holds more than
+    /// `m_max` ids, so links merged in from concurrent inserts compete on
+    /// distance instead of being cut off for coming last. Only reads
+    /// vectors, so it may run inside `Adjacency::update`.
+    fn prune(
+        &self,
+        vector: &Vector<T>,
+        neighbor_ids: &mut Vec<NodeId>,
+        m_max: usize,
+        layer_num: LayerNum,
+    ) {
+        if neighbor_ids.len() <= m_max {
+            return;
         }
+        let candidate_pool = neighbor_ids
+            .iter()
+            .map(|id| (*id, self.distance(id, vector)))
+            .collect();
+        *neighbor_ids =
+            self.select_neighbors(vector, candidate_pool, m_max, layer_num, false, true);
+    }
+
+    /// Distance from `vector` to the closest already selected vector, or
+    /// infinity if nothing is selected yet.
+    fn distance_to_selected(&self, vector: &Vector<T>, selected: &[(NodeId, Vector<T>)]) -> f64 {
+        selected
+            .iter()
+            .map(|(_, selected_vector)| {
+                self.config
+                    .metric
+                    .distance(vector.data(), selected_vector.data())
+            })
+            .fold(f64::INFINITY, f64::min)
     }
 
-    fn select_neighbors<'a>(
-        &'a self,
-        query_vector: &Vector,
-        mut candidate_pool: DoublePriorityQueue<&'a Node, OrderedFloat>,
+    fn select_neighbors(
+        &self,
+        query_vector: &Vector<T>,
+        mut candidate_pool: DoublePriorityQueue<NodeId, OrderedFloat>,
         m: usize,
-        layer_num: usize,
+        layer_num: LayerNum,
         extend_candidates: bool,
         keep_pruned_connections: bool,
-    ) -> Vec<&'a Node> {
+    ) -> Vec<NodeId> {
         if extend_candidates {
-            let initial_candidates: Vec<&Node> =
-                candidate_pool.iter().map(|(node, _)| *node).collect();
-            #[allow(clippy::mutable_key_type)]
-            let mut visited: HashSet<&Node> = initial_candidates.iter().cloned().collect();
-
-            for candidate in initial_candidates {
-                for neighbor_id in candidate.neighbor_ids(layer_num).unwrap_or(&Vec::new()) {
-                    let neighbor = self.mem_table.get(neighbor_id).unwrap();
-                    if visited.insert(neighbor) {
-                        let dist = OrderedFloat(neighbor.vector().squared_distance(query_vector));
-                        candidate_pool.push(neighbor, dist);
+            let initial_candidates: Vec<NodeId> =
+                candidate_pool.iter().map(|(node_id, _)| *node_id).collect();
+            let mut visited: HashSet<NodeId> = initial_candidates.iter().cloned().collect();
+
+            for candidate_id in initial_candidates {
+                for neighbor_id in self.neighbor_ids(&candidate_id, layer_num) {
+                    if visited.insert(neighbor_id) {
+                        let dist = self.distance(&neighbor_id, query_vector);
+                        candidate_pool.push(neighbor_id, dist);
                     }
                 }
             }
         }
 
-        let mut selected_neighbors = Vec::with_capacity(m);
+        let mut selected_neighbors: Vec<(NodeId, Vector<T>)> = Vec::with_capacity(m);
         let mut pruned_connections = DoublePriorityQueue::new();
 
-        while let Some((candidate_node, candidate_dist)) = candidate_pool.pop_min() {
+        while let Some((candidate_id, candidate_dist)) = candidate_pool.pop_min() {
             if selected_neighbors.len() >= m {
                 break;
             }
 
+            let candidate_vector = self.vector(&candidate_id);
+
             if selected_neighbors.is_empty() {
-                selected_neighbors.push(candidate_node);
+                selected_neighbors.push((candidate_id, candidate_vector));
                 continue;
             }
 
-            let is_diverse_candidate = selected_neighbors.iter().all(|selected| {
-                let dist_to_selected = candidate_node.vector().squared_distance(selected.vector());
-                dist_to_selected >= candidate_dist.0
-            });
+            let is_diverse_candidate = self
+                .distance_to_selected(&candidate_vector, &selected_neighbors)
+                >= candidate_dist.0;
 
             if is_diverse_candidate {
-                selected_neighbors.push(candidate_node);
+                selected_neighbors.push((candidate_id, candidate_vector));
             } else {
-                pruned_connections.push(candidate_node, candidate_dist);
+                pruned_connections.push(candidate_id, candidate_dist);
             }
         }
 
+        let mut selected_neighbors: Vec<NodeId> =
+            selected_neighbors.into_iter().map(|(id, _)| id).collect();
+
         if keep_pruned_connections {
             while selected_neighbors.len() < m {
                 if let Some((best_pruned, _)) = pruned_connections.pop_min() {
@@ -172,40 +752,58 @@ impl HNSW {
         selected_neighbors
     }
 
-    fn search_layer<'a>(
-        &'a self,
-        query_vector: &Vector,
-        entry_node: &'a Node,
+    fn search_layer(
+        &self,
+        distance: &impl Fn(&NodeId) -> OrderedFloat,
+        entry_id: NodeId,
         ef: usize,
-        layer_num: usize,
-    ) -> DoublePriorityQueue<&'a Node, OrderedFloat> {
-        let mut nearest_neighbors = DoublePriorityQueue::new();
-        let mut candidate_heap = PriorityQueue::new();
-        let mut visited_nodes = HashSet::new();
+        layer_num: LayerNum,
+    ) -> DoublePriorityQueue<NodeId, OrderedFloat> {
+        let mut scratch = SearchScratch::default();
+        self.search_layer_into(distance, entry_id, ef, layer_num, &mut scratch);
+        scratch.nearest_neighbors
+    }
+
+    /// `search_layer` on reused buffers; the result is left in
+    /// `scratch.nearest_neighbors`.
+    fn search_layer_into(
+        &self,
+        distance: &impl Fn(&NodeId) -> OrderedFloat,
+        entry_id: NodeId,
+        ef: usize,
+        layer_num: LayerNum,
+        scratch: &mut SearchScratch,
+    ) {
+        let SearchScratch {
+            nearest_neighbors,
+            candidate_heap,
+            visited_nodes,
+        } = scratch;
+        nearest_neighbors.clear();
+        candidate_heap.clear();
+        visited_nodes.clear();
 
-        let entry_point_dist = OrderedFloat(entry_node.vector().squared_distance(query_vector));
+        let entry_point_dist = distance(&entry_id);
 
-        visited_nodes.insert(entry_node);
-        nearest_neighbors.push(entry_node, entry_point_dist);
-        candidate_heap.push(entry_node, Reverse(entry_point_dist));
+        visited_nodes.insert(entry_id);
+        nearest_neighbors.push(entry_id, entry_point_dist);
+        candidate_heap.push(entry_id, Reverse(entry_point_dist));
 
-        while let Some((current_node, Reverse(current_dist))) = candidate_heap.pop() {
+        while let Some((current_id, Reverse(current_dist))) = candidate_heap.pop() {
             let (_, furthest_neighbor_dist) = nearest_neighbors.peek_max().unwrap();
 
             if current_dist > *furthest_neighbor_dist && nearest_neighbors.len() >= ef {
                 break;
             }
 
-            for neighbor_id in current_node.neighbor_ids(layer_num).unwrap_or(&Vec::new()) {
-                let neighbor = self.mem_table.get(neighbor_id).unwrap();
-                if visited_nodes.insert(neighbor) {
-                    let neighbor_dist =
-                        OrderedFloat(neighbor.vector().squared_distance(query_vector));
+            for neighbor_id in self.neighbor_ids(&current_id, layer_num) {
+                if visited_nodes.insert(neighbor_id) {
+                    let neighbor_dist = distance(&neighbor_id);
                     let (_, furthest_dist) = nearest_neighbors.peek_max().unwrap();
 
                     if neighbor_dist < *furthest_dist || nearest_neighbors.len() < ef {
-                        candidate_heap.push(neighbor, Reverse(neighbor_dist));
-                        nearest_neighbors.push(neighbor, neighbor_dist);
+                        candidate_heap.push(neighbor_id, Reverse(neighbor_dist));
+                        nearest_neighbors.push(neighbor_id, neighbor_dist);
 
                         if nearest_neighbors.len() > ef {
                             nearest_neighbors.pop_max();
@@ -214,108 +812,828 @@ impl HNSW {
                 }
             }
         }
-        nearest_neighbors
     }
 
-    pub fn search(&self, query: Vector, k: usize, ef: usize) -> Vec<Vector> {
-        if self.entry_id.is_none() {
-            return Vec::new();
-        }
+    pub fn search(&self, query: Vector<T>, k: usize, ef: usize) -> Result<Vec<Vector<T>>> {
+        Ok(self
+            .search_nodes(&query, k, ef)?
+            .into_iter()
+            .map(|node_id| self.vector(&node_id))
+            .collect())
+    }
+
+    /// Same as `search`, but returns node ids. Ids are assigned in insertion
+    /// order starting at zero.
+    pub fn search_nodes(&self, query: &Vector<T>, k: usize, ef: usize) -> Result<Vec<NodeId>> {
+        self.check_query(query)?;
+        Ok(self.search_ids(query, k, ef, self.quantizer.is_some()))
+    }
 
-        let entry_id = self.entry_id.unwrap();
-        let mut entry_node = self.mem_table.get(&entry_id).unwrap();
+    /// Maximal marginal relevance search. Fetches the `fetch_k` nearest
+    /// nodes, then greedily picks `k` of them, each time taking the one that
+    /// maximizes `lambda * sim(query, node) - (1 - lambda) * sim(node, picked)`,
+    /// where `sim(node, picked)` is the similarity to the closest node picked
+    /// so far. Distances are mapped to similarities by `Metric::similarity`.
+    /// `lambda = 1.0` gives plain nearest-neighbor order; lower values favor
+    /// diverse results.
+    pub fn search_mmr(
+        &self,
+        query: &Vector<T>,
+        k: usize,
+        fetch_k: usize,
+        lambda: f64,
+    ) -> Result<Vec<NodeId>> {
+        let metric = self.config.metric;
+        let mut candidates: Vec<(NodeId, Vector<T>, f64)> = self
+            .search_nodes(query, fetch_k, cmp::max(fetch_k, k))?
+            .into_iter()
+            .map(|node_id| {
+                let vector = self.vector(&node_id);
+                let relevance = metric.similarity(metric.distance(vector.data(), query.data()));
+                (node_id, vector, relevance)
+            })
+            .collect();
 
-        for current_layer_num in (1..=self.top_layer_num).rev() {
-            let mut nearest_candidates =
-                self.search_layer(&query, entry_node, 1, current_layer_num);
-            entry_node = nearest_candidates.pop_min().unwrap().0;
+        let mut selected: Vec<(NodeId, Vector<T>)> = Vec::with_capacity(k);
+        while selected.len() < k && !candidates.is_empty() {
+            let score = |(_, vector, relevance): &(NodeId, Vector<T>, f64)| {
+                let redundancy = if selected.is_empty() {
+                    0.0
+                } else {
+                    metric.similarity(self.distance_to_selected(vector, &selected))
+                };
+                // Scores are maximized, so NaN must rank lowest here rather
+                // than highest as `OrderedFloat` would.
+                let score = lambda * relevance - (1.0 - lambda) * redundancy;
+                OrderedFloat(if score.is_nan() {
+                    f64::NEG_INFINITY
+                } else {
+                    score
+                })
+            };
+            let best = (0..candidates.len())
+                .max_by_key(|&index| (score(&candidates[index]), Reverse(index)))
+                .unwrap();
+            let (node_id, vector, _) = candidates.remove(best);
+            selected.push((node_id, vector));
         }
+        Ok(selected.into_iter().map(|(node_id, _)| node_id).collect())
+    }
 
-        let candidates = self.search_layer(&query, entry_node, ef, 0);
+    /// Runs `search_nodes` for every query on all available cores and
+    /// returns the results in input order. Each worker handles a contiguous
+    /// chunk of `queries` and reuses one set of search buffers for it. Fails
+    /// without searching if any query is invalid.
+    pub fn search_batch(
+        &self,
+        queries: &[Vector<T>],
+        k: usize,
+        ef: usize,
+    ) -> Result<Vec<Vec<NodeId>>> {
+        for query in queries {
+            self.check_query(query)?;
+        }
+        let threads = thread::available_parallelism().map_or(1, |threads| threads.get());
+        let chunk_size = queries.len().div_ceil(threads).max(1);
+        let quantized = self.quantizer.is_some();
+        let mut results = vec![Vec::new(); queries.len()];
 
-        let mut result = candidates
-            .into_sorted_iter()
-            .map(|(node, _)| node.vector().clone())
-            .collect::<Vec<_>>();
-        result.truncate(k);
-        result
+        thread::scope(|scope| {
+            for (queries, results) in queries
+                .chunks(chunk_size)
+                .zip(results.chunks_mut(chunk_size))
+            {
+                scope.spawn(move || {
+                    let mut scratch = SearchScratch::default();
+                    for (query, result) in queries.iter().zip(results) {
+                        *result = self.search_ids_with(query, k, ef, quantized, &mut scratch);
+                    }
+                });
+            }
+        });
+        Ok(results)
     }
-}
 
-#[cfg(test)]
-mod tests {
-    use super::*;
+    fn search_ids(&self, query: &Vector<T>, k: usize, ef: usize, quantized: bool) -> Vec<NodeId> {
+        self.search_ids_with(query, k, ef, quantized, &mut SearchScratch::default())
+    }
 
-    fn setup_hnsw() -> (HNSW, Vec<Vector>) {
-        let mut hnsw = HNSW::new();
-        let vectors = vec![
-            Vector::new(vec![0.0, 0.0]),
-            Vector::new(vec![1.0, 1.0]),
-            Vector::new(vec![8.0, 8.0]),
-            Vector::new(vec![10.0, 10.0]),
-            Vector::new(vec![12.0, 12.0]),
-        ];
+    fn search_ids_with(
+        &self,
+        query: &Vector<T>,
+        k: usize,
+        ef: usize,
+        quantized: bool,
+        scratch: &mut SearchScratch,
+    ) -> Vec<NodeId> {
+        let Some(entry_id) = *self.entry_id.read().unwrap() else {
+            return Vec::new();
+        };
 
-        for vector in &vectors {
-            hnsw.insert(vector.clone(), 16, 32, 200, 4);
+        let query_distance = self
+            .quantizer
+            .as_ref()
+            .filter(|_| quantized)
+            .map(|quantizer| quantizer.prepare(query, self.config.metric));
+        let quantized_distance = |node_id: &NodeId| {
+            let query_distance = query_distance.as_ref().unwrap();
+            OrderedFloat(query_distance.distance(&self.codes.get(node_id).unwrap()))
+        };
+        let exact_distance = |node_id: &NodeId| self.distance(node_id, query);
+
+        if query_distance.is_some() {
+            self.search_layers(&quantized_distance, entry_id, ef, scratch);
+            let mut reranked: Vec<(NodeId, OrderedFloat)> = scratch
+                .nearest_neighbors
+                .iter()
+                .map(|(node_id, _)| (*node_id, exact_distance(node_id)))
+                .collect();
+            reranked.sort_by_key(|(_, dist)| *dist);
+            return reranked
+                .into_iter()
+                .filter(|(node_id, _)| !self.is_deleted(*node_id))
+                .take(k)
+                .map(|(node_id, _)| node_id)
+                .collect();
         }
-        (hnsw, vectors)
+
+        self.search_layers(&exact_distance, entry_id, ef, scratch);
+        let mut result = Vec::with_capacity(cmp::min(k, scratch.nearest_neighbors.len()));
+        while result.len() < k {
+            let Some((node_id, _)) = scratch.nearest_neighbors.pop_min() else {
+                break;
+            };
+            if !self.is_deleted(node_id) {
+                result.push(node_id);
+            }
+        }
+        result
     }
 
-    #[test]
-    fn test_insert_creates_entry_point() {
-        let (hnsw, _) = setup_hnsw();
-        assert!(
-            hnsw.entry_id.is_some(),
-            "HNSW should have an entry point after insertion."
-        );
+    /// Descends to layer 0 and leaves its `ef` nearest nodes in
+    /// `scratch.nearest_neighbors`.
+    fn search_layers(
+        &self,
+        distance: &impl Fn(&NodeId) -> OrderedFloat,
+        entry_id: NodeId,
+        ef: usize,
+        scratch: &mut SearchScratch,
+    ) {
+        let entry_id = self.descend(distance, entry_id, scratch);
+        self.search_layer_into(distance, entry_id, ef, 0, scratch);
     }
 
-    #[test]
-    fn test_search_returns_correct_neighbors() {
-        let (hnsw, _) = setup_hnsw();
-        let query = Vector::new(vec![0.5, 0.5]);
+    /// Greedy walk from the top layer down to layer 1, returning the entry
+    /// point for layer 0.
+    fn descend(
+        &self,
+        distance: &impl Fn(&NodeId) -> OrderedFloat,
+        mut entry_id: NodeId,
+        scratch: &mut SearchScratch,
+    ) -> NodeId {
+        for current_layer_num in (1..=self.top_layer_num.load(Ordering::SeqCst)).rev() {
+            self.search_layer_into(distance, entry_id, 1, current_layer_num, scratch);
+            entry_id = *scratch.nearest_neighbors.peek_min().unwrap().0;
+        }
+        entry_id
+    }
 
-        let k = 2;
-        let results = hnsw.search(query, k, 100);
+    /// Lazily yields `(node_id, distance)` pairs nearest first. The
+    /// layer-0 traversal is resumed on every pull instead of restarted, and
+    /// its beam width doubles whenever the caller has consumed as many
+    /// results as the beam holds, so paging through results costs about the
+    /// same as one search for the deepest page. Uses exact distances.
+    pub fn search_iter(&self, query: Vector<T>) -> Result<SearchIter<'_, T>> {
+        self.check_query(&query)?;
+        let mut iter = SearchIter {
+            hnsw: self,
+            query,
+            ef: SEARCH_ITER_INITIAL_EF,
+            yielded: 0,
+            visited: HashSet::new(),
+            candidates: PriorityQueue::new(),
+            beam: DoublePriorityQueue::new(),
+            pending: DoublePriorityQueue::new(),
+        };
+        if let Some(entry_id) = *self.entry_id.read().unwrap() {
+            let entry_id = self.descend(
+                &|node_id: &NodeId| iter.distance(node_id),
+                entry_id,
+                &mut SearchScratch::default(),
+            );
+            iter.discover(entry_id);
+        }
+        Ok(iter)
+    }
 
-        assert_eq!(results.len(), k, "Search should return k results.");
+    /// Returns up to `limit` nodes whose distance to `query` is at
+    /// most `max_distance`, nearest first. Layer 0 is searched with a small
+    /// beam, and every node found inside the radius keeps being expanded, so
+    /// the traversal only stops once the whole frontier lies outside it (or
+    /// `limit` closer nodes are already found). Always uses exact distances.
+    /// A NaN `max_distance` matches nothing.
+    pub fn search_radius(
+        &self,
+        query: &Vector<T>,
+        max_distance: f64,
+        limit: usize,
+    ) -> Result<Vec<(NodeId, f64)>> {
+        self.check_query(query)?;
+        let Some(entry_id) = *self.entry_id.read().unwrap() else {
+            return Ok(Vec::new());
+        };
+        if limit == 0 || max_distance.is_nan() {
+            return Ok(Vec::new());
+        }
 
-        let expected_neighbor_1 = Vector::new(vec![0.0, 0.0]);
-        let expected_neighbor_2 = Vector::new(vec![1.0, 1.0]);
+        let exact_distance = |node_id: &NodeId| self.distance(node_id, query);
+        let entry_id = self.descend(&exact_distance, entry_id, &mut SearchScratch::default());
 
-        assert!(
-            results.contains(&expected_neighbor_1),
-            "Results should contain the nearest neighbor."
-        );
-        assert!(
-            results.contains(&expected_neighbor_2),
-            "Results should contain the second nearest neighbor."
-        );
+        Ok(self
+            .search_layer_radius(
+                &exact_distance,
+                entry_id,
+                OrderedFloat(max_distance),
+                RADIUS_SEARCH_EF,
+                limit,
+            )
+            .into_sorted_iter()
+            .map(|(node_id, dist)| (node_id, dist.0))
+            .collect())
     }
 
-    #[test]
-    fn test_search_for_exact_match() {
-        let (hnsw, _) = setup_hnsw();
-
-        let query = Vector::new(vec![8.0, 8.0]);
+    /// Beam search over layer 0 that additionally follows every node within
+    /// `radius`. Returns the (at most `limit`) nearest nodes within `radius`.
+    fn search_layer_radius(
+        &self,
+        distance: &impl Fn(&NodeId) -> OrderedFloat,
+        entry_id: NodeId,
+        radius: OrderedFloat,
+        ef: usize,
+        limit: usize,
+    ) -> DoublePriorityQueue<NodeId, OrderedFloat> {
+        let mut nearest_neighbors = DoublePriorityQueue::new();
+        let mut within_radius = DoublePriorityQueue::new();
+        let mut candidate_heap = PriorityQueue::new();
+        let mut visited_nodes = HashSet::new();
 
-        let k = 1;
-        let results = hnsw.search(query.clone(), k, 100);
+        let entry_point_dist = distance(&entry_id);
 
-        assert_eq!(results.len(), k);
+        visited_nodes.insert(entry_id);
+        nearest_neighbors.push(entry_id, entry_point_dist);
+        candidate_heap.push(entry_id, Reverse(entry_point_dist));
 
-        assert_eq!(
-            results[0], query,
-            "The first result should be the exact match."
-        );
-    }
+        while let Some((current_id, Reverse(current_dist))) = candidate_heap.pop() {
+            if within_radius.len() >= limit && current_dist > *within_radius.peek_max().unwrap().1 {
+                break;
+            }
+            let (_, furthest_neighbor_dist) = nearest_neighbors.peek_max().unwrap();
+            if current_dist > radius
+                && current_dist > *furthest_neighbor_dist
+                && nearest_neighbors.len() >= ef
+            {
+                break;
+            }
+
+            if current_dist <= radius && !self.is_deleted(current_id) {
+                within_radius.push(current_id, current_dist);
+                if within_radius.len() > limit {
+                    within_radius.pop_max();
+                }
+            }
+
+            for neighbor_id in self.neighbor_ids(&current_id, 0) {
+                if visited_nodes.insert(neighbor_id) {
+                    let neighbor_dist = distance(&neighbor_id);
+                    let (_, furthest_dist) = nearest_neighbors.peek_max().unwrap();
+
+                    if neighbor_dist <= radius
+                        || neighbor_dist < *furthest_dist
+                        || nearest_neighbors.len() < ef
+                    {
+                        candidate_heap.push(neighbor_id, Reverse(neighbor_dist));
+                        nearest_neighbors.push(neighbor_id, neighbor_dist);
+
+                        if nearest_neighbors.len() > ef {
+                            nearest_neighbors.pop_max();
+                        }
+                    }
+                }
+            }
+        }
+        within_radius
+    }
+
+    /// Fraction of the unquantized top-`k` that the quantized search also
+    /// returns, averaged over `queries`. Returns `None` without a quantizer.
+    pub fn quantization_recall(
+        &self,
+        queries: &[Vector<T>],
+        k: usize,
+        ef: usize,
+    ) -> Result<Option<f64>> {
+        if self.quantizer.is_none() {
+            return Ok(None);
+        }
+        let mut found = 0;
+        let mut expected = 0;
+        for query in queries {
+            self.check_query(query)?;
+            let baseline: HashSet<NodeId> =
+                self.search_ids(query, k, ef, false).into_iter().collect();
+            let quantized = self.search_ids(query, k, ef, true);
+            found += quantized.iter().filter(|id| baseline.contains(id)).count();
+            expected += baseline.len();
+        }
+        if expected == 0 {
+            return Ok(Some(1.0));
+        }
+        Ok(Some(found as f64 / expected as f64))
+    }
+
+    /// Writes the graph, its parameters and its vectors to `path` as
+    /// `[graph][vectors]`; see `graph_bytes` for the first part. Vectors are
+    /// stored back to back, little-endian.
+    pub fn save(&self, path: &Path) -> Result<()> {
+        let mut bytes = self.graph_bytes();
+        for node_id in 0..self.vectors.len() {
+            self.vectors.with_data(node_id, |data| {
+                for val in data {
+                    val.write_le(&mut bytes);
+                }
+            });
+        }
+        Ok(fs::write(path, bytes)?)
+    }
+
+    /// Reads an index written by `save` into an anonymous vector file. The
+    /// level RNG restarts from the configured seed.
+    pub fn load(path: &Path) -> Result<Self> {
+        let bytes = fs::read(path)?;
+        let mut reader = bytes.as_slice();
+        let (hnsw, len) = Self::from_graph_bytes(&mut reader, VectorFile::anonymous()?)?;
+
+        let size = T::ELEMENT_TYPE.size();
+        let vector_size = hnsw
+            .dimension()
+            .unwrap_or(0)
+            .checked_mul(size)
+            .ok_or_else(|| invalid_data("corrupt dimension"))?;
+        if len.checked_mul(vector_size) != Some(reader.len()) {
+            return Err(invalid_data("vectors do not match the saved node count").into());
+        }
+        if vector_size > 0 {
+            for data in reader.chunks_exact(vector_size) {
+                let vector = Vector::new(data.chunks_exact(size).map(T::read_le).collect());
+                hnsw.vectors.push(&vector)?;
+            }
+        }
+        Ok(hnsw)
+    }
+
+    /// Reopens an index made by `create_mapped` and last persisted by
+    /// `flush`. The vector file is mapped in place, so only the graph file is
+    /// read and startup does not depend on how many vectors are stored.
+    /// Vectors inserted after the last `flush` may have reached the file
+    /// without their links; they are dropped, as if never inserted.
+    pub fn open_mapped(path: &Path) -> Result<Self> {
+        let graph_path = graph_path(path);
+        let bytes = fs::read(&graph_path)?;
+        let mut reader = bytes.as_slice();
+        let vectors = VectorFile::open(path)?;
+        let file_len = vectors.len();
+        let file_dimension = vectors.dimension();
+
+        let (mut hnsw, len) = Self::from_graph_bytes(&mut reader, vectors)?;
+        if !reader.is_empty() {
+            return Err(invalid_data("trailing bytes after graph").into());
+        }
+        if file_len < len || (len > 0 && file_dimension != hnsw.dimension()) {
+            return Err(invalid_data("vector file does not match the graph").into());
+        }
+        hnsw.vectors.truncate(len);
+        hnsw.graph_path = Some(graph_path);
+        Ok(hnsw)
+    }
+
+    /// `[element tag][config][params][capacity][top layer][entry id][len]
+    /// [deleted ids][links][quantizer][codes]`, integers as `u64` LE except
+    /// link lists, which are a `u32` count and `u32` ids for every node and
+    /// layer. The quantizer is its `Quantizer::to_bytes` prefixed by its
+    /// length, 0 when there is none, followed by the code of every node, so
+    /// a compressed index is searched the same way after reopening.
+    fn graph_bytes(&self) -> Vec<u8> {
+        let config = HNSWConfig {
+            dimension: self.dimension(),
+            ..self.config
+        };
+        let entry_id = *self.entry_id.read().unwrap();
+        let top_layer_num = self.top_layer_num.load(Ordering::SeqCst);
+        let len = self.vectors.len();
+
+        let mut bytes = vec![T::ELEMENT_TYPE.tag()];
+        bytes.extend_from_slice(&config.to_bytes());
+        let params = self.params;
+        for val in [
+            params.m,
+            params.m_max,
+            params.ef_construction,
+            params.ml,
+            params.ef,
+            self.adjacency.capacity().unwrap_or(0),
+            top_layer_num,
+            entry_id.unwrap_or(NO_ENTRY),
+            len,
+            self.deleted.len(),
+        ] {
+            bytes.extend_from_slice(&(val as u64).to_le_bytes());
+        }
+        for node_id in self.deleted.iter() {
+            bytes.extend_from_slice(&(*node_id as u64).to_le_bytes());
+        }
+        for node_id in 0..len {
+            for layer_num in 0..=top_layer_num {
+                let neighbor_ids = self.neighbor_ids(&node_id, layer_num);
+                bytes.extend_from_slice(&(neighbor_ids.len() as u32).to_le_bytes());
+                for neighbor_id in neighbor_ids {
+                    bytes.extend_from_slice(&(neighbor_id as u32).to_le_bytes());
+                }
+            }
+        }
+        let quantizer = self
+            .quantizer
+            .as_ref()
+            .map(Quantizer::to_bytes)
+            .unwrap_or_default();
+        bytes.extend_from_slice(&(quantizer.len() as u64).to_le_bytes());
+        bytes.extend_from_slice(&quantizer);
+        if self.quantizer.is_some() {
+            for node_id in 0..len {
+                bytes.extend_from_slice(&self.codes.get(&node_id).unwrap());
+            }
+        }
+        bytes
+    }
+
+    /// Reads the output of `graph_bytes` from the front of `reader` into an
+    /// index over `vectors`, and returns it with the saved node count. The
+    /// caller checks that `vectors` holds that many vectors.
+    fn from_graph_bytes(reader: &mut &[u8], vectors: VectorFile<T>) -> Result<(Self, usize)> {
+        let mut header = [0; 26];
+        reader.read_exact(&mut header)?;
+        if ElementType::from_tag(header[0]) != Some(T::ELEMENT_TYPE) {
+            return Err(invalid_data("index was saved with a different element type").into());
+        }
+        let config =
+            HNSWConfig::from_bytes(&header[1..]).ok_or_else(|| invalid_data("corrupt config"))?;
+        let mut fields = [0; 10];
+        for field in &mut fields {
+            *field = read_u64(reader)? as usize;
+        }
+        let [
+            m,
+            m_max,
+            ef_construction,
+            ml,
+            ef,
+            capacity,
+            top_layer_num,
+            entry_id,
+            len,
+            num_deleted,
+        ] = fields;
+        if (len > 0 && (config.dimension.is_none() || capacity == 0 || entry_id >= len))
+            || (len == 0 && entry_id != NO_ENTRY)
+            || top_layer_num > config.max_layer
+            || capacity > m_max
+            || capacity >= MAX_NODES
+        {
+            return Err(invalid_data("corrupt header").into());
+        }
+        // Every deleted id takes 8 bytes and every list at least its 4 byte
+        // count, so counts the rest of the file cannot hold are corrupt.
+        let min_len = num_deleted.checked_mul(8).and_then(|deleted_len| {
+            len.checked_mul(top_layer_num + 1)?
+                .checked_mul(4)?
+                .checked_add(deleted_len)
+        });
+        if min_len.is_none_or(|min_len| min_len > reader.len()) {
+            return Err(invalid_data("corrupt header").into());
+        }
+
+        let mut hnsw = Self::with_vector_file(config, vectors);
+        hnsw.params = HNSWParams {
+            m,
+            m_max,
+            ef_construction,
+            ml,
+            ef,
+        };
+        if capacity > 0 {
+            hnsw.adjacency.fix_capacity(capacity);
+        }
+        for _ in 0..num_deleted {
+            let node_id = read_u64(reader)? as NodeId;
+            if node_id >= len || !hnsw.deleted.insert(node_id) {
+                return Err(invalid_data("corrupt deleted ids").into());
+            }
+        }
+
+        for node_id in 0..len {
+            for layer_num in 0..=top_layer_num {
+                let count = read_u32(reader)? as usize;
+                if count > capacity {
+                    return Err(invalid_data("corrupt links").into());
+                }
+                let mut neighbor_ids = Vec::with_capacity(count);
+                for _ in 0..count {
+                    let neighbor_id = read_u32(reader)? as NodeId;
+                    if neighbor_id >= len {
+                        return Err(invalid_data("corrupt links").into());
+                    }
+                    neighbor_ids.push(neighbor_id);
+                }
+                if !neighbor_ids.is_empty() {
+                    hnsw.adjacency
+                        .update(node_id, layer_num, |ids| *ids = neighbor_ids);
+                }
+            }
+        }
+
+        let quantizer_len = read_u64(reader)? as usize;
+        if quantizer_len > reader.len() {
+            return Err(invalid_data("corrupt quantizer").into());
+        }
+        if quantizer_len > 0 {
+            let (quantizer, rest) = reader.split_at(quantizer_len);
+            let quantizer = Quantizer::from_bytes(quantizer)
+                .ok_or_else(|| invalid_data("corrupt quantizer"))?;
+            let code_len = quantizer.code_len();
+            let codes_len = len
+                .checked_mul(code_len)
+                .filter(|codes_len| code_len > 0 && *codes_len <= rest.len())
+                .ok_or_else(|| invalid_data("codes do not match the saved node count"))?;
+            let (codes, rest) = rest.split_at(codes_len);
+            for (node_id, code) in codes.chunks_exact(code_len).enumerate() {
+                hnsw.codes.insert(node_id, code.to_vec());
+            }
+            hnsw.quantizer = Some(quantizer);
+            *reader = rest;
+        }
+
+        *hnsw.entry_id.get_mut().unwrap() = Some(entry_id).filter(|id| *id != NO_ENTRY);
+        hnsw.top_layer_num.store(top_layer_num, Ordering::SeqCst);
+        Ok((hnsw, len))
+    }
+}
+
+/// Graph file kept next to the vector file of a mapped index.
+fn graph_path(path: &Path) -> PathBuf {
+    let mut graph_path = path.as_os_str().to_owned();
+    graph_path.push(".graph");
+    PathBuf::from(graph_path)
+}
+
+fn invalid_data(msg: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, msg.to_string())
+}
+
+fn read_u64(reader: &mut impl Read) -> io::Result<u64> {
+    let mut buf = [0; 8];
+    reader.read_exact(&mut buf)?;
+    Ok(u64::from_le_bytes(buf))
+}
+
+fn read_u32(reader: &mut impl Read) -> io::Result<u32> {
+    let mut buf = [0; 4];
+    reader.read_exact(&mut buf)?;
+    Ok(u32::from_le_bytes(buf))
+}
+
+/// Buffers for one layer search, kept between queries by `search_batch`.
+#[derive(Default)]
+struct SearchScratch {
+    nearest_neighbors: DoublePriorityQueue<NodeId, OrderedFloat>,
+    candidate_heap: PriorityQueue<NodeId, Reverse<OrderedFloat>>,
+    visited_nodes: HashSet<NodeId>,
+}
+
+/// Iterator returned by `HNSW::search_iter`.
+pub struct SearchIter<'a, T: Element = f64> {
+    hnsw: &'a HNSW<T>,
+    query: Vector<T>,
+    ef: usize,
+    yielded: usize,
+    visited: HashSet<NodeId>,
+    /// Discovered nodes whose neighbors have not been expanded yet.
+    candidates: PriorityQueue<NodeId, Reverse<OrderedFloat>>,
+    /// The `ef` nearest nodes discovered so far; bounds which candidates are
+    /// worth expanding.
+    beam: DoublePriorityQueue<NodeId, OrderedFloat>,
+    /// Discovered nodes that have not been yielded yet.
+    pending: DoublePriorityQueue<NodeId, OrderedFloat>,
+}
+
+impl<T: Element> SearchIter<'_, T> {
+    fn distance(&self, node_id: &NodeId) -> OrderedFloat {
+        self.hnsw.distance(node_id, &self.query)
+    }
+
+    fn discover(&mut self, node_id: NodeId) {
+        if !self.visited.insert(node_id) {
+            return;
+        }
+        let dist = self.distance(&node_id);
+        self.candidates.push(node_id, Reverse(dist));
+        self.pending.push(node_id, dist);
+        self.beam.push(node_id, dist);
+        if self.beam.len() > self.ef {
+            self.beam.pop_max();
+        }
+    }
+
+    /// Expands candidates until none is closer than the furthest beam entry.
+    fn expand(&mut self) {
+        while let Some((_, Reverse(candidate_dist))) = self.candidates.peek() {
+            let beam_full = self.beam.len() >= self.ef;
+            if beam_full && candidate_dist > self.beam.peek_max().unwrap().1 {
+                break;
+            }
+            let (candidate_id, _) = self.candidates.pop().unwrap();
+            for neighbor_id in self.hnsw.neighbor_ids(&candidate_id, 0) {
+                self.discover(neighbor_id);
+            }
+        }
+    }
+}
+
+impl<T: Element> Iterator for SearchIter<'_, T> {
+    type Item = (NodeId, f64);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            if self.yielded >= self.ef {
+                self.ef *= 2;
+            }
+            self.expand();
+            while self.pending.is_empty() && !self.candidates.is_empty() {
+                self.ef *= 2;
+                self.expand();
+            }
+            let (node_id, dist) = self.pending.pop_min()?;
+            self.yielded += 1;
+            if !self.hnsw.is_deleted(node_id) {
+                return Some((node_id, dist.0));
+            }
+        }
+    }
+}
+
+/// Graph parameters come from `params`. Filtered searches page through
+/// `search_iter`, so a filter that rejects most nodes can walk the whole
+/// graph.
+impl<T: Element> VectorIndex<T> for HNSW<T> {
+    fn insert(&mut self, vector: Vector<T>) -> Result<NodeId> {
+        let params = self.params;
+        HNSW::insert(
+            self,
+            vector,
+            params.m,
+            params.m_max,
+            params.ef_construction,
+            params.ml,
+        )
+    }
+
+    fn delete(&mut self, node_id: NodeId) -> Result<bool> {
+        Ok(HNSW::delete(self, node_id))
+    }
+
+    fn search(&self, query: &Vector<T>, k: usize) -> Result<Vec<(NodeId, f64)>> {
+        Ok(self
+            .search_nodes(query, k, self.params.ef.max(k))?
+            .into_iter()
+            .map(|node_id| (node_id, self.distance(&node_id, query).0))
+            .collect())
+    }
+
+    fn search_filtered(
+        &self,
+        query: &Vector<T>,
+        k: usize,
+        filter: &dyn Fn(NodeId) -> bool,
+    ) -> Result<Vec<(NodeId, f64)>> {
+        Ok(self
+            .search_iter(query.clone())?
+            .filter(|(node_id, _)| filter(*node_id))
+            .take(k)
+            .collect())
+    }
+
+    fn len(&self) -> usize {
+        HNSW::len(self)
+    }
+
+    fn dimension(&self) -> Option<usize> {
+        HNSW::dimension(self)
+    }
+
+    fn metric(&self) -> Metric {
+        self.config.metric
+    }
+
+    fn save(&self, path: &Path) -> Result<()> {
+        HNSW::save(self, path)
+    }
+
+    fn load(path: &Path) -> Result<Self> {
+        HNSW::load(path)
+    }
+}
+
+impl<T: Element> Default for HNSW<T> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::error::Error;
+    use crate::evaluation::recall_at_k;
+    use tempfile::tempdir;
+
+    fn setup_hnsw() -> (HNSW, Vec<Vector>) {
+        let hnsw = HNSW::new();
+        let vectors = vec![
+            Vector::new(vec![0.0, 0.0]),
+            Vector::new(vec![1.0, 1.0]),
+            Vector::new(vec![8.0, 8.0]),
+            Vector::new(vec![10.0, 10.0]),
+            Vector::new(vec![12.0, 12.0]),
+        ];
+
+        for vector in &vectors {
+            hnsw.insert(vector.clone(), 16, 32, 200, 4).unwrap();
+        }
+        (hnsw, vectors)
+    }
+
+    #[test]
+    fn test_insert_creates_entry_point() {
+        let (hnsw, _) = setup_hnsw();
+        assert!(
+            hnsw.entry_id.read().unwrap().is_some(),
+            "HNSW should have an entry point after insertion."
+        );
+    }
+
+    #[test]
+    fn test_search_returns_correct_neighbors() {
+        let (hnsw, _) = setup_hnsw();
+        let query = Vector::new(vec![0.5, 0.5]);
+
+        let k = 2;
+        let results = hnsw.search(query, k, 100).unwrap();
+
+        assert_eq!(results.len(), k, "Search should return k results.");
+
+        let expected_neighbor_1 = Vector::new(vec![0.0, 0.0]);
+        let expected_neighbor_2 = Vector::new(vec![1.0, 1.0]);
+
+        assert!(
+            results.contains(&expected_neighbor_1),
+            "Results should contain the nearest neighbor."
+        );
+        assert!(
+            results.contains(&expected_neighbor_2),
+            "Results should contain the second nearest neighbor."
+        );
+    }
+
+    #[test]
+    fn test_search_for_exact_match() {
+        let (hnsw, _) = setup_hnsw();
+
+        let query = Vector::new(vec![8.0, 8.0]);
+
+        let k = 1;
+        let results = hnsw.search(query.clone(), k, 100).unwrap();
+
+        assert_eq!(results.len(), k);
+
+        assert_eq!(
+            results[0], query,
+            "The first result should be the exact match."
+        );
+    }
 
     #[test]
     fn test_search_on_empty_index() {
         let hnsw = HNSW::new();
         let query = Vector::new(vec![1.0, 1.0]);
-        let results = hnsw.search(query, 5, 100);
+        let results = hnsw.search(query, 5, 100).unwrap();
 
         assert!(
             results.is_empty(),
@@ -329,7 +1647,7 @@ mod tests {
         let query = Vector::new(vec![0.5, 0.5]);
 
         let k = vectors.len() + 5;
-        let results = hnsw.search(query, k, 100);
+        let results = hnsw.search(query, k, 100).unwrap();
 
         assert_eq!(
             results.len(),
@@ -337,4 +1655,753 @@ mod tests {
             "Should return all elements if k > dataset size."
         );
     }
+
+    #[test]
+    fn test_search_with_f32_elements() {
+        let hnsw: HNSW<f32> = HNSW::new();
+        for point in [[0.0, 0.0], [1.0, 1.0], [8.0, 8.0], [10.0, 10.0]] {
+            hnsw.insert(Vector::new(point.to_vec()), 16, 32, 200, 4)
+                .unwrap();
+        }
+
+        let results = hnsw.search(Vector::new(vec![7.5, 7.5]), 1, 100).unwrap();
+
+        assert_eq!(results, vec![Vector::new(vec![8.0f32, 8.0])]);
+    }
+
+    #[test]
+    fn test_scalar_quantization_before_any_insert() {
+        let mut hnsw: HNSW = HNSW::new();
+        assert!(!hnsw.enable_scalar_quantization());
+        assert!(hnsw.quantizer().is_none());
+    }
+
+    #[test]
+    fn test_scalar_quantized_search_reranks_exactly() {
+        let (mut hnsw, _) = setup_hnsw();
+        assert!(hnsw.enable_scalar_quantization());
+        hnsw.insert(Vector::new(vec![9.0, 9.0]), 16, 32, 200, 4)
+            .unwrap();
+
+        let results = hnsw.search(Vector::new(vec![9.1, 9.1]), 3, 100).unwrap();
+
+        assert_eq!(
+            results,
+            vec![
+                Vector::new(vec![9.0, 9.0]),
+                Vector::new(vec![10.0, 10.0]),
+                Vector::new(vec![8.0, 8.0]),
+            ],
+            "Quantized search should return exactly ordered results."
+        );
+    }
+
+    #[test]
+    fn test_scalar_quantization_recall_against_unquantized() {
+        let mut rng = StdRng::seed_from_u64(7);
+        let mut random_vector = || Vector::new((0..16).map(|_| rng.r#gen::<f64>()).collect());
+
+        let mut hnsw = HNSW::new();
+        for _ in 0..500 {
+            hnsw.insert(random_vector(), 16, 32, 100, 1).unwrap();
+        }
+        let queries: Vec<Vector> = (0..20).map(|_| random_vector()).collect();
+
+        assert!(
+            hnsw.quantization_recall(&queries, 10, 50)
+                .unwrap()
+                .is_none()
+        );
+        assert!(hnsw.enable_scalar_quantization());
+
+        let recall = hnsw.quantization_recall(&queries, 10, 50).unwrap().unwrap();
+        assert!(recall >= 0.9, "SQ8 recall@10 was {recall}");
+    }
+
+    #[test]
+    fn test_quantized_search_follows_configured_metric() {
+        let mut rng = StdRng::seed_from_u64(7);
+        // Varied norms make the L2, cosine and inner product orders differ.
+        let mut random_vector = || {
+            let scale = rng.gen_range(0.1..2.0);
+            Vector::new((0..16).map(|_| scale * rng.gen_range(-1.0..1.0)).collect())
+        };
+        let vectors: Vec<Vector> = (0..500).map(|_| random_vector()).collect();
+        let queries: Vec<Vector> = (0..20).map(|_| random_vector()).collect();
+
+        for metric in [Metric::Cosine, Metric::Dot] {
+            let mut hnsw = HNSW::with_config(HNSWConfig {
+                metric,
+                ..HNSWConfig::default()
+            });
+            for vector in &vectors {
+                hnsw.insert(vector.clone(), 16, 32, 100, 1).unwrap();
+            }
+
+            assert!(hnsw.enable_scalar_quantization());
+            let recall = hnsw.quantization_recall(&queries, 10, 50).unwrap().unwrap();
+            assert!(recall >= 0.9, "{metric:?} SQ8 recall@10 was {recall}");
+
+            assert!(hnsw.enable_product_quantization(8, 64, 10));
+            let recall = hnsw.quantization_recall(&queries, 10, 50).unwrap().unwrap();
+            assert!(recall >= 0.7, "{metric:?} PQ recall@10 was {recall}");
+        }
+    }
+
+    #[test]
+    fn test_product_quantization_recall_against_unquantized() {
+        let mut rng = StdRng::seed_from_u64(7);
+        let mut random_vector = || Vector::new((0..16).map(|_| rng.r#gen::<f64>()).collect());
+
+        let mut hnsw = HNSW::new();
+        for _ in 0..500 {
+            hnsw.insert(random_vector(), 16, 32, 100, 1).unwrap();
+        }
+        let queries: Vec<Vector> = (0..20).map(|_| random_vector()).collect();
+
+        assert!(!hnsw.enable_product_quantization(32, 64, 10));
+        assert!(hnsw.enable_product_quantization(8, 64, 10));
+        assert!(matches!(hnsw.quantizer(), Some(Quantizer::Product(_))));
+
+        let recall = hnsw.quantization_recall(&queries, 10, 50).unwrap().unwrap();
+        assert!(recall >= 0.7, "PQ recall@10 was {recall}");
+    }
+
+    #[test]
+    fn test_binary_quantization_recall_against_unquantized() {
+        let mut rng = StdRng::seed_from_u64(7);
+        let mut random_vector = || Vector::new((0..64).map(|_| rng.gen_range(-1.0..1.0)).collect());
+
+        let mut hnsw = HNSW::new();
+        for _ in 0..500 {
+            hnsw.insert(random_vector(), 16, 32, 100, 1).unwrap();
+        }
+        let queries: Vec<Vector> = (0..20).map(|_| random_vector()).collect();
+
+        assert!(hnsw.enable_binary_quantization());
+        assert!(matches!(hnsw.quantizer(), Some(Quantizer::Binary(_))));
+
+        let recall = hnsw
+            .quantization_recall(&queries, 10, 200)
+            .unwrap()
+            .unwrap();
+        assert!(recall >= 0.6, "Binary recall@10 was {recall}");
+    }
+
+    #[test]
+    fn test_quantized_index_round_trips_through_save() -> Result<()> {
+        let dir = tempdir()?;
+        let vectors = random_vectors(300);
+        let queries = random_vectors(10);
+        let mut hnsw = HNSW::new();
+        for vector in &vectors {
+            hnsw.insert(vector.clone(), 16, 32, 100, 1)?;
+        }
+
+        for (name, quantize) in [
+            (
+                "sq8",
+                HNSW::enable_scalar_quantization as fn(&mut HNSW) -> bool,
+            ),
+            ("pq", |hnsw: &mut HNSW| {
+                hnsw.enable_product_quantization(4, 16, 5)
+            }),
+            ("binary", HNSW::enable_binary_quantization),
+        ] {
+            assert!(quantize(&mut hnsw));
+            let path = dir.path().join(name);
+            hnsw.save(&path)?;
+            let loaded: HNSW = HNSW::load(&path)?;
+
+            assert_eq!(loaded.quantizer(), hnsw.quantizer());
+            for query in &queries {
+                assert_eq!(
+                    loaded.search_nodes(query, 10, 20)?,
+                    hnsw.search_nodes(query, 10, 20)?,
+                    "A loaded {name} index should traverse the same codes."
+                );
+            }
+        }
+
+        hnsw.disable_quantization();
+        let path = dir.path().join("exact");
+        hnsw.save(&path)?;
+        assert_eq!(HNSW::<f64>::load(&path)?.quantizer(), None);
+
+        Ok(())
+    }
+
+    fn graph(hnsw: &HNSW) -> Vec<Vec<Vec<NodeId>>> {
+        let top_layer_num = hnsw.top_layer_num.load(Ordering::SeqCst);
+        (0..hnsw.len())
+            .map(|node_id| {
+                (0..=top_layer_num)
+                    .map(|layer| hnsw.neighbor_ids(&node_id, layer))
+                    .collect()
+            })
+            .collect()
+    }
+
+    fn random_vectors(count: usize) -> Vec<Vector> {
+        let mut rng = StdRng::seed_from_u64(7);
+        (0..count)
+            .map(|_| Vector::new((0..8).map(|_| rng.r#gen::<f64>()).collect()))
+            .collect()
+    }
+
+    #[test]
+    fn test_load_rejects_corrupt_capacity_and_counts() -> Result<()> {
+        let dir = tempdir()?;
+        let path = dir.path().join("index.hnsw");
+        let (hnsw, _) = setup_hnsw();
+        hnsw.save(&path)?;
+        let bytes = fs::read(&path)?;
+        // The tag and config take 26 bytes; then m, m_max, ef_construction,
+        // ml, ef, capacity, top layer, entry id and len as u64.
+        let field = |index: usize, val: u64| {
+            let mut corrupt = bytes.clone();
+            corrupt[26 + index * 8..][..8].copy_from_slice(&val.to_le_bytes());
+            corrupt
+        };
+
+        for corrupt in [
+            field(5, 0),
+            field(5, 33),
+            field(5, u64::MAX),
+            field(1, 16),
+            field(8, u64::MAX),
+        ] {
+            fs::write(&path, corrupt)?;
+            assert!(
+                HNSW::<f64>::load(&path).is_err(),
+                "Corrupt headers should be rejected."
+            );
+        }
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_seeded_single_thread_build_is_reproducible() {
+        let vectors = random_vectors(200);
+        let config = BuildConfig {
+            index: HNSWConfig {
+                seed: 42,
+                ..HNSWConfig::default()
+            },
+            ..BuildConfig::default()
+        };
+
+        let first = HNSW::build_from(vectors.clone(), 1, &config, BuildControl::default()).unwrap();
+        let second =
+            HNSW::build_from(vectors.clone(), 1, &config, BuildControl::default()).unwrap();
+
+        assert_eq!(first.len(), vectors.len());
+        assert_eq!(graph(&first), graph(&second));
+        assert_eq!(
+            *first.entry_id.read().unwrap(),
+            *second.entry_id.read().unwrap()
+        );
+    }
+
+    #[test]
+    fn test_same_seed_gives_same_levels() {
+        let vectors = random_vectors(100);
+        let build = |seed: u64| {
+            let hnsw = HNSW::with_config(HNSWConfig {
+                seed,
+                ..HNSWConfig::default()
+            });
+            for vector in &vectors {
+                hnsw.insert(vector.clone(), 8, 16, 50, 1).unwrap();
+            }
+            hnsw
+        };
+
+        let first = build(3);
+        let second = build(3);
+
+        assert_eq!(graph(&first), graph(&second));
+        assert_eq!(
+            first.top_layer_num.load(Ordering::SeqCst),
+            second.top_layer_num.load(Ordering::SeqCst)
+        );
+    }
+
+    #[test]
+    fn test_levels_are_capped_at_max_layer() {
+        let hnsw: HNSW = HNSW::with_config(HNSWConfig {
+            seed: 1,
+            max_layer: 2,
+            ..HNSWConfig::default()
+        });
+        let levels: Vec<LayerNum> = (0..1000).map(|_| hnsw.random_layer(10)).collect();
+
+        assert!(levels.iter().all(|&level| level <= 2));
+        assert!(
+            levels.contains(&2),
+            "A large ml should reach the capped top layer."
+        );
+    }
+
+    #[test]
+    fn test_config_round_trips_through_bytes() {
+        let config = HNSWConfig {
+            seed: u64::MAX - 5,
+            max_layer: 7,
+            metric: Metric::Cosine,
+            dimension: Some(384),
+        };
+
+        assert_eq!(HNSWConfig::from_bytes(&config.to_bytes()), Some(config));
+        assert_eq!(HNSWConfig::from_bytes(&[0; 16]), None);
+        let unset = HNSWConfig::default();
+        assert_eq!(HNSWConfig::from_bytes(&unset.to_bytes()), Some(unset));
+    }
+
+    #[test]
+    fn test_search_radius_matches_brute_force() {
+        let vectors = random_vectors(500);
+        let hnsw = HNSW::build_from(
+            vectors.clone(),
+            1,
+            &BuildConfig::default(),
+            BuildControl::default(),
+        )
+        .unwrap();
+        let query = &vectors[17];
+        let max_distance = 0.6;
+
+        let mut expected: Vec<(NodeId, f64)> = vectors
+            .iter()
+            .enumerate()
+            .map(|(node_id, vector)| (node_id, vector.squared_distance(query)))
+            .filter(|(_, dist)| *dist <= max_distance)
+            .collect();
+        expected.sort_by(|a, b| a.1.total_cmp(&b.1));
+
+        let found = hnsw.search_radius(query, max_distance, usize::MAX).unwrap();
+
+        assert!(
+            expected.len() > RADIUS_SEARCH_EF,
+            "The radius should cover more nodes than the beam."
+        );
+        assert_eq!(found, expected);
+    }
+
+    #[test]
+    fn test_search_radius_respects_limit() {
+        let (hnsw, _) = setup_hnsw();
+        let query = Vector::new(vec![8.5, 8.5]);
+
+        let found = hnsw.search_radius(&query, 30.0, 2).unwrap();
+        let ids: Vec<NodeId> = found.iter().map(|(node_id, _)| *node_id).collect();
+
+        assert_eq!(
+            ids,
+            vec![2, 3],
+            "Only the two nearest nodes should be kept."
+        );
+        assert!(hnsw.search_radius(&query, 30.0, 0).unwrap().is_empty());
+        assert!(hnsw.search_radius(&query, 0.1, 10).unwrap().is_empty());
+        assert!(
+            HNSW::<f64>::new()
+                .search_radius(&query, 1.0, 10)
+                .unwrap()
+                .is_empty()
+        );
+    }
+
+    #[test]
+    fn test_search_iter_pages_in_distance_order() {
+        let vectors = random_vectors(500);
+        let hnsw = HNSW::build_from(
+            vectors.clone(),
+            1,
+            &BuildConfig::default(),
+            BuildControl::default(),
+        )
+        .unwrap();
+        let query = Vector::new(vec![0.5; 8]);
+
+        let mut expected: Vec<NodeId> = (0..vectors.len()).collect();
+        expected.sort_by(|a, b| {
+            vectors[*a]
+                .squared_distance(&query)
+                .total_cmp(&vectors[*b].squared_distance(&query))
+        });
+
+        let found: Vec<(NodeId, f64)> =
+            hnsw.search_iter(query.clone()).unwrap().take(100).collect();
+        let ids: Vec<NodeId> = found.iter().map(|(node_id, _)| *node_id).collect();
+
+        assert_eq!(ids[..10], expected[..10]);
+        assert!(recall_at_k(std::slice::from_ref(&ids), &[expected[..100].to_vec()], 100) >= 0.95);
+        assert!(found.windows(2).all(|pair| pair[0].1 <= pair[1].1));
+
+        let second_page: Vec<(NodeId, f64)> =
+            hnsw.search_iter(query).unwrap().skip(10).take(10).collect();
+        assert_eq!(second_page, found[10..20]);
+    }
+
+    #[test]
+    fn test_search_iter_yields_every_node_once() {
+        let (hnsw, _) = setup_hnsw();
+
+        let ids: Vec<NodeId> = hnsw
+            .search_iter(Vector::new(vec![9.0, 9.0]))
+            .unwrap()
+            .map(|(node_id, _)| node_id)
+            .collect();
+
+        assert_eq!(ids.len(), 5);
+        assert_eq!(ids.iter().collect::<HashSet<_>>().len(), 5);
+        assert_eq!(
+            HNSW::<f64>::new()
+                .search_iter(Vector::new(vec![0.0]))
+                .unwrap()
+                .next(),
+            None
+        );
+    }
+
+    #[test]
+    fn test_search_batch_matches_single_queries() {
+        let vectors = random_vectors(300);
+        let hnsw = HNSW::build_from(
+            vectors.clone(),
+            1,
+            &BuildConfig::default(),
+            BuildControl::default(),
+        )
+        .unwrap();
+        let queries: Vec<Vector> = vectors.iter().step_by(7).cloned().collect();
+
+        let batch = hnsw.search_batch(&queries, 5, 50).unwrap();
+
+        assert_eq!(batch.len(), queries.len());
+        for (query, result) in queries.iter().zip(&batch) {
+            assert_eq!(*result, hnsw.search_nodes(query, 5, 50).unwrap());
+        }
+        assert!(hnsw.search_batch(&[], 5, 50).unwrap().is_empty());
+        assert_eq!(
+            HNSW::new().search_batch(&queries[..2], 5, 50).unwrap(),
+            vec![Vec::<NodeId>::new(); 2]
+        );
+    }
+
+    #[test]
+    fn test_search_mmr_skips_near_duplicates() {
+        let hnsw = HNSW::new();
+        for vector in [
+            vec![1.0, 0.0],
+            vec![1.0, 0.01],
+            vec![1.01, 0.0],
+            vec![0.0, 1.2],
+            vec![5.0, 5.0],
+        ] {
+            hnsw.insert(Vector::new(vector), 16, 32, 200, 1).unwrap();
+        }
+        let query = Vector::new(vec![0.0, 0.0]);
+
+        assert_eq!(
+            hnsw.search_mmr(&query, 3, 5, 1.0).unwrap(),
+            hnsw.search_nodes(&query, 3, 5).unwrap()
+        );
+        assert_eq!(
+            hnsw.search_mmr(&query, 2, 5, 0.5).unwrap(),
+            vec![0, 3],
+            "Near duplicates of the first result should be skipped."
+        );
+        assert!(hnsw.search_mmr(&query, 0, 5, 0.5).unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_search_uses_configured_metric() {
+        let vectors = [
+            Vector::new(vec![1.0, 0.0]),
+            Vector::new(vec![10.0, 1.0]),
+            Vector::new(vec![20.0, -5.0]),
+        ];
+        let query = Vector::new(vec![1.0, 0.1]);
+        let search = |metric: Metric| {
+            let hnsw = HNSW::with_config(HNSWConfig {
+                metric,
+                ..HNSWConfig::default()
+            });
+            for vector in &vectors {
+                hnsw.insert(vector.clone(), 16, 32, 200, 1).unwrap();
+            }
+            hnsw.search_nodes(&query, 1, 10).unwrap()
+        };
+
+        assert_eq!(search(Metric::L2), vec![0]);
+        assert_eq!(search(Metric::Cosine), vec![1]);
+        assert_eq!(
+            search(Metric::Dot),
+            vec![2],
+            "The largest inner product should win."
+        );
+    }
+
+    #[test]
+    fn test_mapped_index_matches_anonymous_index() -> Result<()> {
+        let dir = tempdir()?;
+        let path = dir.path().join("vectors.vec");
+        let vectors = random_vectors(300);
+        let config = BuildConfig::default();
+        let anonymous = HNSW::with_config(config.index);
+        let mapped = HNSW::create_mapped(config.index, &path)?;
+
+        for vector in &vectors {
+            for hnsw in [&anonymous, &mapped] {
+                hnsw.insert(
+                    vector.clone(),
+                    config.m,
+                    config.m_max,
+                    config.ef_construction,
+                    config.ml,
+                )?;
+            }
+        }
+        mapped.flush()?;
+
+        assert_eq!(graph(&mapped), graph(&anonymous));
+        assert_eq!(mapped.get(&123), Some(vectors[123].clone()));
+        assert_eq!(
+            mapped.search_nodes(&vectors[7], 1, 50)?,
+            vec![7],
+            "A mapped index should find a stored vector."
+        );
+        let file: VectorFile = VectorFile::open(&path)?;
+        assert_eq!(file.len(), vectors.len());
+        assert_eq!(file.get(299), Some(vectors[299].clone()));
+        assert!(
+            HNSW::<f64>::create_mapped(config.index, &path).is_err(),
+            "Creating over an existing vector file should fail."
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_open_mapped_reads_only_the_graph() -> Result<()> {
+        let dir = tempdir()?;
+        let path = dir.path().join("vectors.vec");
+        let vectors = random_vectors(300);
+        let before = {
+            let mut hnsw = HNSW::create_mapped(HNSWConfig::default(), &path)?;
+            for vector in &vectors[..299] {
+                hnsw.insert(vector.clone(), 8, 16, 100, 1)?;
+            }
+            assert!(hnsw.enable_scalar_quantization());
+            hnsw.delete(3);
+            hnsw.flush()?;
+            hnsw.save(&dir.path().join("full"))?;
+            graph(&hnsw)
+        };
+        let graph_len = fs::metadata(graph_path(&path))?.len();
+        let full_len = fs::metadata(dir.path().join("full"))?.len();
+        assert_eq!(
+            full_len - graph_len,
+            299 * 8 * 8,
+            "The graph file should hold everything but the vectors."
+        );
+
+        let hnsw: HNSW = HNSW::open_mapped(&path)?;
+        assert_eq!(graph(&hnsw), before);
+        assert_eq!(hnsw.len(), 298);
+        assert!(matches!(hnsw.quantizer(), Some(Quantizer::Scalar(_))));
+        assert_eq!(hnsw.search_nodes(&vectors[7], 1, 50)?, vec![7]);
+        assert_eq!(hnsw.get(&3), None);
+
+        // Inserts after reopening append to the same vector file.
+        hnsw.insert(vectors[299].clone(), 8, 16, 100, 1)?;
+        hnsw.flush()?;
+        drop(hnsw);
+        let hnsw: HNSW = HNSW::open_mapped(&path)?;
+        assert_eq!(hnsw.get(&299), Some(vectors[299].clone()));
+        assert_eq!(hnsw.search_nodes(&vectors[299], 1, 50)?, vec![299]);
+
+        // Inserts that were never flushed are dropped on reopening, and the
+        // next insert takes the first unflushed id.
+        hnsw.insert(Vector::new(vec![2.0; 8]), 8, 16, 100, 1)?;
+        hnsw.insert(Vector::new(vec![3.0; 8]), 8, 16, 100, 1)?;
+        drop(hnsw);
+        let hnsw: HNSW = HNSW::open_mapped(&path)?;
+        assert_eq!(hnsw.len(), 299);
+        assert_eq!(hnsw.get(&300), None);
+        let vector = Vector::new(vec![4.0; 8]);
+        assert_eq!(hnsw.insert(vector.clone(), 8, 16, 100, 1)?, 300);
+        assert_eq!(hnsw.get(&300), Some(vector.clone()));
+        assert_eq!(hnsw.search_nodes(&vector, 1, 50)?, vec![300]);
+
+        // A vector file shorter than its graph is rejected.
+        hnsw.flush()?;
+        drop(hnsw);
+        fs::remove_file(&path)?;
+        VectorFile::create(&path)?.push(&vectors[0])?;
+        assert!(HNSW::<f64>::open_mapped(&path).is_err());
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_parallel_build_keeps_ids_and_finds_neighbors() {
+        let vectors = random_vectors(400);
+        let progress_calls = AtomicUsize::new(0);
+        let progress = |_: usize| {
+            progress_calls.fetch_add(1, Ordering::SeqCst);
+        };
+        let control = BuildControl {
+            progress: Some(&progress),
+            cancel: None,
+        };
+
+        let hnsw = HNSW::build_from(vectors.clone(), 4, &BuildConfig::default(), control).unwrap();
+
+        assert_eq!(hnsw.len(), vectors.len());
+        assert_eq!(progress_calls.load(Ordering::SeqCst), vectors.len());
+        for (node_id, vector) in vectors.iter().enumerate().take(20) {
+            assert_eq!(hnsw.search_nodes(vector, 1, 50).unwrap(), vec![node_id]);
+        }
+    }
+
+    #[test]
+    fn test_concurrent_build_reaches_every_node() {
+        let vectors = random_vectors(1000);
+        // Small lists overflow often, which is when concurrent links used to
+        // be cut off.
+        let config = BuildConfig {
+            m: 8,
+            m_max: 16,
+            ef_construction: 64,
+            ..BuildConfig::default()
+        };
+
+        let hnsw = HNSW::build_from(vectors.clone(), 8, &config, BuildControl::default()).unwrap();
+
+        let entry_id = hnsw.entry_id.read().unwrap().unwrap();
+        let mut reached = HashSet::from([entry_id]);
+        let mut frontier = vec![entry_id];
+        while let Some(node_id) = frontier.pop() {
+            for neighbor_id in hnsw.neighbor_ids(&node_id, 0) {
+                assert!(hnsw.neighbor_ids(&neighbor_id, 0).len() <= config.m_max);
+                if reached.insert(neighbor_id) {
+                    frontier.push(neighbor_id);
+                }
+            }
+        }
+        assert_eq!(
+            reached.len(),
+            vectors.len(),
+            "Every node should be reachable from the entry point on layer 0."
+        );
+    }
+
+    #[test]
+    fn test_cancelled_build_stops_early() {
+        let vectors = random_vectors(300);
+        let cancel = AtomicBool::new(false);
+        let progress = |count: usize| {
+            if count >= 50 {
+                cancel.store(true, Ordering::SeqCst);
+            }
+        };
+        let control = BuildControl {
+            progress: Some(&progress),
+            cancel: Some(&cancel),
+        };
+
+        let hnsw = HNSW::build_from(vectors, 2, &BuildConfig::default(), control).unwrap();
+
+        assert!(hnsw.len() < 300, "Build should stop after cancellation.");
+    }
+
+    #[test]
+    fn test_nan_distances_rank_last() {
+        let hnsw = HNSW::with_config(HNSWConfig {
+            metric: Metric::Dot,
+            ..HNSWConfig::default()
+        });
+        // With the query below, the first vector's dot product sums `inf`
+        // and `-inf`.
+        for vector in [vec![1e308, 1e308], vec![1.0, 0.0], vec![0.0, 1.0]] {
+            hnsw.insert(Vector::new(vector), 16, 32, 200, 1).unwrap();
+        }
+        let query = Vector::new(vec![1e308, -1e308]);
+
+        assert_eq!(hnsw.search_nodes(&query, 3, 10).unwrap(), vec![1, 2, 0]);
+        let found: Vec<(NodeId, f64)> = hnsw.search_iter(query.clone()).unwrap().collect();
+        assert_eq!(found.len(), 3);
+        assert!(found[2].1.is_nan(), "The NaN distance should come last.");
+        assert_eq!(hnsw.search_mmr(&query, 2, 3, 0.5).unwrap(), vec![1, 2]);
+        assert!(hnsw.search_radius(&query, f64::NAN, 10).unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_rejects_mismatched_dimensions() {
+        let hnsw = HNSW::new();
+        assert!(
+            hnsw.search_nodes(&Vector::new(vec![1.0]), 1, 10)
+                .unwrap()
+                .is_empty(),
+            "An empty index without a dimension should accept any query."
+        );
+        hnsw.insert(Vector::new(vec![1.0, 2.0]), 16, 32, 200, 1)
+            .unwrap();
+
+        assert_eq!(hnsw.dimension(), Some(2));
+        assert!(matches!(
+            hnsw.insert(Vector::new(vec![1.0, 2.0, 3.0]), 16, 32, 200, 1),
+            Err(Error::DimensionMismatch {
+                expected: 2,
+                actual: 3
+            })
+        ));
+        assert!(matches!(
+            hnsw.search_nodes(&Vector::new(vec![1.0]), 1, 10),
+            Err(Error::DimensionMismatch { .. })
+        ));
+        assert!(hnsw.search_iter(Vector::new(vec![1.0])).is_err());
+        assert_eq!(hnsw.len(), 1);
+
+        let configured: HNSW = HNSW::with_config(HNSWConfig {
+            dimension: Some(3),
+            ..HNSWConfig::default()
+        });
+        assert!(
+            configured
+                .insert(Vector::new(vec![1.0, 2.0]), 16, 32, 200, 1)
+                .is_err(),
+            "The configured dimension should apply to the first insert."
+        );
+    }
+
+    #[test]
+    fn test_rejects_non_finite_values() {
+        let (hnsw, _) = setup_hnsw();
+
+        assert!(matches!(
+            hnsw.insert(Vector::new(vec![f64::NAN, 1.0]), 16, 32, 200, 1),
+            Err(Error::NonFiniteValue { position: 0 })
+        ));
+        assert!(matches!(
+            hnsw.search(Vector::new(vec![1.0, f64::INFINITY]), 1, 10),
+            Err(Error::NonFiniteValue { position: 1 })
+        ));
+        assert_eq!(hnsw.len(), 5);
+
+        let mut vectors = random_vectors(20);
+        vectors[10] = Vector::new(vec![f64::NAN; 8]);
+        for threads in [1, 2] {
+            let result = HNSW::build_from(
+                vectors.clone(),
+                threads,
+                &BuildConfig::default(),
+                BuildControl::default(),
+            );
+            assert!(matches!(result, Err(Error::NonFiniteValue { .. })));
+        }
+    }
 }
@@ -0,0 +1,268 @@
+use super::bm25::{BM25Config, BM25Index};
+use super::hnsw::{BuildConfig, HNSW};
+use crate::linalg::element::Element;
+use crate::linalg::vector::Vector;
+use crate::storage::node::NodeId;
+use crate::storage::{self, Storage};
+use std::collections::HashMap;
+use std::io;
+use std::path::Path;
+
+const TEXT_PREFIX: &[u8] = b"text/";
+const VECTOR_PREFIX: &[u8] = b"vec/";
+
+/// How `hybrid_search` combines the lexical and the vector ranking.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Fusion {
+    /// Reciprocal rank fusion: each list contributes `1 / (k + rank)` per
+    /// document, with ranks starting at 1. Ignores raw scores entirely.
+    ReciprocalRank { k: f64 },
+    /// `text_weight * bm25 + (1 - text_weight) * similarity`, with BM25
+    /// scores divided by the best one and vector distances min-max scaled
+    /// to `[0, 1]` (closest is 1) over the candidates.
+    Weighted { text_weight: f64 },
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct HybridConfig {
+    pub build: BuildConfig,
+    pub bm25: BM25Config,
+    /// Candidates fetched from each side before fusion.
+    pub candidates: usize,
+    pub fusion: Fusion,
+}
+
+impl Default for HybridConfig {
+    fn default() -> Self {
+        Self {
+            build: BuildConfig::default(),
+            bm25: BM25Config::default(),
+            candidates: 100,
+            fusion: Fusion::ReciprocalRank { k: 60.0 },
+        }
+    }
+}
+
+/// Records with a text field and a vector. Text is indexed with BM25 and
+/// vectors with `HNSW`; both are stored in one `Storage`, and the graph is
+/// rebuilt from the stored vectors on open.
+pub struct HybridIndex<T: Element = f64> {
+    storage: Storage,
+    text: BM25Index,
+    hnsw: HNSW<T>,
+    config: HybridConfig,
+}
+
+impl<T: Element> HybridIndex<T> {
+    pub fn open(dir: &Path, config: HybridConfig) -> io::Result<Self> {
+        let storage = Storage::open(dir)?;
+        let text = BM25Index::open(&storage, TEXT_PREFIX, config.bm25)?;
+        let hnsw = HNSW::with_config(config.build.index);
+
+        let vectors = storage.scan_prefix(VECTOR_PREFIX)?;
+        for (expected_id, entry) in vectors.into_iter().enumerate() {
+            let vector = entry.value.as_deref().and_then(Vector::<T>::from_bytes);
+            let node_id = vector_key_id(&entry.key);
+            let (Some(vector), Some(node_id)) = (vector, node_id) else {
+                return Err(io::Error::new(
+                    io::ErrorKind::InvalidData,
+                    "Corrupt stored vector",
+                ));
+            };
+            if node_id != expected_id {
+                return Err(io::Error::new(
+                    io::ErrorKind::InvalidData,
+                    "Stored vector ids are not contiguous",
+                ));
+            }
+            hnsw.insert(
+                vector,
+                config.build.m,
+                config.build.m_max,
+                config.build.ef_construction,
+                config.build.ml,
+            )?;
+        }
+
+        Ok(Self {
+            storage,
+            text,
+            hnsw,
+            config,
+        })
+    }
+
+    pub fn len(&self) -> usize {
+        self.hnsw.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.hnsw.is_empty()
+    }
+
+    pub fn config(&self) -> &HybridConfig {
+        &self.config
+    }
+
+    /// Stores the record, then links its vector into the graph, so a
+    /// failed write leaves no node that `open` would not rebuild. Vectors
+    /// are validated first, as the graph would reject them after the write.
+    pub fn insert(&mut self, text: &str, vector: Vector<T>) -> io::Result<NodeId> {
+        if vector.is_empty() {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "Cannot store empty vectors",
+            ));
+        }
+        vector.validate(self.hnsw.dimension())?;
+        let node_id = self.hnsw.len();
+        let key = [VECTOR_PREFIX, &(node_id as u64).to_be_bytes()].concat();
+        self.storage
+            .set(&key, &vector.to_bytes(), storage::timestamp())?;
+        self.text.insert(&mut self.storage, node_id, text)?;
+
+        let build = self.config.build;
+        let linked_id = self.hnsw.insert(
+            vector,
+            build.m,
+            build.m_max,
+            build.ef_construction,
+            build.ml,
+        )?;
+        debug_assert_eq!(linked_id, node_id);
+        Ok(node_id)
+    }
+
+    pub fn flush(&mut self) -> io::Result<()> {
+        self.storage.flush()
+    }
+
+    pub fn checkpoint(&mut self) -> io::Result<()> {
+        self.storage.checkpoint()
+    }
+
+    pub fn text_search(&self, text: &str, k: usize) -> io::Result<Vec<(NodeId, f64)>> {
+        self.text.search(&self.storage, text, k)
+    }
+
+    /// Fuses the top `config.candidates` BM25 matches for `text` with the
+    /// nearest `config.candidates` vectors to `vector` and returns the best
+    /// `k` record ids with their fused scores, highest first.
+    pub fn hybrid_search(
+        &self,
+        text: &str,
+        vector: &Vector<T>,
+        k: usize,
+    ) -> io::Result<Vec<(NodeId, f64)>> {
+        let lexical = self.text_search(text, self.config.candidates)?;
+        let semantic: Vec<(NodeId, f64)> = self
+            .hnsw
+            .search_iter(vector.clone())?
+            .take(self.config.candidates)
+            .collect();
+
+        let mut fused: HashMap<NodeId, f64> = HashMap::new();
+        match self.config.fusion {
+            Fusion::ReciprocalRank { k: rank_constant } => {
+                for list in [&lexical, &semantic] {
+                    for (rank, (node_id, _)) in list.iter().enumerate() {
+                        *fused.entry(*node_id).or_default() +=
+                            1.0 / (rank_constant + rank as f64 + 1.0);
+                    }
+                }
+            }
+            Fusion::Weighted { text_weight } => {
+                let best_text = lexical.first().map_or(1.0, |(_, score)| *score);
+                for (node_id, score) in &lexical {
+                    *fused.entry(*node_id).or_default() += text_weight * score / best_text;
+                }
+                let nearest = semantic.first().map_or(0.0, |(_, dist)| *dist);
+                let furthest = semantic.last().map_or(0.0, |(_, dist)| *dist);
+                for (node_id, dist) in &semantic {
+                    let similarity = if furthest > nearest {
+                        (furthest - dist) / (furthest - nearest)
+                    } else {
+                        1.0
+                    };
+                    *fused.entry(*node_id).or_default() += (1.0 - text_weight) * similarity;
+                }
+            }
+        }
+
+        let mut ranked: Vec<(NodeId, f64)> = fused.into_iter().collect();
+        ranked.sort_by(|a, b| b.1.total_cmp(&a.1).then(a.0.cmp(&b.0)));
+        ranked.truncate(k);
+        Ok(ranked)
+    }
+}
+
+fn vector_key_id(key: &[u8]) -> Option<NodeId> {
+    let id = key.strip_prefix(VECTOR_PREFIX)?;
+    Some(u64::from_be_bytes(id.try_into().ok()?) as NodeId)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::tempdir;
+
+    fn catalog(dir: &Path, fusion: Fusion) -> io::Result<HybridIndex> {
+        let config = HybridConfig {
+            fusion,
+            ..HybridConfig::default()
+        };
+        let mut index = HybridIndex::open(dir, config)?;
+        index.insert("trail running shoe", Vector::new(vec![0.0, 0.0]))?;
+        index.insert("road running shoe", Vector::new(vec![0.1, 0.0]))?;
+        index.insert("running shoe QX-42", Vector::new(vec![5.0, 5.0]))?;
+        index.insert("wool hiking sock", Vector::new(vec![0.0, 0.2]))?;
+        Ok(index)
+    }
+
+    #[test]
+    fn test_hybrid_search_surfaces_keyword_matches() -> io::Result<()> {
+        for fusion in [
+            Fusion::ReciprocalRank { k: 60.0 },
+            Fusion::Weighted { text_weight: 0.5 },
+        ] {
+            let dir = tempdir()?;
+            let index = catalog(dir.path(), fusion)?;
+            let query = Vector::new(vec![0.0, 0.0]);
+
+            let results = index.hybrid_search("qx-42", &query, 2)?;
+
+            assert_eq!(results.len(), 2);
+            assert!(
+                results.iter().any(|(node_id, _)| *node_id == 2),
+                "The exact product code should be found despite its distant vector ({fusion:?})."
+            );
+            assert!(results.windows(2).all(|pair| pair[0].1 >= pair[1].1));
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn test_hybrid_index_reopens() -> io::Result<()> {
+        let dir = tempdir()?;
+        {
+            let mut index = catalog(dir.path(), Fusion::ReciprocalRank { k: 60.0 })?;
+            index.checkpoint()?;
+            index.insert("red umbrella", Vector::new(vec![9.0, 9.0]))?;
+            assert!(index.insert("bad record", Vector::new(vec![1.0])).is_err());
+            index.flush()?;
+        }
+
+        let index: HybridIndex = HybridIndex::open(dir.path(), HybridConfig::default())?;
+
+        assert_eq!(index.len(), 5);
+        assert!(
+            index.text_search("bad", 1)?.is_empty(),
+            "A rejected vector should not leave its record behind."
+        );
+        assert_eq!(index.text_search("umbrella", 1)?[0].0, 4);
+        let results = index.hybrid_search("umbrella", &Vector::new(vec![9.0, 9.0]), 1)?;
+        assert_eq!(results[0].0, 4);
+
+        Ok(())
+    }
+}
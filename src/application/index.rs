@@ -0,0 +1,410 @@
+use super::disk_ann::{DiskANNConfig, DiskANNParams};
+use super::flat::FlatIndex;
+use super::hnsw::{HNSW, HNSWConfig, HNSWParams};
+use super::ivf_flat::IVFFlat;
+use super::ivf_pq::{IVFPQ, IVFPQConfig};
+use super::vector_index::VectorIndex;
+use crate::error::{Error, Result};
+use crate::linalg::element::Element;
+use crate::linalg::metric::Metric;
+
+/// Index type and parameters chosen when a collection is created.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum IndexConfig {
+    HNSW {
+        m: usize,
+        m_max: usize,
+        ef_construction: usize,
+        ml: usize,
+        ef: usize,
+    },
+    IVFFlat {
+        num_lists: usize,
+        nprobe: usize,
+    },
+    Flat,
+    IVFPQ {
+        num_lists: usize,
+        nprobe: usize,
+        num_subspaces: usize,
+        num_centroids: usize,
+    },
+    /// A graph built once over a fixed set of vectors; see `new_index`.
+    DiskANN {
+        max_degree: usize,
+        build_list_size: usize,
+        num_subspaces: usize,
+        list_size: usize,
+        beam_width: usize,
+    },
+}
+
+impl Default for IndexConfig {
+    fn default() -> Self {
+        IndexConfig::HNSW {
+            m: 16,
+            m_max: 32,
+            ef_construction: 200,
+            ml: 1,
+            ef: 100,
+        }
+    }
+}
+
+impl IndexConfig {
+    /// Whether this index type can rank by `metric`. IVF-Flat and IVF-PQ
+    /// cluster by L2, so they only support L2.
+    pub fn supports(&self, metric: Metric) -> bool {
+        !matches!(
+            self,
+            IndexConfig::IVFFlat { .. } | IndexConfig::IVFPQ { .. }
+        ) || metric == Metric::L2
+    }
+
+    /// Encodes as a type tag followed by the parameters as `u64` LE.
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let (tag, params): (u8, Vec<usize>) = match *self {
+            IndexConfig::HNSW {
+                m,
+                m_max,
+                ef_construction,
+                ml,
+                ef,
+            } => (0, vec![m, m_max, ef_construction, ml, ef]),
+            IndexConfig::IVFFlat { num_lists, nprobe } => (1, vec![num_lists, nprobe]),
+            IndexConfig::Flat => (2, Vec::new()),
+            IndexConfig::IVFPQ {
+                num_lists,
+                nprobe,
+                num_subspaces,
+                num_centroids,
+            } => (3, vec![num_lists, nprobe, num_subspaces, num_centroids]),
+            IndexConfig::DiskANN {
+                max_degree,
+                build_list_size,
+                num_subspaces,
+                list_size,
+                beam_width,
+            } => (
+                4,
+                vec![
+                    max_degree,
+                    build_list_size,
+                    num_subspaces,
+                    list_size,
+                    beam_width,
+                ],
+            ),
+        };
+        let mut bytes = vec![tag];
+        for param in params {
+            bytes.extend_from_slice(&(param as u64).to_le_bytes());
+        }
+        bytes
+    }
+
+    pub fn from_bytes(bytes: &[u8]) -> Option<Self> {
+        let (tag, rest) = bytes.split_first()?;
+        if rest.len() % 8 != 0 {
+            return None;
+        }
+        let params: Vec<usize> = rest
+            .chunks_exact(8)
+            .map(|chunk| u64::from_le_bytes(chunk.try_into().unwrap()) as usize)
+            .collect();
+        match (tag, params.as_slice()) {
+            (0, &[m, m_max, ef_construction, ml, ef]) => Some(IndexConfig::HNSW {
+                m,
+                m_max,
+                ef_construction,
+                ml,
+                ef,
+            }),
+            (1, &[num_lists, nprobe]) => Some(IndexConfig::IVFFlat { num_lists, nprobe }),
+            (2, &[]) => Some(IndexConfig::Flat),
+            (3, &[num_lists, nprobe, num_subspaces, num_centroids]) => Some(IndexConfig::IVFPQ {
+                num_lists,
+                nprobe,
+                num_subspaces,
+                num_centroids,
+            }),
+            (
+                4,
+                &[
+                    max_degree,
+                    build_list_size,
+                    num_subspaces,
+                    list_size,
+                    beam_width,
+                ],
+            ) => Some(IndexConfig::DiskANN {
+                max_degree,
+                build_list_size,
+                num_subspaces,
+                list_size,
+                beam_width,
+            }),
+            _ => None,
+        }
+    }
+
+    /// An empty index of this type ranking by `metric`, with its search
+    /// parameters taken from the config. Fails if the type does not
+    /// support `metric`; see `supports`.
+    ///
+    /// A DiskANN graph cannot take inserts, so its index starts as an exact
+    /// `FlatIndex` that does; `disk_ann` gives the settings to build the
+    /// graph from those vectors once they are all in.
+    pub fn new_index<T: Element>(&self, metric: Metric) -> Result<Box<dyn VectorIndex<T>>> {
+        if !self.supports(metric) {
+            return Err(Error::InvalidConfig(format!(
+                "{self:?} does not support the {metric:?} metric"
+            )));
+        }
+        Ok(match *self {
+            IndexConfig::HNSW {
+                m,
+                m_max,
+                ef_construction,
+                ml,
+                ef,
+            } => {
+                let mut hnsw = HNSW::with_config(HNSWConfig {
+                    metric,
+                    ..HNSWConfig::default()
+                });
+                hnsw.set_params(HNSWParams {
+                    m,
+                    m_max,
+                    ef_construction,
+                    ml,
+                    ef,
+                });
+                Box::new(hnsw)
+            }
+            IndexConfig::IVFFlat { num_lists, nprobe } => {
+                if num_lists == 0 || nprobe == 0 {
+                    return Err(Error::InvalidConfig(
+                        "num_lists and nprobe must be positive".to_string(),
+                    ));
+                }
+                let mut ivf = IVFFlat::new(num_lists);
+                ivf.set_nprobe(nprobe);
+                Box::new(ivf)
+            }
+            IndexConfig::Flat => Box::new(FlatIndex::with_metric(metric)),
+            IndexConfig::IVFPQ {
+                num_lists,
+                nprobe,
+                num_subspaces,
+                num_centroids,
+            } => {
+                if num_lists == 0 || nprobe == 0 || num_subspaces == 0 {
+                    return Err(Error::InvalidConfig(
+                        "num_lists, nprobe and num_subspaces must be positive".to_string(),
+                    ));
+                }
+                if !(1..=256).contains(&num_centroids) {
+                    return Err(Error::InvalidConfig(
+                        "num_centroids must be between 1 and 256".to_string(),
+                    ));
+                }
+                let mut ivf = IVFPQ::new(IVFPQConfig {
+                    num_lists,
+                    num_subspaces,
+                    num_centroids,
+                    ..IVFPQConfig::default()
+                });
+                ivf.set_nprobe(nprobe);
+                Box::new(ivf)
+            }
+            IndexConfig::DiskANN { .. } => {
+                self.disk_ann(metric)?;
+                Box::new(FlatIndex::with_metric(metric))
+            }
+        })
+    }
+
+    /// Build settings and search parameters of a DiskANN config ranking by
+    /// `metric`. Build pruning keeps the default slack, except for
+    /// `Metric::Dot`, whose distances can be negative.
+    pub fn disk_ann(&self, metric: Metric) -> Result<(DiskANNConfig, DiskANNParams)> {
+        let IndexConfig::DiskANN {
+            max_degree,
+            build_list_size,
+            num_subspaces,
+            list_size,
+            beam_width,
+        } = *self
+        else {
+            return Err(Error::InvalidConfig(format!(
+                "{self:?} is not a DiskANN config"
+            )));
+        };
+        if max_degree == 0 || num_subspaces == 0 || list_size == 0 || beam_width == 0 {
+            return Err(Error::InvalidConfig(
+                "max_degree, num_subspaces, list_size and beam_width must be positive".to_string(),
+            ));
+        }
+        let config = DiskANNConfig {
+            max_degree,
+            build_list_size,
+            metric,
+            num_subspaces,
+            alpha: match metric {
+                Metric::Dot => 1.0,
+                _ => DiskANNConfig::default().alpha,
+            },
+            ..DiskANNConfig::default()
+        };
+        let params = DiskANNParams {
+            list_size,
+            beam_width,
+        };
+        Ok((config, params))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::linalg::vector::Vector;
+
+    fn disk_ann() -> IndexConfig {
+        IndexConfig::DiskANN {
+            max_degree: 16,
+            build_list_size: 32,
+            num_subspaces: 2,
+            list_size: 32,
+            beam_width: 2,
+        }
+    }
+
+    #[test]
+    fn test_index_type_follows_config() -> Result<()> {
+        let configs = [
+            IndexConfig::default(),
+            IndexConfig::IVFFlat {
+                num_lists: 4,
+                nprobe: 4,
+            },
+            IndexConfig::Flat,
+            IndexConfig::IVFPQ {
+                num_lists: 4,
+                nprobe: 4,
+                num_subspaces: 2,
+                num_centroids: 16,
+            },
+            disk_ann(),
+        ];
+
+        for config in configs {
+            let mut index = config.new_index(Metric::L2)?;
+            for point in [[0.0, 0.0], [1.0, 1.0], [8.0, 8.0]] {
+                index.insert(Vector::new(point.to_vec()))?;
+            }
+
+            let results = index.search(&Vector::new(vec![7.0, 7.0]), 1)?;
+
+            assert_eq!(results, vec![(2, 2.0)], "{config:?}");
+            assert!(
+                index.insert(Vector::new(vec![f64::NAN, 0.0])).is_err(),
+                "{config:?} should reject NaN components."
+            );
+            assert!(
+                matches!(
+                    index.insert(Vector::new(vec![1.0, 2.0, 3.0])),
+                    Err(Error::DimensionMismatch { .. })
+                ),
+                "{config:?} should reject vectors of another length."
+            );
+            assert!(
+                matches!(
+                    index.search(&Vector::new(vec![1.0]), 1),
+                    Err(Error::DimensionMismatch { .. })
+                ),
+                "{config:?} should reject queries of another length."
+            );
+        }
+
+        let ivf = IndexConfig::IVFFlat {
+            num_lists: 4,
+            nprobe: 4,
+        };
+        assert!(matches!(
+            ivf.new_index::<f64>(Metric::Dot),
+            Err(Error::InvalidConfig(_))
+        ));
+        for (num_lists, nprobe) in [(0, 4), (4, 0)] {
+            let ivf = IndexConfig::IVFFlat { num_lists, nprobe };
+            assert!(
+                matches!(
+                    ivf.new_index::<f64>(Metric::L2),
+                    Err(Error::InvalidConfig(_))
+                ),
+                "{ivf:?} should be rejected."
+            );
+        }
+        let bad_configs = [
+            IndexConfig::IVFPQ {
+                num_lists: 4,
+                nprobe: 4,
+                num_subspaces: 2,
+                num_centroids: 0,
+            },
+            IndexConfig::IVFPQ {
+                num_lists: 4,
+                nprobe: 4,
+                num_subspaces: 0,
+                num_centroids: 16,
+            },
+            IndexConfig::DiskANN {
+                max_degree: 0,
+                build_list_size: 32,
+                num_subspaces: 2,
+                list_size: 32,
+                beam_width: 2,
+            },
+        ];
+        for config in bad_configs {
+            assert!(
+                matches!(
+                    config.new_index::<f64>(Metric::L2),
+                    Err(Error::InvalidConfig(_))
+                ),
+                "{config:?} should be rejected."
+            );
+        }
+        assert!(!configs[3].supports(Metric::Dot));
+        assert!(disk_ann().supports(Metric::Dot));
+        assert_eq!(disk_ann().disk_ann(Metric::Dot)?.0.alpha, 1.0);
+        assert!(IndexConfig::Flat.disk_ann(Metric::L2).is_err());
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_index_config_round_trips_through_bytes() {
+        let configs = [
+            IndexConfig::default(),
+            IndexConfig::IVFFlat {
+                num_lists: 64,
+                nprobe: 8,
+            },
+            IndexConfig::Flat,
+            IndexConfig::IVFPQ {
+                num_lists: 64,
+                nprobe: 8,
+                num_subspaces: 16,
+                num_centroids: 256,
+            },
+            disk_ann(),
+        ];
+
+        for config in configs {
+            assert_eq!(IndexConfig::from_bytes(&config.to_bytes()), Some(config));
+        }
+        assert_eq!(IndexConfig::from_bytes(&[1, 0, 0]), None);
+        assert_eq!(IndexConfig::from_bytes(&[2; 9]), None);
+    }
+}
@@ -0,0 +1,538 @@
+use super::vector_index::VectorIndex;
+use crate::error::Result;
+use crate::linalg::element::{Element, ElementType};
+use crate::linalg::kmeans::{kmeans, nearest_centroid};
+use crate::linalg::metric::Metric;
+use crate::linalg::simd::squared_l2_f64;
+use crate::linalg::vector::Vector;
+use crate::numeric::ordered_float::OrderedFloat;
+use crate::storage::node::NodeId;
+use priority_queue::DoublePriorityQueue;
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
+use std::collections::HashSet;
+use std::fs;
+use std::io::{self, Read};
+use std::path::Path;
+
+/// Vectors per list `insert` waits for before training the centroids itself.
+pub(super) const AUTO_TRAIN_POINTS_PER_LIST: usize = 16;
+const AUTO_TRAIN_ITERATIONS: usize = 10;
+
+/// Inverted file over coarse k-means centroids. Vectors are stored back to
+/// back at full precision, so node ids are insertion positions; each
+/// posting list holds the ids of the live vectors closest to its centroid.
+///
+/// Until the index is trained there are no centroids and every vector sits
+/// in a single list, so search is an exact scan. `train` can be called at
+/// any time; otherwise `insert` trains once there are
+/// `AUTO_TRAIN_POINTS_PER_LIST` vectors per list, and retrains whenever the
+/// index has doubled since. Auto-training is seeded by the vector count, so
+/// the same inserts always give the same lists. It runs inside the insert
+/// that crosses the threshold, which then takes as long as k-means over
+/// every stored vector. Calling `train` after a bulk load pays that cost up
+/// front and pushes the next retrain out to twice the loaded size.
+#[allow(clippy::upper_case_acronyms)]
+pub struct IVFFlat<T: Element = f64> {
+    num_lists: usize,
+    nprobe: usize,
+    dimension: Option<usize>,
+    centroids: Vec<Vec<f64>>,
+    lists: Vec<Vec<NodeId>>,
+    data: Vec<T>,
+    deleted: HashSet<NodeId>,
+    trained_len: usize,
+}
+
+impl<T: Element> IVFFlat<T> {
+    pub fn new(num_lists: usize) -> Self {
+        Self {
+            num_lists,
+            nprobe: 1,
+            dimension: None,
+            centroids: Vec::new(),
+            lists: vec![Vec::new()],
+            data: Vec::new(),
+            deleted: HashSet::new(),
+            trained_len: 0,
+        }
+    }
+
+    /// Lists scanned by searches made through `VectorIndex`; 1 until set.
+    pub fn nprobe(&self) -> usize {
+        self.nprobe
+    }
+
+    pub fn set_nprobe(&mut self, nprobe: usize) {
+        self.nprobe = nprobe;
+    }
+
+    /// Length every vector must have, once fixed by the first insert.
+    pub fn dimension(&self) -> Option<usize> {
+        self.dimension
+    }
+
+    pub fn is_trained(&self) -> bool {
+        !self.centroids.is_empty()
+    }
+
+    /// Number of live vectors.
+    pub fn len(&self) -> usize {
+        self.num_rows() - self.deleted.len()
+    }
+
+    /// Number of stored rows, deleted ones included.
+    fn num_rows(&self) -> usize {
+        match self.dimension {
+            Some(0) | None => 0,
+            Some(dimension) => self.data.len() / dimension,
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Runs k-means over every live vector and reassigns them to the new
+    /// centroids. Can be called again after large loads to rebalance lists.
+    pub fn train(&mut self, iterations: usize, rng: &mut impl Rng) {
+        let node_ids = self.live_ids();
+        let points: Vec<Vec<f64>> = node_ids.iter().map(|id| self.point(*id)).collect();
+        let centroids = kmeans(&points, self.num_lists, iterations, rng);
+        if centroids.is_empty() {
+            return;
+        }
+        self.centroids = centroids;
+        self.lists = self.assign(node_ids, &points);
+        self.trained_len = self.len();
+    }
+
+    fn live_ids(&self) -> Vec<NodeId> {
+        (0..self.num_rows())
+            .filter(|node_id| !self.deleted.contains(node_id))
+            .collect()
+    }
+
+    /// Posting lists for `node_ids` under the current centroids, each in
+    /// id order.
+    fn assign(&self, node_ids: Vec<NodeId>, points: &[Vec<f64>]) -> Vec<Vec<NodeId>> {
+        let mut lists = vec![Vec::new(); self.centroids.len().max(1)];
+        for (node_id, point) in node_ids.into_iter().zip(points) {
+            lists[self.nearest_list(point)].push(node_id);
+        }
+        lists
+    }
+
+    fn nearest_list(&self, point: &[f64]) -> usize {
+        if self.is_trained() {
+            nearest_centroid(&self.centroids, point)
+        } else {
+            0
+        }
+    }
+
+    fn row(&self, node_id: NodeId) -> &[T] {
+        let dimension = self.dimension.unwrap_or(0);
+        &self.data[node_id * dimension..(node_id + 1) * dimension]
+    }
+
+    fn point(&self, node_id: NodeId) -> Vec<f64> {
+        self.row(node_id).iter().map(|val| val.to_f64()).collect()
+    }
+
+    /// Rejects vectors whose length differs from the first insert, and
+    /// vectors with NaN or infinite components.
+    pub fn insert(&mut self, vector: Vector<T>) -> Result<NodeId> {
+        vector.validate(self.dimension)?;
+        self.dimension.get_or_insert(vector.len());
+        let list = self.nearest_list(&vector.to_f64());
+        let node_id = self.num_rows();
+        self.data.extend_from_slice(vector.data());
+        self.lists[list].push(node_id);
+
+        let len = self.len();
+        if len >= (self.num_lists * AUTO_TRAIN_POINTS_PER_LIST).max(2 * self.trained_len) {
+            self.train(
+                AUTO_TRAIN_ITERATIONS,
+                &mut StdRng::seed_from_u64(len as u64),
+            );
+        }
+        Ok(node_id)
+    }
+
+    /// Removes `node_id` from its posting list. Its row stays in place so
+    /// ids are never reused. Returns false if it was not a live vector.
+    pub fn delete(&mut self, node_id: NodeId) -> bool {
+        if node_id >= self.num_rows() || !self.deleted.insert(node_id) {
+            return false;
+        }
+        for list in &mut self.lists {
+            if let Some(position) = list.iter().position(|id| *id == node_id) {
+                list.remove(position);
+                break;
+            }
+        }
+        true
+    }
+
+    pub fn search(&self, query: Vector<T>, k: usize, nprobe: usize) -> Result<Vec<Vector<T>>> {
+        Ok(self
+            .search_ids(&query, k, nprobe)?
+            .into_iter()
+            .map(|(node_id, _)| Vector::new(self.row(node_id).to_vec()))
+            .collect())
+    }
+
+    /// `k` nearest ids with their squared distances, closest first, from
+    /// the `nprobe` lists whose centroids are closest to `query`.
+    pub fn search_ids(
+        &self,
+        query: &Vector<T>,
+        k: usize,
+        nprobe: usize,
+    ) -> Result<Vec<(NodeId, f64)>> {
+        self.search_ids_filtered(query, k, nprobe, |_| true)
+    }
+
+    /// Same as `search_ids`, keeping only ids accepted by `filter`.
+    pub fn search_ids_filtered(
+        &self,
+        query: &Vector<T>,
+        k: usize,
+        nprobe: usize,
+        filter: impl Fn(NodeId) -> bool,
+    ) -> Result<Vec<(NodeId, f64)>> {
+        query.validate(self.dimension)?;
+        let probes: Vec<usize> = if self.is_trained() {
+            let widened_query = query.to_f64();
+            let mut probes: Vec<(usize, OrderedFloat)> = self
+                .centroids
+                .iter()
+                .map(|centroid| OrderedFloat(squared_l2_f64(centroid, &widened_query)))
+                .enumerate()
+                .collect();
+            probes.sort_by_key(|(_, dist)| *dist);
+            probes
+                .into_iter()
+                .take(nprobe)
+                .map(|(list, _)| list)
+                .collect()
+        } else {
+            vec![0]
+        };
+
+        let mut nearest = DoublePriorityQueue::new();
+        for list in probes {
+            for node_id in self.lists[list].iter().filter(|id| filter(**id)) {
+                let dist = OrderedFloat(T::squared_l2(self.row(*node_id), query.data()));
+                nearest.push(*node_id, dist);
+                if nearest.len() > k {
+                    nearest.pop_max();
+                }
+            }
+        }
+
+        Ok(nearest
+            .into_sorted_iter()
+            .map(|(node_id, dist)| (node_id, dist.0))
+            .collect())
+    }
+
+    /// Writes `[element tag][num_lists][nprobe][trained_len][dimension]
+    /// [rows][deleted ids][centroids][data]`, integers as `u64` LE and a
+    /// dimension of 0 for an empty index. Lists are not saved: `load` puts
+    /// every live vector back under its nearest centroid in id order, which
+    /// is where `insert` and `train` left it.
+    pub fn save(&self, path: &Path) -> io::Result<()> {
+        let mut bytes = vec![T::ELEMENT_TYPE.tag()];
+        for val in [
+            self.num_lists,
+            self.nprobe,
+            self.trained_len,
+            self.dimension.unwrap_or(0),
+            self.num_rows(),
+            self.deleted.len(),
+        ] {
+            bytes.extend_from_slice(&(val as u64).to_le_bytes());
+        }
+        for node_id in &self.deleted {
+            bytes.extend_from_slice(&(*node_id as u64).to_le_bytes());
+        }
+        bytes.extend_from_slice(&(self.centroids.len() as u64).to_le_bytes());
+        for val in self.centroids.iter().flatten() {
+            bytes.extend_from_slice(&val.to_le_bytes());
+        }
+        for val in &self.data {
+            val.write_le(&mut bytes);
+        }
+        fs::write(path, bytes)
+    }
+
+    pub fn load(path: &Path) -> io::Result<Self> {
+        let bytes = fs::read(path)?;
+        let mut reader = bytes.as_slice();
+        let invalid = |msg: &str| io::Error::new(io::ErrorKind::InvalidData, msg.to_string());
+
+        let mut tag = [0; 1];
+        reader.read_exact(&mut tag)?;
+        if ElementType::from_tag(tag[0]) != Some(T::ELEMENT_TYPE) {
+            return Err(invalid("index was saved with a different element type"));
+        }
+        let num_lists = read_u64(&mut reader)? as usize;
+        let nprobe = read_u64(&mut reader)? as usize;
+        let trained_len = read_u64(&mut reader)? as usize;
+        let dimension = read_u64(&mut reader)? as usize;
+        let num_rows = read_u64(&mut reader)? as usize;
+        if dimension == 0 && num_rows > 0 {
+            return Err(invalid("rows saved without a dimension"));
+        }
+
+        let num_deleted = read_u64(&mut reader)? as usize;
+        let mut deleted = HashSet::new();
+        for _ in 0..num_deleted {
+            let node_id = read_u64(&mut reader)? as NodeId;
+            if node_id >= num_rows || !deleted.insert(node_id) {
+                return Err(invalid("corrupt deleted ids"));
+            }
+        }
+
+        let num_centroids = read_u64(&mut reader)? as usize;
+        if num_centroids > 0 && dimension == 0 {
+            return Err(invalid("centroids saved without a dimension"));
+        }
+        let mut centroids = Vec::new();
+        for _ in 0..num_centroids {
+            let centroid = (0..dimension)
+                .map(|_| read_f64(&mut reader))
+                .collect::<io::Result<Vec<f64>>>()?;
+            centroids.push(centroid);
+        }
+
+        let size = T::ELEMENT_TYPE.size();
+        let len = dimension
+            .checked_mul(num_rows)
+            .and_then(|len| len.checked_mul(size))
+            .ok_or_else(|| invalid("corrupt header"))?;
+        if reader.len() != len {
+            return Err(invalid("data does not match the saved row count"));
+        }
+
+        let mut ivf = Self {
+            num_lists,
+            nprobe,
+            dimension: Some(dimension).filter(|dimension| *dimension > 0),
+            centroids,
+            lists: Vec::new(),
+            data: reader.chunks_exact(size).map(T::read_le).collect(),
+            deleted,
+            trained_len,
+        };
+        let node_ids = ivf.live_ids();
+        let points: Vec<Vec<f64>> = node_ids.iter().map(|id| ivf.point(*id)).collect();
+        ivf.lists = ivf.assign(node_ids, &points);
+        Ok(ivf)
+    }
+}
+
+fn read_u64(reader: &mut impl Read) -> io::Result<u64> {
+    let mut buf = [0; 8];
+    reader.read_exact(&mut buf)?;
+    Ok(u64::from_le_bytes(buf))
+}
+
+fn read_f64(reader: &mut impl Read) -> io::Result<f64> {
+    let mut buf = [0; 8];
+    reader.read_exact(&mut buf)?;
+    Ok(f64::from_le_bytes(buf))
+}
+
+/// Ranks by squared L2, the distance the centroids are clustered under.
+impl<T: Element> VectorIndex<T> for IVFFlat<T> {
+    fn insert(&mut self, vector: Vector<T>) -> Result<NodeId> {
+        IVFFlat::insert(self, vector)
+    }
+
+    fn delete(&mut self, node_id: NodeId) -> Result<bool> {
+        Ok(IVFFlat::delete(self, node_id))
+    }
+
+    fn search(&self, query: &Vector<T>, k: usize) -> Result<Vec<(NodeId, f64)>> {
+        self.search_ids(query, k, self.nprobe)
+    }
+
+    fn search_filtered(
+        &self,
+        query: &Vector<T>,
+        k: usize,
+        filter: &dyn Fn(NodeId) -> bool,
+    ) -> Result<Vec<(NodeId, f64)>> {
+        self.search_ids_filtered(query, k, self.nprobe, filter)
+    }
+
+    fn len(&self) -> usize {
+        IVFFlat::len(self)
+    }
+
+    fn dimension(&self) -> Option<usize> {
+        self.dimension
+    }
+
+    fn metric(&self) -> Metric {
+        Metric::L2
+    }
+
+    fn save(&self, path: &Path) -> Result<()> {
+        Ok(IVFFlat::save(self, path)?)
+    }
+
+    fn load(path: &Path) -> Result<Self> {
+        Ok(IVFFlat::load(path)?)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::error::Error;
+    use rand::SeedableRng;
+    use rand::rngs::StdRng;
+    use tempfile::tempdir;
+
+    fn setup_ivf() -> (IVFFlat, Vec<Vector>) {
+        let mut ivf = IVFFlat::new(2);
+        let vectors = vec![
+            Vector::new(vec![0.0, 0.0]),
+            Vector::new(vec![1.0, 1.0]),
+            Vector::new(vec![8.0, 8.0]),
+            Vector::new(vec![10.0, 10.0]),
+            Vector::new(vec![12.0, 12.0]),
+        ];
+
+        for vector in &vectors {
+            ivf.insert(vector.clone()).unwrap();
+        }
+        ivf.train(10, &mut StdRng::seed_from_u64(0));
+        (ivf, vectors)
+    }
+
+    #[test]
+    fn test_untrained_search_is_exact() {
+        let mut ivf = IVFFlat::new(4);
+        ivf.insert(Vector::new(vec![0.0, 0.0])).unwrap();
+        ivf.insert(Vector::new(vec![5.0, 5.0])).unwrap();
+
+        assert!(!ivf.is_trained());
+        assert_eq!(
+            ivf.search(Vector::new(vec![4.0, 4.0]), 1, 1).unwrap(),
+            vec![Vector::new(vec![5.0, 5.0])]
+        );
+    }
+
+    #[test]
+    fn test_search_returns_correct_neighbors() {
+        let (ivf, _) = setup_ivf();
+
+        let results = ivf.search(Vector::new(vec![0.5, 0.5]), 2, 1).unwrap();
+
+        assert!(ivf.is_trained());
+        assert_eq!(results.len(), 2);
+        assert!(results.contains(&Vector::new(vec![0.0, 0.0])));
+        assert!(results.contains(&Vector::new(vec![1.0, 1.0])));
+    }
+
+    #[test]
+    fn test_nprobe_limits_scanned_lists() {
+        let (ivf, vectors) = setup_ivf();
+        let query = Vector::new(vec![0.5, 0.5]);
+
+        assert!(ivf.search(query.clone(), vectors.len(), 1).unwrap().len() < vectors.len());
+        assert_eq!(
+            ivf.search(query, vectors.len(), 2).unwrap().len(),
+            vectors.len()
+        );
+    }
+
+    #[test]
+    fn test_insert_after_training_is_searchable() {
+        let (mut ivf, vectors) = setup_ivf();
+        ivf.insert(Vector::new(vec![11.0, 11.0])).unwrap();
+
+        let results = ivf.search(Vector::new(vec![11.1, 11.1]), 1, 1).unwrap();
+
+        assert_eq!(ivf.len(), vectors.len() + 1);
+        assert_eq!(results, vec![Vector::new(vec![11.0, 11.0])]);
+    }
+
+    #[test]
+    fn test_rejects_mismatched_dimension() {
+        let (mut ivf, vectors) = setup_ivf();
+        let mismatch = Vector::new(vec![1.0, 2.0, 3.0]);
+
+        assert!(matches!(
+            ivf.insert(mismatch.clone()),
+            Err(Error::DimensionMismatch {
+                expected: 2,
+                actual: 3
+            })
+        ));
+        assert!(matches!(
+            ivf.search(mismatch, 1, 1),
+            Err(Error::DimensionMismatch { .. })
+        ));
+        assert!(matches!(
+            ivf.search(Vector::new(vec![f64::NAN, 0.0]), 1, 1),
+            Err(Error::NonFiniteValue { position: 0 })
+        ));
+        assert_eq!(ivf.len(), vectors.len());
+        assert_eq!(ivf.dimension(), Some(2));
+    }
+
+    #[test]
+    fn test_insert_trains_once_enough_vectors_arrive() {
+        let mut ivf = IVFFlat::new(2);
+        for i in 0..2 * AUTO_TRAIN_POINTS_PER_LIST {
+            assert!(!ivf.is_trained());
+            let offset = if i % 2 == 0 { 0.0 } else { 100.0 };
+            ivf.insert(Vector::new(vec![offset + i as f64 * 0.01, offset]))
+                .unwrap();
+        }
+
+        assert!(ivf.is_trained());
+        assert_eq!(
+            ivf.search(Vector::new(vec![0.0, 0.0]), ivf.len(), 1)
+                .unwrap()
+                .len(),
+            AUTO_TRAIN_POINTS_PER_LIST,
+            "One probe should only scan the list near the query."
+        );
+    }
+
+    #[test]
+    fn test_deleted_vectors_survive_save_and_load() -> Result<()> {
+        let dir = tempdir()?;
+        let path = dir.path().join("index.ivf");
+        let (mut ivf, vectors) = setup_ivf();
+        assert!(ivf.delete(0));
+        assert!(!ivf.delete(0), "Deleting twice should report no change.");
+        ivf.save(&path)?;
+
+        let loaded: IVFFlat = IVFFlat::load(&path)?;
+
+        assert!(loaded.is_trained());
+        assert_eq!(loaded.len(), vectors.len() - 1);
+        for query in &vectors {
+            assert_eq!(
+                loaded.search_ids(query, 2, 1)?,
+                ivf.search_ids(query, 2, 1)?,
+                "A loaded index should keep the same lists."
+            );
+        }
+        assert_eq!(
+            loaded.search(vectors[0].clone(), 1, 2)?,
+            vec![vectors[1].clone()]
+        );
+        assert!(IVFFlat::<f32>::load(&path).is_err());
+
+        Ok(())
+    }
+}
@@ -0,0 +1,676 @@
+use super::ivf_flat::AUTO_TRAIN_POINTS_PER_LIST;
+use super::vector_index::VectorIndex;
+use crate::error::Result;
+use crate::linalg::element::{Element, ElementType};
+use crate::linalg::kmeans::{kmeans, nearest_centroid};
+use crate::linalg::metric::Metric;
+use crate::linalg::simd::squared_l2_f64;
+use crate::linalg::vector::Vector;
+use crate::numeric::ordered_float::OrderedFloat;
+use crate::quantization::product::ProductQuantizer;
+use crate::storage::node::NodeId;
+use priority_queue::DoublePriorityQueue;
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
+use std::fs;
+use std::io::{self, Read};
+use std::mem;
+use std::path::Path;
+
+#[derive(Debug, Clone, Copy)]
+pub struct IVFPQConfig {
+    pub num_lists: usize,
+    pub num_subspaces: usize,
+    pub num_centroids: usize,
+    pub iterations: usize,
+}
+
+impl Default for IVFPQConfig {
+    fn default() -> Self {
+        Self {
+            num_lists: 256,
+            num_subspaces: 8,
+            num_centroids: 256,
+            iterations: 20,
+        }
+    }
+}
+
+/// Inverted file over coarse k-means centroids whose posting lists hold PQ
+/// codes of each vector's residual to its centroid.
+///
+/// An index from `train` starts trained. One from `new` keeps whole vectors
+/// and searches them exactly until it holds `num_lists *
+/// AUTO_TRAIN_POINTS_PER_LIST` of them, then trains on them once, seeded by
+/// that count, and replaces them with codes.
+#[allow(clippy::upper_case_acronyms)]
+pub struct IVFPQ<T: Element = f64> {
+    config: IVFPQConfig,
+    nprobe: usize,
+    dimension: Option<usize>,
+    centroids: Vec<Vec<f64>>,
+    quantizer: Option<ProductQuantizer>,
+    lists: Vec<Vec<(NodeId, Vec<u8>)>>,
+    /// Vectors added before training, with their ids.
+    pending: Vec<(NodeId, Vector<T>)>,
+    next_node_id: NodeId,
+}
+
+fn residual(point: &[f64], centroid: &[f64]) -> Vec<f64> {
+    point.iter().zip(centroid).map(|(p, c)| p - c).collect()
+}
+
+/// Coarse centroids and residual codebooks for `points`. Fails before any
+/// clustering if the subspaces cannot split the dimension.
+fn fit(
+    points: &[Vec<f64>],
+    config: &IVFPQConfig,
+    rng: &mut impl Rng,
+) -> Option<(Vec<Vec<f64>>, ProductQuantizer)> {
+    if config.num_subspaces == 0 || config.num_subspaces > points.first()?.len() {
+        return None;
+    }
+    let centroids = kmeans(points, config.num_lists, config.iterations, rng);
+    if centroids.is_empty() {
+        return None;
+    }
+
+    let residuals: Vec<Vec<f64>> = points
+        .iter()
+        .map(|point| residual(point, &centroids[nearest_centroid(&centroids, point)]))
+        .collect();
+    let quantizer = ProductQuantizer::train(
+        &residuals,
+        config.num_subspaces,
+        config.num_centroids,
+        config.iterations,
+        rng,
+    )?;
+    Some((centroids, quantizer))
+}
+
+impl<T: Element> IVFPQ<T> {
+    /// An empty, untrained index that trains itself on the vectors it is
+    /// given; see the type docs.
+    pub fn new(config: IVFPQConfig) -> Self {
+        Self {
+            config,
+            nprobe: 1,
+            dimension: None,
+            centroids: Vec::new(),
+            quantizer: None,
+            lists: Vec::new(),
+            pending: Vec::new(),
+            next_node_id: 0,
+        }
+    }
+
+    /// Trains the coarse centroids and the residual codebooks on `training`.
+    /// The training vectors are not added to the index.
+    pub fn train(training: &[Vector<T>], config: IVFPQConfig, rng: &mut impl Rng) -> Option<Self> {
+        let points: Vec<Vec<f64>> = training.iter().map(|v| v.to_f64()).collect();
+        let (centroids, quantizer) = fit(&points, &config, rng)?;
+
+        Some(Self {
+            dimension: Some(quantizer.dimension()),
+            lists: vec![Vec::new(); centroids.len()],
+            centroids,
+            quantizer: Some(quantizer),
+            ..Self::new(config)
+        })
+    }
+
+    /// Lists scanned by searches made through `VectorIndex`; 1 until set.
+    pub fn nprobe(&self) -> usize {
+        self.nprobe
+    }
+
+    pub fn set_nprobe(&mut self, nprobe: usize) {
+        self.nprobe = nprobe;
+    }
+
+    /// Length every vector must have, once fixed by training or the first
+    /// add.
+    pub fn dimension(&self) -> Option<usize> {
+        self.dimension
+    }
+
+    pub fn is_trained(&self) -> bool {
+        self.quantizer.is_some()
+    }
+
+    pub fn len(&self) -> usize {
+        self.pending.len() + self.lists.iter().map(|list| list.len()).sum::<usize>()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    pub fn quantizer(&self) -> Option<&ProductQuantizer> {
+        self.quantizer.as_ref()
+    }
+
+    /// Rejects vectors whose length differs from the trained dimension, and
+    /// vectors with NaN or infinite components.
+    pub fn add(&mut self, vector: &Vector<T>) -> Result<NodeId> {
+        vector.validate(self.dimension)?;
+        self.dimension.get_or_insert(vector.len());
+        let node_id = self.next_node_id;
+        self.next_node_id += 1;
+
+        if self.is_trained() {
+            self.encode(node_id, &vector.to_f64());
+            return Ok(node_id);
+        }
+        self.pending.push((node_id, vector.clone()));
+        let len = self.pending.len();
+        if len >= self.config.num_lists * AUTO_TRAIN_POINTS_PER_LIST {
+            self.train_pending(&mut StdRng::seed_from_u64(len as u64));
+        }
+        Ok(node_id)
+    }
+
+    /// Trains on the pending vectors and encodes them. Leaves them pending,
+    /// to be searched exactly, if the config has more subspaces than
+    /// dimensions.
+    fn train_pending(&mut self, rng: &mut StdRng) {
+        let points: Vec<Vec<f64>> = self
+            .pending
+            .iter()
+            .map(|(_, vector)| vector.to_f64())
+            .collect();
+        let Some((centroids, quantizer)) = fit(&points, &self.config, rng) else {
+            return;
+        };
+        self.lists = vec![Vec::new(); centroids.len()];
+        self.centroids = centroids;
+        self.quantizer = Some(quantizer);
+        let pending = mem::take(&mut self.pending);
+        for ((node_id, _), point) in pending.into_iter().zip(&points) {
+            self.encode(node_id, point);
+        }
+    }
+
+    /// Expects a trained index.
+    fn encode(&mut self, node_id: NodeId, point: &[f64]) {
+        let quantizer = self.quantizer.as_ref().unwrap();
+        let list = nearest_centroid(&self.centroids, point);
+        let code = quantizer.encode(&residual(point, &self.centroids[list]));
+        self.lists[list].push((node_id, code));
+    }
+
+    /// Removes `node_id` from whichever list or pending vector holds it.
+    /// Returns false if it was not a live vector.
+    pub fn delete(&mut self, node_id: NodeId) -> bool {
+        if let Some(position) = self.pending.iter().position(|(id, _)| *id == node_id) {
+            self.pending.remove(position);
+            return true;
+        }
+        for list in &mut self.lists {
+            if let Some(position) = list.iter().position(|(id, _)| *id == node_id) {
+                list.remove(position);
+                return true;
+            }
+        }
+        false
+    }
+
+    /// Approximate `k` nearest ids with their estimated squared distances,
+    /// scanning the `nprobe` lists whose centroids are closest to `query`.
+    /// Before training the distances are exact.
+    pub fn search(&self, query: &Vector<T>, k: usize, nprobe: usize) -> Result<Vec<(NodeId, f64)>> {
+        self.search_filtered(query, k, nprobe, |_| true)
+    }
+
+    /// Same as `search`, keeping only ids accepted by `filter`.
+    pub fn search_filtered(
+        &self,
+        query: &Vector<T>,
+        k: usize,
+        nprobe: usize,
+        filter: impl Fn(NodeId) -> bool,
+    ) -> Result<Vec<(NodeId, f64)>> {
+        query.validate(self.dimension)?;
+        let mut nearest: DoublePriorityQueue<NodeId, OrderedFloat> = DoublePriorityQueue::new();
+        let mut push = |node_id: NodeId, dist: f64| {
+            if filter(node_id) {
+                nearest.push(node_id, OrderedFloat(dist));
+                if nearest.len() > k {
+                    nearest.pop_max();
+                }
+            }
+        };
+
+        for (node_id, vector) in &self.pending {
+            push(*node_id, vector.squared_distance(query));
+        }
+        if let Some(quantizer) = &self.quantizer {
+            let query = query.to_f64();
+            let mut probes: Vec<(usize, OrderedFloat)> = self
+                .centroids
+                .iter()
+                .map(|centroid| OrderedFloat(squared_l2_f64(centroid, &query)))
+                .enumerate()
+                .collect();
+            probes.sort_by_key(|(_, dist)| *dist);
+            for (list, _) in probes.into_iter().take(nprobe) {
+                let table = quantizer.distance_table(&residual(&query, &self.centroids[list]));
+                for (node_id, code) in &self.lists[list] {
+                    push(*node_id, table.distance(code));
+                }
+            }
+        }
+
+        Ok(nearest
+            .into_sorted_iter()
+            .map(|(node_id, dist)| (node_id, dist.0))
+            .collect())
+    }
+
+    /// Writes `[element tag][codebooks][centroids][next id][lists][config]
+    /// [nprobe][dimension][pending vectors]`, lengths and integers as `u64`
+    /// LE. An untrained index has empty codebooks and no centroids, and a
+    /// dimension of 0 means none is fixed yet.
+    pub fn save(&self, path: &Path) -> io::Result<()> {
+        let mut bytes = vec![T::ELEMENT_TYPE.tag()];
+        let quantizer = self
+            .quantizer
+            .as_ref()
+            .map(|quantizer| quantizer.to_bytes())
+            .unwrap_or_default();
+        bytes.extend_from_slice(&(quantizer.len() as u64).to_le_bytes());
+        bytes.extend_from_slice(&quantizer);
+        bytes.extend_from_slice(&(self.centroids.len() as u64).to_le_bytes());
+        for val in self.centroids.iter().flatten() {
+            bytes.extend_from_slice(&val.to_le_bytes());
+        }
+        bytes.extend_from_slice(&(self.next_node_id as u64).to_le_bytes());
+        for list in &self.lists {
+            bytes.extend_from_slice(&(list.len() as u64).to_le_bytes());
+            for (node_id, code) in list {
+                bytes.extend_from_slice(&(*node_id as u64).to_le_bytes());
+                bytes.extend_from_slice(code);
+            }
+        }
+        for val in [
+            self.config.num_lists,
+            self.config.num_subspaces,
+            self.config.num_centroids,
+            self.config.iterations,
+            self.nprobe,
+            self.dimension.unwrap_or(0),
+            self.pending.len(),
+        ] {
+            bytes.extend_from_slice(&(val as u64).to_le_bytes());
+        }
+        for (node_id, vector) in &self.pending {
+            bytes.extend_from_slice(&(*node_id as u64).to_le_bytes());
+            for val in vector.data() {
+                val.write_le(&mut bytes);
+            }
+        }
+        fs::write(path, bytes)
+    }
+
+    pub fn load(path: &Path) -> io::Result<Self> {
+        let bytes = fs::read(path)?;
+        let mut reader = bytes.as_slice();
+        let invalid = |msg: &str| io::Error::new(io::ErrorKind::InvalidData, msg.to_string());
+
+        let mut tag = [0; 1];
+        reader.read_exact(&mut tag)?;
+        if ElementType::from_tag(tag[0]) != Some(T::ELEMENT_TYPE) {
+            return Err(invalid("index was saved with a different element type"));
+        }
+
+        let quantizer_len = read_u64(&mut reader)? as usize;
+        check_fits(reader, quantizer_len, 1)?;
+        let mut quantizer = vec![0; quantizer_len];
+        reader.read_exact(&mut quantizer)?;
+        let quantizer = match quantizer_len {
+            0 => None,
+            _ => Some(
+                ProductQuantizer::from_bytes(&quantizer)
+                    .ok_or_else(|| invalid("corrupt codebooks"))?,
+            ),
+        };
+        let (trained_dimension, code_len) = quantizer.as_ref().map_or((0, 0), |quantizer| {
+            (quantizer.dimension(), quantizer.num_subspaces())
+        });
+
+        let num_lists = read_u64(&mut reader)? as usize;
+        if quantizer.is_none() && num_lists > 0 {
+            return Err(invalid("centroids saved without codebooks"));
+        }
+        let centroid_len = trained_dimension
+            .checked_mul(8)
+            .ok_or_else(|| invalid("corrupt codebooks"))?;
+        check_fits(reader, num_lists, centroid_len)?;
+        let mut centroids = Vec::with_capacity(num_lists);
+        for _ in 0..num_lists {
+            let centroid = (0..trained_dimension)
+                .map(|_| read_f64(&mut reader))
+                .collect::<io::Result<Vec<f64>>>()?;
+            centroids.push(centroid);
+        }
+
+        let next_node_id = read_u64(&mut reader)? as NodeId;
+        let mut lists = Vec::with_capacity(num_lists);
+        for _ in 0..num_lists {
+            let len = read_u64(&mut reader)? as usize;
+            check_fits(reader, len, code_len.saturating_add(8))?;
+            let mut list = Vec::with_capacity(len);
+            for _ in 0..len {
+                let node_id = read_u64(&mut reader)? as NodeId;
+                let mut code = vec![0; code_len];
+                reader.read_exact(&mut code)?;
+                list.push((node_id, code));
+            }
+            lists.push(list);
+        }
+
+        let config = IVFPQConfig {
+            num_lists: read_u64(&mut reader)? as usize,
+            num_subspaces: read_u64(&mut reader)? as usize,
+            num_centroids: read_u64(&mut reader)? as usize,
+            iterations: read_u64(&mut reader)? as usize,
+        };
+        let nprobe = read_u64(&mut reader)? as usize;
+        let dimension = Some(read_u64(&mut reader)? as usize).filter(|dimension| *dimension > 0);
+        if quantizer.is_some() && dimension != Some(trained_dimension) {
+            return Err(invalid("dimension does not match the codebooks"));
+        }
+        let num_pending = read_u64(&mut reader)? as usize;
+        let size = T::ELEMENT_TYPE.size();
+        let vector_len = dimension
+            .unwrap_or(0)
+            .checked_mul(size)
+            .ok_or_else(|| invalid("corrupt dimension"))?;
+        check_fits(reader, num_pending, vector_len.saturating_add(8))?;
+        let mut pending = Vec::with_capacity(num_pending);
+        for _ in 0..num_pending {
+            let node_id = read_u64(&mut reader)? as NodeId;
+            let (data, rest) = reader.split_at(vector_len);
+            pending.push((
+                node_id,
+                Vector::new(data.chunks_exact(size).map(T::read_le).collect()),
+            ));
+            reader = rest;
+        }
+        if !reader.is_empty() {
+            return Err(invalid("trailing bytes after index"));
+        }
+
+        Ok(Self {
+            config,
+            nprobe,
+            dimension,
+            centroids,
+            quantizer,
+            lists,
+            pending,
+            next_node_id,
+        })
+    }
+}
+
+/// Fails unless `count` items of `size` bytes fit in what is left of the
+/// file, so a corrupt length never sizes an allocation.
+fn check_fits(reader: &[u8], count: usize, size: usize) -> io::Result<()> {
+    if count.checked_mul(size).is_none_or(|len| len > reader.len()) {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            "length does not fit in the file",
+        ));
+    }
+    Ok(())
+}
+
+fn read_u64(reader: &mut impl Read) -> io::Result<u64> {
+    let mut buf = [0; 8];
+    reader.read_exact(&mut buf)?;
+    Ok(u64::from_le_bytes(buf))
+}
+
+fn read_f64(reader: &mut impl Read) -> io::Result<f64> {
+    let mut buf = [0; 8];
+    reader.read_exact(&mut buf)?;
+    Ok(f64::from_le_bytes(buf))
+}
+
+/// Ranks by squared L2. Once trained, distances are PQ estimates, so a
+/// stored vector need not come first in a search for itself.
+impl<T: Element> VectorIndex<T> for IVFPQ<T> {
+    fn insert(&mut self, vector: Vector<T>) -> Result<NodeId> {
+        self.add(&vector)
+    }
+
+    fn delete(&mut self, node_id: NodeId) -> Result<bool> {
+        Ok(IVFPQ::delete(self, node_id))
+    }
+
+    fn search(&self, query: &Vector<T>, k: usize) -> Result<Vec<(NodeId, f64)>> {
+        IVFPQ::search(self, query, k, self.nprobe)
+    }
+
+    fn search_filtered(
+        &self,
+        query: &Vector<T>,
+        k: usize,
+        filter: &dyn Fn(NodeId) -> bool,
+    ) -> Result<Vec<(NodeId, f64)>> {
+        IVFPQ::search_filtered(self, query, k, self.nprobe, filter)
+    }
+
+    fn len(&self) -> usize {
+        IVFPQ::len(self)
+    }
+
+    fn dimension(&self) -> Option<usize> {
+        self.dimension
+    }
+
+    fn metric(&self) -> Metric {
+        Metric::L2
+    }
+
+    fn save(&self, path: &Path) -> Result<()> {
+        Ok(IVFPQ::save(self, path)?)
+    }
+
+    fn load(path: &Path) -> Result<Self> {
+        Ok(IVFPQ::load(path)?)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::error::Error;
+    use rand::SeedableRng;
+    use rand::rngs::StdRng;
+    use tempfile::tempdir;
+
+    fn random_vectors(rng: &mut StdRng, count: usize) -> Vec<Vector> {
+        (0..count)
+            .map(|_| Vector::new((0..8).map(|_| rng.gen_range(-1.0..1.0)).collect()))
+            .collect()
+    }
+
+    fn config() -> IVFPQConfig {
+        IVFPQConfig {
+            num_lists: 8,
+            num_subspaces: 4,
+            num_centroids: 64,
+            iterations: 10,
+        }
+    }
+
+    fn setup_index() -> (IVFPQ, Vec<Vector>) {
+        let mut rng = StdRng::seed_from_u64(42);
+        let vectors = random_vectors(&mut rng, 500);
+        let mut index = IVFPQ::train(&vectors, config(), &mut rng).unwrap();
+        for vector in &vectors {
+            index.add(vector).unwrap();
+        }
+        (index, vectors)
+    }
+
+    #[test]
+    fn test_train_on_empty_set() {
+        let mut rng = StdRng::seed_from_u64(0);
+        assert!(IVFPQ::<f64>::train(&[], config(), &mut rng).is_none());
+    }
+
+    #[test]
+    fn test_search_finds_indexed_vector() {
+        let (index, vectors) = setup_index();
+
+        assert_eq!(index.len(), vectors.len());
+        for (node_id, vector) in vectors.iter().enumerate().take(20) {
+            let results = index.search(vector, 10, 8).unwrap();
+            assert_eq!(results.len(), 10);
+            assert!(
+                results.iter().any(|(id, _)| *id == node_id),
+                "Exhaustive probing should find the query itself in the top 10."
+            );
+            assert!(results.windows(2).all(|pair| pair[0].1 <= pair[1].1));
+        }
+    }
+
+    #[test]
+    fn test_rejects_invalid_vectors() {
+        let (mut index, vectors) = setup_index();
+
+        assert!(matches!(
+            index.add(&Vector::new(vec![1.0; 3])),
+            Err(Error::DimensionMismatch {
+                expected: 8,
+                actual: 3
+            })
+        ));
+        let mut nan = vec![0.0; 8];
+        nan[5] = f64::NAN;
+        assert!(matches!(
+            index.add(&Vector::new(nan)),
+            Err(Error::NonFiniteValue { position: 5 })
+        ));
+        assert!(matches!(
+            index.search(&Vector::new(vec![1.0; 9]), 5, 4),
+            Err(Error::DimensionMismatch { .. })
+        ));
+        assert_eq!(index.len(), vectors.len());
+    }
+
+    #[test]
+    fn test_search_with_fewer_probes_scans_less() {
+        let (index, vectors) = setup_index();
+
+        let results = index.search(&vectors[0], vectors.len(), 1).unwrap();
+
+        assert!(!results.is_empty());
+        assert!(results.len() < vectors.len());
+    }
+
+    #[test]
+    fn test_save_and_load_round_trip() -> io::Result<()> {
+        let dir = tempdir()?;
+        let path = dir.path().join("index.ivfpq");
+        let (index, vectors) = setup_index();
+
+        index.save(&path)?;
+        let loaded: IVFPQ = IVFPQ::load(&path)?;
+
+        assert_eq!(loaded.quantizer(), index.quantizer());
+        assert_eq!(loaded.len(), index.len());
+        assert_eq!(
+            loaded.search(&vectors[3], 5, 4)?,
+            index.search(&vectors[3], 5, 4)?
+        );
+
+        assert!(IVFPQ::<f32>::load(&path).is_err());
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_load_rejects_lengths_past_end_of_file() -> io::Result<()> {
+        let dir = tempdir()?;
+        let path = dir.path().join("index.ivfpq");
+        let (index, _) = setup_index();
+        index.save(&path)?;
+        let bytes = fs::read(&path)?;
+
+        let mut huge_quantizer = bytes.clone();
+        huge_quantizer[1..9].copy_from_slice(&u64::MAX.to_le_bytes());
+        let mut truncated = bytes.clone();
+        truncated.truncate(bytes.len() / 2);
+
+        // An untrained index ends with `[dimension][pending count][id]
+        // [vector]`; a dimension that overflows the record size is corrupt.
+        let mut untrained: IVFPQ = IVFPQ::new(config());
+        let vector = Vector::new(vec![1.0; 4]);
+        untrained.add(&vector)?;
+        untrained.save(&path)?;
+        let mut huge_dimension = fs::read(&path)?;
+        let at = huge_dimension.len() - 24 - 4 * 8;
+        huge_dimension[at..at + 8].copy_from_slice(&u64::MAX.to_le_bytes());
+
+        for corrupt in [huge_quantizer, truncated, huge_dimension] {
+            fs::write(&path, corrupt)?;
+            let error = IVFPQ::<f64>::load(&path).err().unwrap();
+            assert!(matches!(
+                error.kind(),
+                io::ErrorKind::InvalidData | io::ErrorKind::UnexpectedEof
+            ));
+        }
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_new_index_trains_once_enough_vectors_arrive() -> io::Result<()> {
+        let dir = tempdir()?;
+        let path = dir.path().join("index.ivfpq");
+        let mut rng = StdRng::seed_from_u64(42);
+        let threshold = config().num_lists * AUTO_TRAIN_POINTS_PER_LIST;
+        let vectors = random_vectors(&mut rng, threshold + 1);
+        let mut index: IVFPQ = IVFPQ::new(config());
+        for vector in &vectors[..threshold - 1] {
+            index.add(vector)?;
+        }
+
+        assert!(!index.is_trained());
+        assert_eq!(index.dimension(), Some(8));
+        assert_eq!(
+            index.search(&vectors[3], 1, 1)?,
+            vec![(3, 0.0)],
+            "An untrained index should search exactly."
+        );
+        assert!(index.delete(3));
+        assert!(!index.delete(3));
+        index.save(&path)?;
+        let loaded: IVFPQ = IVFPQ::load(&path)?;
+        assert_eq!(loaded.len(), threshold - 2);
+        assert_eq!(
+            loaded.search(&vectors[4], 5, 1)?,
+            index.search(&vectors[4], 5, 1)?
+        );
+
+        index.add(&vectors[threshold - 1])?;
+        assert!(
+            !index.is_trained(),
+            "Deleted vectors should not count towards training."
+        );
+        index.add(&vectors[threshold])?;
+
+        assert!(index.is_trained());
+        assert_eq!(index.len(), threshold);
+        let results = index.search(&vectors[5], 10, config().num_lists)?;
+        assert!(results.iter().any(|(id, _)| *id == 5));
+        assert!(results.iter().all(|(id, _)| *id != 3));
+
+        Ok(())
+    }
+}
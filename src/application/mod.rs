@@ -0,0 +1,12 @@
+pub mod bm25;
+pub mod collection;
+pub mod disk_ann;
+pub mod flat;
+pub mod hnsw;
+pub mod hybrid;
+pub mod index;
+pub mod ivf_flat;
+pub mod ivf_pq;
+pub mod multi_vector;
+pub mod sparse;
+pub mod vector_index;
@@ -0,0 +1,226 @@
+use super::hnsw::{BuildConfig, HNSW};
+use crate::error::Result;
+use crate::linalg::element::Element;
+use crate::linalg::vector::Vector;
+use crate::storage::node::NodeId;
+use std::collections::HashSet;
+
+/// Id of a multi-vector document, distinct from the ids of its vectors.
+pub type DocId = usize;
+
+#[derive(Debug, Clone, Copy)]
+pub struct MultiVectorConfig {
+    pub build: BuildConfig,
+    pub ef: usize,
+    /// Nearest token vectors fetched per query vector; their documents are
+    /// the candidates scored exactly.
+    pub candidates: usize,
+}
+
+impl Default for MultiVectorConfig {
+    fn default() -> Self {
+        Self {
+            build: BuildConfig::default(),
+            ef: 100,
+            candidates: 32,
+        }
+    }
+}
+
+/// Late-interaction (ColBERT-style) index: each document is a set of token
+/// vectors, and a query set scores a document by MaxSim, the sum over query
+/// vectors of their best dot product with any of the document's vectors.
+/// All token vectors share one `HNSW`, which only generates candidates;
+/// as with ColBERT, embeddings are expected to be normalized so that the
+/// graph's L2 neighbors are also the best dot products.
+pub struct MultiVectorIndex<T: Element = f64> {
+    hnsw: HNSW<T>,
+    config: MultiVectorConfig,
+    /// Owning document of every token vector, indexed by node id.
+    owners: Vec<DocId>,
+    /// Node ids of every document's vectors, indexed by document id.
+    documents: Vec<Vec<NodeId>>,
+}
+
+impl<T: Element> MultiVectorIndex<T> {
+    pub fn new(config: MultiVectorConfig) -> Self {
+        Self {
+            hnsw: HNSW::with_config(config.build.index),
+            config,
+            owners: Vec::new(),
+            documents: Vec::new(),
+        }
+    }
+
+    /// Number of documents.
+    pub fn len(&self) -> usize {
+        self.documents.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.documents.is_empty()
+    }
+
+    /// Number of token vectors across all documents.
+    pub fn num_vectors(&self) -> usize {
+        self.owners.len()
+    }
+
+    /// Adds a document. Every vector is validated before any is inserted, so
+    /// a rejected document leaves the index unchanged.
+    pub fn insert(&mut self, vectors: Vec<Vector<T>>) -> Result<DocId> {
+        let dimension = self.hnsw.dimension().or(vectors.first().map(Vector::len));
+        for vector in &vectors {
+            vector.validate(dimension)?;
+        }
+        let doc_id = self.documents.len();
+        let build = self.config.build;
+        let mut node_ids = Vec::with_capacity(vectors.len());
+        for vector in vectors {
+            let node_id = self.hnsw.insert(
+                vector,
+                build.m,
+                build.m_max,
+                build.ef_construction,
+                build.ml,
+            )?;
+            debug_assert_eq!(node_id, self.owners.len());
+            self.owners.push(doc_id);
+            node_ids.push(node_id);
+        }
+        self.documents.push(node_ids);
+        Ok(doc_id)
+    }
+
+    /// Exact MaxSim score of `doc_id` for `query`, or `None` if there is no
+    /// such document. Rejects query vectors of another dimension.
+    pub fn max_sim(&self, query: &[Vector<T>], doc_id: DocId) -> Result<Option<f64>> {
+        for query_vector in query {
+            query_vector.validate(self.hnsw.dimension())?;
+        }
+        let Some(node_ids) = self.documents.get(doc_id) else {
+            return Ok(None);
+        };
+        Ok(Some(self.score(query, node_ids)))
+    }
+
+    fn score(&self, query: &[Vector<T>], node_ids: &[NodeId]) -> f64 {
+        let vectors: Vec<Vector<T>> = node_ids
+            .iter()
+            .filter_map(|node_id| self.hnsw.get(node_id))
+            .collect();
+        query
+            .iter()
+            .map(|query_vector| {
+                vectors
+                    .iter()
+                    .map(|vector| query_vector.dot(vector))
+                    .fold(f64::NEG_INFINITY, f64::max)
+            })
+            .filter(|best| best.is_finite())
+            .sum()
+    }
+
+    /// Collects the documents owning the `config.candidates` nearest token
+    /// vectors of each query vector, scores them exactly with MaxSim and
+    /// returns the best `k` document ids with their scores.
+    pub fn search(&self, query: &[Vector<T>], k: usize) -> Result<Vec<(DocId, f64)>> {
+        let mut candidates: HashSet<DocId> = HashSet::new();
+        for query_vector in query {
+            let node_ids =
+                self.hnsw
+                    .search_nodes(query_vector, self.config.candidates, self.config.ef)?;
+            candidates.extend(node_ids.into_iter().map(|node_id| self.owners[node_id]));
+        }
+
+        let mut scored: Vec<(DocId, f64)> = candidates
+            .into_iter()
+            .map(|doc_id| (doc_id, self.score(query, &self.documents[doc_id])))
+            .collect();
+        scored.sort_by(|a, b| b.1.total_cmp(&a.1).then(a.0.cmp(&b.0)));
+        scored.truncate(k);
+        Ok(scored)
+    }
+}
+
+impl<T: Element> Default for MultiVectorIndex<T> {
+    fn default() -> Self {
+        Self::new(MultiVectorConfig::default())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rand::rngs::StdRng;
+    use rand::{Rng, SeedableRng};
+
+    fn unit(rng: &mut StdRng, dimension: usize) -> Vector {
+        let data: Vec<f64> = (0..dimension).map(|_| rng.r#gen::<f64>() - 0.5).collect();
+        let norm = data.iter().map(|x| x * x).sum::<f64>().sqrt();
+        Vector::new(data.into_iter().map(|x| x / norm).collect())
+    }
+
+    #[test]
+    fn test_max_sim_sums_best_matches() -> Result<()> {
+        let mut index = MultiVectorIndex::default();
+        let doc = index.insert(vec![
+            Vector::new(vec![1.0, 0.0]),
+            Vector::new(vec![0.0, 1.0]),
+        ])?;
+        let query = vec![Vector::new(vec![0.6, 0.8]), Vector::new(vec![1.0, 0.0])];
+
+        assert_eq!(index.max_sim(&query, doc)?, Some(0.8 + 1.0));
+        assert_eq!(index.max_sim(&query, doc + 1)?, None);
+        assert!(
+            index.max_sim(&[Vector::new(vec![1.0])], doc).is_err(),
+            "Queries of another dimension should be rejected."
+        );
+
+        let mixed = vec![Vector::new(vec![1.0, 1.0]), Vector::new(vec![1.0])];
+        assert!(index.insert(mixed).is_err());
+        assert_eq!(
+            index.num_vectors(),
+            2,
+            "A rejected document should not leave any of its vectors behind."
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_multi_vector_search_matches_exhaustive_max_sim() -> Result<()> {
+        let mut rng = StdRng::seed_from_u64(3);
+        let mut index = MultiVectorIndex::default();
+        for _ in 0..60 {
+            let tokens = rng.gen_range(3..8);
+            index.insert((0..tokens).map(|_| unit(&mut rng, 8)).collect())?;
+        }
+        assert_eq!(index.len(), 60);
+        assert!(index.num_vectors() >= 180);
+
+        // A subset of a document's own vectors should retrieve it first.
+        let target = 17;
+        let query: Vec<Vector> = index.documents[target]
+            .iter()
+            .take(3)
+            .map(|node_id| index.hnsw.get(node_id).unwrap())
+            .collect();
+
+        let results = index.search(&query, 5)?;
+        let mut exhaustive: Vec<(DocId, f64)> = (0..index.len())
+            .map(|doc_id| Ok((doc_id, index.max_sim(&query, doc_id)?.unwrap())))
+            .collect::<Result<_>>()?;
+        exhaustive.sort_by(|a, b| b.1.total_cmp(&a.1).then(a.0.cmp(&b.0)));
+
+        assert_eq!(results[0].0, target);
+        assert_eq!(results, exhaustive[..5]);
+        assert!(
+            MultiVectorIndex::<f64>::default()
+                .search(&query, 5)?
+                .is_empty()
+        );
+
+        Ok(())
+    }
+}
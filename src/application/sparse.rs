@@ -0,0 +1,334 @@
+use crate::linalg::sparse::SparseVector;
+use crate::numeric::ordered_float::OrderedFloat;
+use crate::storage::node::NodeId;
+use crate::storage::{self, Storage};
+use priority_queue::DoublePriorityQueue;
+use std::collections::HashMap;
+use std::io;
+
+/// Postings of one dimension, sorted by node id, plus the range of values
+/// they hold so a query weight can be turned into a score upper bound.
+#[derive(Debug, Default)]
+struct PostingList {
+    postings: Vec<(NodeId, f64)>,
+    max_value: f64,
+    min_value: f64,
+}
+
+impl PostingList {
+    fn upper_bound(&self, query_weight: f64) -> f64 {
+        (query_weight * self.max_value)
+            .max(query_weight * self.min_value)
+            .max(0.0)
+    }
+}
+
+/// Inverted-list index over `SparseVector`s scored by dot product, with
+/// MaxScore pruning at query time. Each vector is stored in a `Storage`
+/// under `{prefix}{node_id}`, so writes go through the WAL and SSTables;
+/// the posting lists are rebuilt from those records on open. `{prefix}next`
+/// holds the next id to assign, so ids of deleted vectors are not reused.
+pub struct SparseIndex {
+    prefix: Vec<u8>,
+    lists: HashMap<u32, PostingList>,
+    vectors: HashMap<NodeId, SparseVector>,
+    next_node_id: NodeId,
+}
+
+/// Read position of a query term in its posting list during `search`.
+struct Cursor<'a> {
+    postings: &'a [(NodeId, f64)],
+    position: usize,
+    weight: f64,
+    upper_bound: f64,
+}
+
+impl Cursor<'_> {
+    fn current(&self) -> Option<NodeId> {
+        self.postings
+            .get(self.position)
+            .map(|(node_id, _)| *node_id)
+    }
+
+    /// Moves to the first posting at or after `node_id` and returns its
+    /// contribution if it is exactly `node_id`.
+    fn seek(&mut self, node_id: NodeId) -> Option<f64> {
+        let rest = &self.postings[self.position..];
+        self.position += rest.partition_point(|(id, _)| *id < node_id);
+        match self.postings.get(self.position) {
+            Some((id, value)) if *id == node_id => Some(self.weight * value),
+            _ => None,
+        }
+    }
+}
+
+impl SparseIndex {
+    pub fn open(storage: &Storage, prefix: &[u8]) -> io::Result<Self> {
+        let mut index = Self {
+            prefix: prefix.to_vec(),
+            lists: HashMap::new(),
+            vectors: HashMap::new(),
+            next_node_id: 0,
+        };
+        let next_key = index.next_key();
+        for entry in storage.scan_prefix(prefix)? {
+            if entry.key == next_key {
+                let next_node_id = entry
+                    .value
+                    .as_deref()
+                    .and_then(|value| value.try_into().ok())
+                    .map(|value| u64::from_le_bytes(value) as NodeId)
+                    .ok_or_else(|| {
+                        io::Error::new(io::ErrorKind::InvalidData, "Corrupt next sparse id")
+                    })?;
+                index.next_node_id = index.next_node_id.max(next_node_id);
+                continue;
+            }
+            let node_id = entry.key[prefix.len()..]
+                .try_into()
+                .ok()
+                .map(|id| u64::from_be_bytes(id) as NodeId);
+            let vector = entry.value.as_deref().and_then(SparseVector::from_bytes);
+            let (Some(node_id), Some(vector)) = (node_id, vector) else {
+                return Err(io::Error::new(
+                    io::ErrorKind::InvalidData,
+                    "Corrupt stored sparse vector",
+                ));
+            };
+            index.add(node_id, vector);
+        }
+        Ok(index)
+    }
+
+    pub fn len(&self) -> usize {
+        self.vectors.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.vectors.is_empty()
+    }
+
+    pub fn vector(&self, node_id: NodeId) -> Option<&SparseVector> {
+        self.vectors.get(&node_id)
+    }
+
+    fn key(&self, node_id: NodeId) -> Vec<u8> {
+        [&self.prefix[..], &(node_id as u64).to_be_bytes()].concat()
+    }
+
+    fn next_key(&self) -> Vec<u8> {
+        [&self.prefix[..], b"next"].concat()
+    }
+
+    /// Adds to the in-memory lists. Ids only grow, so appending keeps every
+    /// posting list sorted.
+    fn add(&mut self, node_id: NodeId, vector: SparseVector) {
+        for (index, value) in vector.iter() {
+            let list = self.lists.entry(index).or_default();
+            if list.postings.is_empty() {
+                list.max_value = value;
+                list.min_value = value;
+            }
+            list.max_value = list.max_value.max(value);
+            list.min_value = list.min_value.min(value);
+            list.postings.push((node_id, value));
+        }
+        self.vectors.insert(node_id, vector);
+        self.next_node_id = self.next_node_id.max(node_id + 1);
+    }
+
+    pub fn insert(&mut self, storage: &mut Storage, vector: SparseVector) -> io::Result<NodeId> {
+        let node_id = self.next_node_id;
+        let timestamp = storage::timestamp();
+        storage.set(&self.key(node_id), &vector.to_bytes(), timestamp)?;
+        let next_node_id = (node_id as u64 + 1).to_le_bytes();
+        storage.set(&self.next_key(), &next_node_id, timestamp)?;
+        self.add(node_id, vector);
+        Ok(node_id)
+    }
+
+    /// Removes `node_id`. Value ranges are left as they are; they stay valid
+    /// upper bounds. Returns `false` if the id is unknown.
+    pub fn delete(&mut self, storage: &mut Storage, node_id: NodeId) -> io::Result<bool> {
+        let Some(vector) = self.vectors.remove(&node_id) else {
+            return Ok(false);
+        };
+        storage.delete(&self.key(node_id), storage::timestamp())?;
+        for index in vector.indices() {
+            if let Some(list) = self.lists.get_mut(index) {
+                list.postings.retain(|(id, _)| *id != node_id);
+            }
+        }
+        Ok(true)
+    }
+
+    /// Returns the `k` highest dot products with `query`, best first.
+    ///
+    /// Uses MaxScore: query terms are ordered by their score upper bound, and
+    /// once the `k`-th best score exceeds the combined bound of the weakest
+    /// terms, those terms no longer drive candidate selection and are only
+    /// probed for documents that can still enter the top `k`.
+    pub fn search(&self, query: &SparseVector, k: usize) -> Vec<(NodeId, f64)> {
+        if k == 0 {
+            return Vec::new();
+        }
+        let mut cursors: Vec<Cursor> = query
+            .iter()
+            .filter_map(|(index, weight)| {
+                let list = self.lists.get(&index)?;
+                Some(Cursor {
+                    postings: &list.postings,
+                    position: 0,
+                    weight,
+                    upper_bound: list.upper_bound(weight),
+                })
+            })
+            .collect();
+        cursors.sort_by(|a, b| a.upper_bound.total_cmp(&b.upper_bound));
+        // prefix_bounds[i] is the total bound of cursors[..i].
+        let prefix_bounds: Vec<f64> = std::iter::once(0.0)
+            .chain(cursors.iter().scan(0.0, |sum, cursor| {
+                *sum += cursor.upper_bound;
+                Some(*sum)
+            }))
+            .collect();
+
+        let mut top: DoublePriorityQueue<NodeId, OrderedFloat> = DoublePriorityQueue::new();
+        let mut threshold = f64::NEG_INFINITY;
+        let mut first_essential = 0;
+
+        loop {
+            while first_essential < cursors.len() && prefix_bounds[first_essential + 1] <= threshold
+            {
+                first_essential += 1;
+            }
+            let Some(node_id) = cursors[first_essential..]
+                .iter()
+                .filter_map(Cursor::current)
+                .min()
+            else {
+                break;
+            };
+
+            let mut score = 0.0;
+            for cursor in &mut cursors[first_essential..] {
+                score += cursor.seek(node_id).unwrap_or(0.0);
+            }
+            for i in (0..first_essential).rev() {
+                if score + prefix_bounds[i + 1] <= threshold {
+                    break;
+                }
+                score += cursors[i].seek(node_id).unwrap_or(0.0);
+            }
+            for cursor in &mut cursors[first_essential..] {
+                if cursor.current() == Some(node_id) {
+                    cursor.position += 1;
+                }
+            }
+
+            if top.len() < k || score > threshold {
+                top.push(node_id, OrderedFloat(score));
+                if top.len() > k {
+                    top.pop_min();
+                }
+                if top.len() == k {
+                    threshold = top.peek_min().unwrap().1.0;
+                }
+            }
+        }
+
+        let mut results: Vec<(NodeId, f64)> = top
+            .into_iter()
+            .map(|(node_id, score)| (node_id, score.0))
+            .collect();
+        results.sort_by(|a, b| b.1.total_cmp(&a.1).then(a.0.cmp(&b.0)));
+        results
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rand::rngs::StdRng;
+    use rand::{Rng, SeedableRng};
+    use tempfile::tempdir;
+
+    fn random_sparse(rng: &mut StdRng, nnz: usize) -> SparseVector {
+        SparseVector::from_pairs((0..nnz).map(|_| (rng.gen_range(0..300), rng.r#gen::<f64>())))
+    }
+
+    #[test]
+    fn test_sparse_search_matches_brute_force() -> io::Result<()> {
+        let dir = tempdir()?;
+        let mut storage = Storage::open(dir.path())?;
+        let mut index = SparseIndex::open(&storage, b"sparse/")?;
+        let mut rng = StdRng::seed_from_u64(11);
+        let vectors: Vec<SparseVector> = (0..500).map(|_| random_sparse(&mut rng, 20)).collect();
+        for vector in &vectors {
+            index.insert(&mut storage, vector.clone())?;
+        }
+
+        for _ in 0..10 {
+            let query = random_sparse(&mut rng, 8);
+            let mut expected: Vec<(NodeId, f64)> = vectors
+                .iter()
+                .enumerate()
+                .map(|(node_id, vector)| (node_id, vector.dot(&query)))
+                .filter(|(_, score)| *score != 0.0)
+                .collect();
+            expected.sort_by(|a, b| b.1.total_cmp(&a.1).then(a.0.cmp(&b.0)));
+            expected.truncate(10);
+
+            let found = index.search(&query, 10);
+
+            assert_eq!(found.len(), expected.len());
+            for ((found_id, found_score), (expected_id, expected_score)) in
+                found.iter().zip(&expected)
+            {
+                assert_eq!(found_id, expected_id);
+                assert!((found_score - expected_score).abs() < 1e-9);
+            }
+        }
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_sparse_index_persists_through_wal_and_sstables() -> io::Result<()> {
+        let dir = tempdir()?;
+        let first = SparseVector::new(vec![1, 2], vec![1.0, 1.0]);
+        let second = SparseVector::new(vec![2, 3], vec![2.0, 1.0]);
+        let third = SparseVector::new(vec![3], vec![5.0]);
+        {
+            let mut storage = Storage::open(dir.path())?;
+            let mut index = SparseIndex::open(&storage, b"sparse/")?;
+            index.insert(&mut storage, first.clone())?;
+            index.insert(&mut storage, second.clone())?;
+            storage.checkpoint()?;
+            index.insert(&mut storage, third)?;
+            let fourth = SparseVector::new(vec![1], vec![4.0]);
+            index.insert(&mut storage, fourth)?;
+            assert!(index.delete(&mut storage, 0)?);
+            assert!(index.delete(&mut storage, 3)?);
+            assert!(!index.delete(&mut storage, 9)?);
+            storage.flush()?;
+        }
+
+        let storage = Storage::open(dir.path())?;
+        let mut index = SparseIndex::open(&storage, b"sparse/")?;
+
+        assert_eq!(index.len(), 2);
+        assert_eq!(index.vector(1), Some(&second));
+        let query = SparseVector::new(vec![2, 3], vec![1.0, 1.0]);
+        assert_eq!(index.search(&query, 5), vec![(2, 5.0), (1, 3.0)]);
+
+        let mut storage = storage;
+        assert_eq!(
+            index.insert(&mut storage, first)?,
+            4,
+            "Ids should not be reused, even the last one deleted."
+        );
+
+        Ok(())
+    }
+}
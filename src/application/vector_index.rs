@@ -0,0 +1,281 @@
+use crate::error::Result;
+use crate::linalg::element::Element;
+use crate::linalg::metric::Metric;
+use crate::linalg::vector::Vector;
+use crate::storage::node::NodeId;
+use std::path::Path;
+
+/// Operations shared by every index, so callers can pick the index type at
+/// runtime through `Box<dyn VectorIndex<T>>`.
+///
+/// Ids are assigned in insertion order starting at zero and are never
+/// reused after `delete`. The first insert fixes the dimension; vectors of
+/// another length or with NaN or infinite components are rejected with the
+/// matching `Error`. Searches return up to `k` live `(id, distance)` pairs,
+/// closest first, with distances measured by `metric`; `IVFPQ` estimates
+/// them from its codes. Indexes built once, like `DiskANN`, fail `insert`
+/// and `delete` with `io::ErrorKind::Unsupported`.
+pub trait VectorIndex<T: Element = f64>: Send + Sync {
+    fn insert(&mut self, vector: Vector<T>) -> Result<NodeId>;
+
+    /// Removes `node_id` from search results. Returns `false` if it was not
+    /// a live vector.
+    fn delete(&mut self, node_id: NodeId) -> Result<bool>;
+
+    fn search(&self, query: &Vector<T>, k: usize) -> Result<Vec<(NodeId, f64)>>;
+
+    /// Same as `search`, keeping only ids for which `filter` returns `true`.
+    fn search_filtered(
+        &self,
+        query: &Vector<T>,
+        k: usize,
+        filter: &dyn Fn(NodeId) -> bool,
+    ) -> Result<Vec<(NodeId, f64)>>;
+
+    /// Number of live vectors.
+    fn len(&self) -> usize;
+
+    fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    fn dimension(&self) -> Option<usize>;
+
+    fn metric(&self) -> Metric;
+
+    fn save(&self, path: &Path) -> Result<()>;
+
+    fn load(path: &Path) -> Result<Self>
+    where
+        Self: Sized;
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::application::disk_ann::{DiskANN, DiskANNConfig};
+    use crate::application::flat::FlatIndex;
+    use crate::application::hnsw::{HNSW, HNSWConfig};
+    use crate::application::ivf_flat::IVFFlat;
+    use crate::application::ivf_pq::{IVFPQ, IVFPQConfig};
+    use crate::error::Error;
+    use rand::rngs::StdRng;
+    use rand::{Rng, SeedableRng};
+    use std::io;
+    use tempfile::tempdir;
+
+    const DIMENSION: usize = 8;
+
+    fn random_vectors(count: usize) -> Vec<Vector> {
+        let mut rng = StdRng::seed_from_u64(7);
+        (0..count)
+            .map(|_| Vector::new((0..DIMENSION).map(|_| rng.r#gen::<f64>()).collect()))
+            .collect()
+    }
+
+    /// Behavior every `VectorIndex` must share. `index` must be empty. A
+    /// `lossy` index ranks by estimated distances, so a vector only has to
+    /// be among its own results rather than first at distance 0.
+    fn check_conformance<I: VectorIndex>(mut index: I, lossy: bool) -> Result<()> {
+        let vectors = random_vectors(200);
+        let query = &vectors[0];
+
+        assert!(index.is_empty());
+        assert_eq!(index.dimension(), None);
+        assert!(
+            index.search(query, 5)?.is_empty(),
+            "An empty index should return no results."
+        );
+
+        for (i, vector) in vectors.iter().enumerate() {
+            assert_eq!(index.insert(vector.clone())?, i);
+        }
+        assert_eq!(index.len(), vectors.len());
+        assert_eq!(index.dimension(), Some(DIMENSION));
+        assert!(matches!(
+            index.insert(Vector::new(vec![1.0; DIMENSION + 1])),
+            Err(Error::DimensionMismatch { .. })
+        ));
+        let mut nan = vec![0.0; DIMENSION];
+        nan[3] = f64::NAN;
+        assert!(matches!(
+            index.insert(Vector::new(nan)),
+            Err(Error::NonFiniteValue { position: 3 })
+        ));
+        assert_eq!(
+            index.len(),
+            vectors.len(),
+            "Rejected vectors should not be stored."
+        );
+        check_searches(&index, &vectors, lossy)?;
+
+        assert!(index.delete(0)?);
+        assert!(!index.delete(0)?, "Deleting twice should report no change.");
+        assert!(!index.delete(vectors.len())?);
+        assert_eq!(index.len(), vectors.len() - 1);
+        let results = index.search(query, 10)?;
+        assert_eq!(results.len(), 10);
+        assert!(
+            results.iter().all(|(node_id, _)| *node_id != 0),
+            "Deleted vectors should not be returned."
+        );
+        assert_eq!(index.insert(query.clone())?, vectors.len());
+
+        let even = |node_id: NodeId| node_id.is_multiple_of(2);
+        let results = index.search_filtered(query, 10, &even)?;
+        assert_eq!(results.len(), 10);
+        assert!(results.iter().all(|(node_id, _)| even(*node_id)));
+        if lossy {
+            assert!(results.iter().any(|(node_id, _)| *node_id == vectors.len()));
+        } else {
+            assert_eq!(results[0].0, vectors.len());
+        }
+        assert!(index.search_filtered(query, 10, &|_| false)?.is_empty());
+
+        check_save_and_load(&index, &vectors)
+    }
+
+    /// Behavior of an index built over `vectors` that cannot change.
+    fn check_read_only_conformance<I: VectorIndex>(mut index: I, vectors: &[Vector]) -> Result<()> {
+        assert_eq!(index.len(), vectors.len());
+        assert_eq!(index.dimension(), Some(DIMENSION));
+        check_searches(&index, vectors, false)?;
+
+        for result in [
+            index.insert(vectors[0].clone()).map(|_| ()),
+            index.delete(0).map(|_| ()),
+        ] {
+            assert!(matches!(
+                result,
+                Err(Error::Io(error)) if error.kind() == io::ErrorKind::Unsupported
+            ));
+        }
+        assert_eq!(index.len(), vectors.len());
+
+        let even = |node_id: NodeId| node_id.is_multiple_of(2);
+        let results = index.search_filtered(&vectors[1], 10, &even)?;
+        assert_eq!(results.len(), 10);
+        assert!(results.iter().all(|(node_id, _)| even(*node_id)));
+        assert!(
+            index
+                .search_filtered(&vectors[1], 10, &|_| false)?
+                .is_empty()
+        );
+
+        check_save_and_load(&index, vectors)
+    }
+
+    /// Every tenth vector finds itself, and invalid queries are rejected.
+    fn check_searches<I: VectorIndex>(index: &I, vectors: &[Vector], lossy: bool) -> Result<()> {
+        for (i, vector) in vectors.iter().enumerate().step_by(10) {
+            let results = index.search(vector, 10)?;
+            assert_eq!(results.len(), 10);
+            if lossy {
+                assert!(
+                    results.iter().any(|(node_id, _)| *node_id == i),
+                    "A vector should be among its own results."
+                );
+            } else {
+                assert_eq!(results[0].0, i, "A vector should find itself first.");
+                assert!(results[0].1.abs() < 1e-9);
+            }
+            assert!(
+                results.windows(2).all(|pair| pair[0].1 <= pair[1].1),
+                "Results should be sorted closest first."
+            );
+        }
+        assert!(matches!(
+            index.search(&Vector::new(vec![1.0; 2]), 5),
+            Err(Error::DimensionMismatch { .. })
+        ));
+        Ok(())
+    }
+
+    fn check_save_and_load<I: VectorIndex>(index: &I, vectors: &[Vector]) -> Result<()> {
+        let dir = tempdir()?;
+        let path = dir.path().join("index.bin");
+        index.save(&path)?;
+        let loaded = I::load(&path)?;
+        assert_eq!(loaded.len(), index.len());
+        assert_eq!(loaded.dimension(), index.dimension());
+        assert_eq!(loaded.metric(), index.metric());
+        for vector in vectors.iter().step_by(10) {
+            assert_eq!(
+                loaded.search(vector, 10)?,
+                index.search(vector, 10)?,
+                "A loaded index should answer like the saved one."
+            );
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn test_hnsw_conforms() -> Result<()> {
+        check_conformance(
+            HNSW::with_config(HNSWConfig {
+                metric: Metric::Cosine,
+                ..HNSWConfig::default()
+            }),
+            false,
+        )
+    }
+
+    #[test]
+    fn test_flat_conforms() -> Result<()> {
+        check_conformance(FlatIndex::with_metric(Metric::Cosine), false)
+    }
+
+    #[test]
+    fn test_ivf_flat_conforms() -> Result<()> {
+        // Four lists train after 64 inserts and retrain after 128, so the
+        // checks run against trained lists.
+        check_conformance(IVFFlat::new(4), false)
+    }
+
+    #[test]
+    fn test_ivf_pq_conforms() -> Result<()> {
+        let mut index = IVFPQ::new(IVFPQConfig {
+            num_lists: 4,
+            num_subspaces: DIMENSION,
+            num_centroids: 16,
+            iterations: 10,
+        });
+        index.set_nprobe(4);
+        check_conformance(index, true)
+    }
+
+    #[test]
+    fn test_disk_ann_conforms() -> Result<()> {
+        let dir = tempdir()?;
+        let vectors = random_vectors(200);
+        let config = DiskANNConfig {
+            max_degree: 16,
+            build_list_size: 32,
+            metric: Metric::Cosine,
+            num_subspaces: 4,
+            num_centroids: 16,
+            ..DiskANNConfig::default()
+        };
+        let index = DiskANN::build(&vectors, &config, &dir.path().join("index.dann"))?;
+        check_read_only_conformance(index, &vectors)
+    }
+
+    #[test]
+    fn test_index_type_chosen_at_runtime() -> Result<()> {
+        let vectors = random_vectors(50);
+        for exact in [true, false] {
+            let mut index: Box<dyn VectorIndex> = if exact {
+                Box::new(FlatIndex::new())
+            } else {
+                Box::new(HNSW::new())
+            };
+            for vector in &vectors {
+                index.insert(vector.clone())?;
+            }
+            assert_eq!(index.len(), 50);
+            assert_eq!(index.metric(), Metric::L2);
+            assert_eq!(index.search(&vectors[9], 1)?[0].0, 9);
+        }
+        Ok(())
+    }
+}
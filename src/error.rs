@@ -0,0 +1,72 @@
+use std::fmt;
+use std::io;
+
+/// Errors returned by the index APIs.
+#[derive(Debug)]
+pub enum Error {
+    /// A vector's length differs from the dimension the index was fixed to.
+    DimensionMismatch {
+        expected: usize,
+        actual: usize,
+    },
+    /// A vector component is NaN or infinite.
+    NonFiniteValue {
+        position: usize,
+    },
+    /// The index already holds `capacity` vectors and cannot take more.
+    CapacityExceeded {
+        capacity: usize,
+    },
+    /// Index parameters that cannot be built, such as zero lists.
+    InvalidConfig(String),
+    Io(io::Error),
+}
+
+pub type Result<T> = std::result::Result<T, Error>;
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Error::DimensionMismatch { expected, actual } => {
+                write!(f, "Expected a vector of dimension {expected}, got {actual}")
+            }
+            Error::NonFiniteValue { position } => {
+                write!(f, "Vector component {position} is NaN or infinite")
+            }
+            Error::CapacityExceeded { capacity } => {
+                write!(
+                    f,
+                    "The index already holds its maximum of {capacity} vectors"
+                )
+            }
+            Error::InvalidConfig(message) => write!(f, "Invalid index config: {message}"),
+            Error::Io(error) => error.fmt(f),
+        }
+    }
+}
+
+impl std::error::Error for Error {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Error::Io(error) => Some(error),
+            _ => None,
+        }
+    }
+}
+
+impl From<io::Error> for Error {
+    fn from(error: io::Error) -> Self {
+        Error::Io(error)
+    }
+}
+
+/// Lets code built on `io::Result`, such as `Database`, use `?` on index
+/// calls. Invalid vectors and configs become `InvalidInput`.
+impl From<Error> for io::Error {
+    fn from(error: Error) -> Self {
+        match error {
+            Error::Io(error) => error,
+            error => io::Error::new(io::ErrorKind::InvalidInput, error.to_string()),
+        }
+    }
+}
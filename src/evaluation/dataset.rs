@@ -0,0 +1,139 @@
+use crate::application::flat::FlatIndex;
+use crate::error::Result;
+use crate::linalg::element::Element;
+use crate::linalg::vector::Vector;
+use crate::storage::node::NodeId;
+use rand::Rng;
+use std::f64::consts::TAU;
+
+/// Base vectors to index, queries to run against them, and optionally the
+/// exact neighbor ids of each query.
+pub struct Dataset<T: Element = f64> {
+    pub base: Vec<Vector<T>>,
+    pub queries: Vec<Vector<T>>,
+    pub ground_truth: Option<Vec<Vec<NodeId>>>,
+}
+
+impl<T: Element> Dataset<T> {
+    pub fn new(base: Vec<Vector<T>>, queries: Vec<Vector<T>>) -> Self {
+        Self {
+            base,
+            queries,
+            ground_truth: None,
+        }
+    }
+
+    pub fn dimension(&self) -> usize {
+        self.base.first().map_or(0, |vector| vector.len())
+    }
+
+    /// Fills `ground_truth` with the exact `k` nearest base ids per query,
+    /// unless ground truth for at least `k` neighbors is already present.
+    pub fn ensure_ground_truth(&mut self, k: usize, threads: usize) -> Result<()> {
+        let sufficient = self
+            .ground_truth
+            .as_ref()
+            .is_some_and(|truth| truth.iter().all(|ids| ids.len() >= k.min(self.base.len())));
+        if !sufficient {
+            self.ground_truth = Some(ground_truth(&self.base, &self.queries, k, threads)?);
+        }
+        Ok(())
+    }
+}
+
+pub fn ground_truth<T: Element>(
+    base: &[Vector<T>],
+    queries: &[Vector<T>],
+    k: usize,
+    threads: usize,
+) -> Result<Vec<Vec<NodeId>>> {
+    let mut flat = FlatIndex::new();
+    for vector in base {
+        flat.insert(vector.clone())?;
+    }
+    queries
+        .iter()
+        .map(|query| {
+            Ok(flat
+                .search_ids_parallel(query, k, threads)?
+                .into_iter()
+                .map(|(node_id, _)| node_id)
+                .collect())
+        })
+        .collect()
+}
+
+/// Components drawn uniformly from `[-1, 1)`.
+pub fn uniform<T: Element>(count: usize, dimension: usize, rng: &mut impl Rng) -> Vec<Vector<T>> {
+    (0..count)
+        .map(|_| {
+            let data: Vec<f64> = (0..dimension).map(|_| rng.gen_range(-1.0..1.0)).collect();
+            Vector::from_f64(&data)
+        })
+        .collect()
+}
+
+/// Points scattered with standard deviation `spread` around `clusters`
+/// uniformly placed centers, which is closer to real embeddings than
+/// `uniform`.
+pub fn gaussian_clusters<T: Element>(
+    count: usize,
+    dimension: usize,
+    clusters: usize,
+    spread: f64,
+    rng: &mut impl Rng,
+) -> Vec<Vector<T>> {
+    let centers: Vec<Vec<f64>> = (0..clusters.max(1))
+        .map(|_| (0..dimension).map(|_| rng.gen_range(-1.0..1.0)).collect())
+        .collect();
+    (0..count)
+        .map(|_| {
+            let center = &centers[rng.gen_range(0..centers.len())];
+            let data: Vec<f64> = center
+                .iter()
+                .map(|val| val + spread * standard_normal(rng))
+                .collect();
+            Vector::from_f64(&data)
+        })
+        .collect()
+}
+
+fn standard_normal(rng: &mut impl Rng) -> f64 {
+    let u1: f64 = 1.0 - rng.r#gen::<f64>();
+    let u2: f64 = rng.r#gen();
+    (-2.0 * u1.ln()).sqrt() * (TAU * u2).cos()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rand::SeedableRng;
+    use rand::rngs::StdRng;
+
+    #[test]
+    fn test_generators_produce_requested_shape() {
+        let mut rng = StdRng::seed_from_u64(0);
+
+        let uniform: Vec<Vector<f32>> = uniform(10, 4, &mut rng);
+        let clustered: Vec<Vector> = gaussian_clusters(20, 3, 2, 0.1, &mut rng);
+
+        assert_eq!(uniform.len(), 10);
+        assert!(uniform.iter().all(|v| v.len() == 4));
+        assert_eq!(clustered.len(), 20);
+        assert!(clustered.iter().all(|v| v.len() == 3));
+    }
+
+    #[test]
+    fn test_ground_truth_is_exact() {
+        let base = vec![
+            Vector::new(vec![0.0]),
+            Vector::new(vec![5.0]),
+            Vector::new(vec![10.0]),
+        ];
+        let mut dataset = Dataset::new(base, vec![Vector::new(vec![6.0])]);
+
+        dataset.ensure_ground_truth(2, 2).unwrap();
+
+        assert_eq!(dataset.ground_truth, Some(vec![vec![1, 2]]));
+    }
+}
@@ -0,0 +1,270 @@
+pub mod dataset;
+
+use crate::application::hnsw::{BuildConfig, BuildControl, HNSW, HNSWConfig};
+use crate::error::Result;
+use crate::linalg::element::Element;
+use crate::storage::node::NodeId;
+use dataset::Dataset;
+use std::collections::HashSet;
+use std::fmt::Write;
+use std::time::{Duration, Instant};
+
+/// Parameter grid for `evaluate_hnsw`. One index is built per `m` (with
+/// `m_max = 2 * m`) and each is queried once per `ef`.
+#[derive(Debug, Clone)]
+pub struct SweepConfig {
+    pub k: usize,
+    pub ms: Vec<usize>,
+    pub efs: Vec<usize>,
+    pub ef_construction: usize,
+    pub ml: usize,
+    pub threads: usize,
+}
+
+impl Default for SweepConfig {
+    fn default() -> Self {
+        Self {
+            k: 10,
+            ms: vec![8, 16, 32],
+            efs: vec![16, 32, 64, 128, 256],
+            ef_construction: 200,
+            ml: 1,
+            threads: 4,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct EvalResult {
+    pub m: usize,
+    pub ef: usize,
+    pub build_seconds: f64,
+    pub recall: f64,
+    pub qps: f64,
+    pub p50_latency_ms: f64,
+    pub p99_latency_ms: f64,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct EvalReport {
+    pub k: usize,
+    pub num_base: usize,
+    pub num_queries: usize,
+    pub dimension: usize,
+    pub results: Vec<EvalResult>,
+}
+
+/// Fraction of the first `k` ground-truth ids of each query that appear in
+/// the first `k` results, averaged over all queries.
+pub fn recall_at_k(results: &[Vec<NodeId>], ground_truth: &[Vec<NodeId>], k: usize) -> f64 {
+    let mut found = 0;
+    let mut expected = 0;
+    for (result, truth) in results.iter().zip(ground_truth) {
+        let truth: HashSet<&NodeId> = truth.iter().take(k).collect();
+        found += result
+            .iter()
+            .take(k)
+            .filter(|id| truth.contains(id))
+            .count();
+        expected += truth.len();
+    }
+    if expected == 0 {
+        return 1.0;
+    }
+    found as f64 / expected as f64
+}
+
+/// Nearest-rank percentile of already sorted latencies, in milliseconds.
+fn percentile_ms(sorted: &[Duration], percentile: f64) -> f64 {
+    if sorted.is_empty() {
+        return 0.0;
+    }
+    let rank = ((percentile / 100.0) * sorted.len() as f64).ceil() as usize;
+    sorted[rank.clamp(1, sorted.len()) - 1].as_secs_f64() * 1000.0
+}
+
+pub fn evaluate_hnsw<T: Element>(
+    dataset: &mut Dataset<T>,
+    config: &SweepConfig,
+) -> Result<EvalReport> {
+    dataset.ensure_ground_truth(config.k, config.threads)?;
+    let ground_truth = dataset.ground_truth.as_ref().unwrap();
+
+    let mut results = Vec::new();
+    for &m in &config.ms {
+        let build_start = Instant::now();
+        let build_config = BuildConfig {
+            m,
+            m_max: 2 * m,
+            ef_construction: config.ef_construction,
+            ml: config.ml,
+            index: HNSWConfig::default(),
+        };
+        let hnsw = HNSW::build_from(
+            dataset.base.iter().cloned(),
+            config.threads,
+            &build_config,
+            BuildControl::default(),
+        )?;
+        let build_seconds = build_start.elapsed().as_secs_f64();
+
+        for &ef in &config.efs {
+            let mut latencies = Vec::with_capacity(dataset.queries.len());
+            let mut found = Vec::with_capacity(dataset.queries.len());
+            let search_start = Instant::now();
+            for query in &dataset.queries {
+                let query_start = Instant::now();
+                found.push(hnsw.search_nodes(query, config.k, ef.max(config.k))?);
+                latencies.push(query_start.elapsed());
+            }
+            let total_seconds = search_start.elapsed().as_secs_f64();
+            latencies.sort();
+
+            results.push(EvalResult {
+                m,
+                ef,
+                build_seconds,
+                recall: recall_at_k(&found, ground_truth, config.k),
+                qps: if total_seconds > 0.0 {
+                    dataset.queries.len() as f64 / total_seconds
+                } else {
+                    0.0
+                },
+                p50_latency_ms: percentile_ms(&latencies, 50.0),
+                p99_latency_ms: percentile_ms(&latencies, 99.0),
+            });
+        }
+    }
+
+    Ok(EvalReport {
+        k: config.k,
+        num_base: dataset.base.len(),
+        num_queries: dataset.queries.len(),
+        dimension: dataset.dimension(),
+        results,
+    })
+}
+
+fn json_number(value: f64) -> String {
+    if value.is_finite() {
+        format!("{value:.6}")
+    } else {
+        "null".to_string()
+    }
+}
+
+impl EvalReport {
+    pub fn to_json(&self) -> String {
+        let results: Vec<String> = self
+            .results
+            .iter()
+            .map(|result| {
+                format!(
+                    "{{\"m\":{},\"ef\":{},\"build_seconds\":{},\"recall\":{},\"qps\":{},\"p50_latency_ms\":{},\"p99_latency_ms\":{}}}",
+                    result.m,
+                    result.ef,
+                    json_number(result.build_seconds),
+                    json_number(result.recall),
+                    json_number(result.qps),
+                    json_number(result.p50_latency_ms),
+                    json_number(result.p99_latency_ms),
+                )
+            })
+            .collect();
+        format!(
+            "{{\"k\":{},\"num_base\":{},\"num_queries\":{},\"dimension\":{},\"results\":[{}]}}",
+            self.k,
+            self.num_base,
+            self.num_queries,
+            self.dimension,
+            results.join(",")
+        )
+    }
+
+    pub fn to_table(&self) -> String {
+        let mut table = format!(
+            "recall@{} over {} queries, {} base vectors, dimension {}\n",
+            self.k, self.num_queries, self.num_base, self.dimension
+        );
+        writeln!(
+            table,
+            "{:>5} {:>6} {:>10} {:>8} {:>12} {:>10} {:>10}",
+            "m", "ef", "build (s)", "recall", "qps", "p50 (ms)", "p99 (ms)"
+        )
+        .unwrap();
+        for result in &self.results {
+            writeln!(
+                table,
+                "{:>5} {:>6} {:>10.3} {:>8.4} {:>12.1} {:>10.3} {:>10.3}",
+                result.m,
+                result.ef,
+                result.build_seconds,
+                result.recall,
+                result.qps,
+                result.p50_latency_ms,
+                result.p99_latency_ms
+            )
+            .unwrap();
+        }
+        table
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rand::SeedableRng;
+    use rand::rngs::StdRng;
+
+    #[test]
+    fn test_recall_at_k() {
+        let truth = vec![vec![1, 2, 3], vec![4, 5, 6]];
+        let results = vec![vec![1, 3, 9], vec![6, 5, 4]];
+
+        assert_eq!(recall_at_k(&results, &truth, 3), 5.0 / 6.0);
+        assert_eq!(recall_at_k(&results, &truth, 1), 0.5);
+        assert_eq!(recall_at_k(&[], &[], 10), 1.0);
+    }
+
+    #[test]
+    fn test_percentile_ms() {
+        let latencies: Vec<Duration> = (1..=100).map(Duration::from_millis).collect();
+
+        assert_eq!(percentile_ms(&latencies, 50.0), 50.0);
+        assert_eq!(percentile_ms(&latencies, 99.0), 99.0);
+        assert_eq!(percentile_ms(&[], 99.0), 0.0);
+    }
+
+    #[test]
+    fn test_evaluate_hnsw_sweep() -> Result<()> {
+        let mut rng = StdRng::seed_from_u64(5);
+        let base = dataset::gaussian_clusters::<f64>(300, 8, 10, 0.05, &mut rng);
+        let queries = dataset::gaussian_clusters::<f64>(20, 8, 10, 0.05, &mut rng);
+        let mut dataset = Dataset::new(base, queries);
+        let config = SweepConfig {
+            k: 5,
+            ms: vec![4, 8],
+            efs: vec![5, 100],
+            ef_construction: 50,
+            ml: 1,
+            threads: 2,
+        };
+
+        let report = evaluate_hnsw(&mut dataset, &config)?;
+
+        assert_eq!(report.results.len(), 4);
+        assert_eq!(report.dimension, 8);
+        for result in &report.results {
+            assert!((0.0..=1.0).contains(&result.recall));
+            assert!(result.p50_latency_ms <= result.p99_latency_ms);
+        }
+        assert!(report.results[3].recall >= 0.9, "{}", report.to_table());
+
+        let json = report.to_json();
+        assert!(json.starts_with("{\"k\":5,\"num_base\":300"));
+        assert_eq!(json.matches("\"recall\":").count(), 4);
+        assert_eq!(report.to_table().lines().count(), 2 + 4);
+
+        Ok(())
+    }
+}
@@ -0,0 +1,111 @@
+pub mod npy;
+pub mod texmex;
+
+use crate::application::vector_index::VectorIndex;
+use crate::evaluation::dataset::Dataset;
+use crate::linalg::element::Element;
+use crate::linalg::vector::Vector;
+use npy::NpyReader;
+use std::io;
+use std::path::Path;
+use texmex::{VecsFormat, VecsReader, read_ground_truth};
+
+/// Opens `path` as a stream of vectors, choosing the reader from the
+/// extension (`.fvecs`, `.ivecs`, `.bvecs` or `.npy`).
+pub fn read_vectors<T: Element>(
+    path: &Path,
+) -> io::Result<Box<dyn Iterator<Item = io::Result<Vector<T>>>>> {
+    if path.extension().is_some_and(|ext| ext == "npy") {
+        return Ok(Box::new(NpyReader::<T>::open(path)?));
+    }
+    match VecsFormat::from_path(path) {
+        Some(format) => Ok(Box::new(VecsReader::<T>::open(path, format)?)),
+        None => Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            format!("unrecognised vector file {}", path.display()),
+        )),
+    }
+}
+
+/// Streams every vector in `path` into `index` and returns how many were
+/// inserted.
+pub fn load_into<T: Element>(index: &mut dyn VectorIndex<T>, path: &Path) -> io::Result<usize> {
+    let mut count = 0;
+    for vector in read_vectors(path)? {
+        index.insert(vector?)?;
+        count += 1;
+    }
+    Ok(count)
+}
+
+/// Loads a benchmark dataset such as SIFT1M. When `ground_truth` is given it
+/// is used as-is by the recall evaluation instead of being recomputed.
+pub fn load_dataset<T: Element>(
+    base: &Path,
+    queries: &Path,
+    ground_truth: Option<&Path>,
+) -> io::Result<Dataset<T>> {
+    let mut dataset = Dataset::new(
+        read_vectors(base)?.collect::<io::Result<_>>()?,
+        read_vectors(queries)?.collect::<io::Result<_>>()?,
+    );
+    if let Some(path) = ground_truth {
+        dataset.ground_truth = Some(read_ground_truth(path)?);
+    }
+    Ok(dataset)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::application::index::IndexConfig;
+    use crate::evaluation::{SweepConfig, evaluate_hnsw};
+    use crate::linalg::metric::Metric;
+    use tempfile::tempdir;
+    use texmex::{write_ground_truth, write_vecs};
+
+    #[test]
+    fn test_load_into_index() -> io::Result<()> {
+        let dir = tempdir()?;
+        let path = dir.path().join("base.npy");
+        let vectors = vec![Vector::new(vec![0.0, 0.0]), Vector::new(vec![4.0, 4.0])];
+        npy::write_npy(&path, &vectors)?;
+        let mut index = IndexConfig::Flat.new_index(Metric::L2)?;
+
+        assert_eq!(load_into(index.as_mut(), &path)?, 2);
+        assert_eq!(
+            index.search(&Vector::new(vec![3.0, 3.0]), 1)?,
+            vec![(1, 2.0)]
+        );
+        assert!(load_into(index.as_mut(), &dir.path().join("base.csv")).is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn test_ground_truth_file_drives_evaluation() -> io::Result<()> {
+        let dir = tempdir()?;
+        let base: Vec<Vector<f32>> = (0..50).map(|i| Vector::new(vec![i as f32, 0.0])).collect();
+        let queries: Vec<Vector<f32>> = vec![Vector::new(vec![10.2, 0.0])];
+        write_vecs(&dir.path().join("base.fvecs"), VecsFormat::Fvecs, &base)?;
+        write_vecs(&dir.path().join("query.fvecs"), VecsFormat::Fvecs, &queries)?;
+        // Deliberately wrong so the test proves the file is used as-is.
+        write_ground_truth(&dir.path().join("gt.ivecs"), &[vec![40, 41]])?;
+
+        let mut dataset: Dataset<f32> = load_dataset(
+            &dir.path().join("base.fvecs"),
+            &dir.path().join("query.fvecs"),
+            Some(&dir.path().join("gt.ivecs")),
+        )?;
+        let config = SweepConfig {
+            k: 2,
+            ms: vec![8],
+            efs: vec![50],
+            ..SweepConfig::default()
+        };
+        let report = evaluate_hnsw(&mut dataset, &config)?;
+
+        assert_eq!(report.num_base, 50);
+        assert_eq!(report.results[0].recall, 0.0);
+        Ok(())
+    }
+}
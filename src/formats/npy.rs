@@ -0,0 +1,267 @@
+use crate::linalg::element::{Element, ElementType};
+use crate::linalg::vector::Vector;
+use half::f16;
+use std::fs::File;
+use std::io::{self, BufReader, BufWriter, Read, Write};
+use std::marker::PhantomData;
+use std::path::Path;
+
+const MAGIC: &[u8] = b"\x93NUMPY";
+
+fn invalid(msg: impl Into<String>) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, msg.into())
+}
+
+/// Streams the rows of a C-ordered two-dimensional `.npy` array of
+/// `f2`, `f4`, `f8` or `i1` values, converting each to `T`.
+pub struct NpyReader<T: Element = f64, R: Read = BufReader<File>> {
+    reader: R,
+    descr: ElementType,
+    rows: usize,
+    columns: usize,
+    rows_read: usize,
+    element_type: PhantomData<T>,
+}
+
+impl<T: Element> NpyReader<T> {
+    pub fn open(path: &Path) -> io::Result<Self> {
+        Self::new(BufReader::new(File::open(path)?))
+    }
+}
+
+impl<T: Element, R: Read> NpyReader<T, R> {
+    pub fn new(mut reader: R) -> io::Result<Self> {
+        let mut preamble = [0; 8];
+        reader.read_exact(&mut preamble)?;
+        if &preamble[..6] != MAGIC {
+            return Err(invalid("missing .npy magic string"));
+        }
+        let header_len = match preamble[6] {
+            1 => {
+                let mut len = [0; 2];
+                reader.read_exact(&mut len)?;
+                u16::from_le_bytes(len) as usize
+            }
+            2 | 3 => {
+                let mut len = [0; 4];
+                reader.read_exact(&mut len)?;
+                u32::from_le_bytes(len) as usize
+            }
+            version => return Err(invalid(format!("unsupported .npy version {version}"))),
+        };
+        let mut header = vec![0; header_len];
+        reader.read_exact(&mut header)?;
+        let header = String::from_utf8(header).map_err(|_| invalid("header is not UTF-8"))?;
+
+        let descr = match header_value(&header, "descr")?.trim_matches(['\'', '"']) {
+            "<f8" => ElementType::F64,
+            "<f4" => ElementType::F32,
+            "<f2" => ElementType::F16,
+            "|i1" | "<i1" => ElementType::I8,
+            other => return Err(invalid(format!("unsupported dtype {other}"))),
+        };
+        if header_value(&header, "fortran_order")? != "False" {
+            return Err(invalid("Fortran-ordered arrays are not supported"));
+        }
+        let shape: Vec<usize> = header_value(&header, "shape")?
+            .trim_matches(['(', ')'])
+            .split(',')
+            .map(str::trim)
+            .filter(|dim| !dim.is_empty())
+            .map(|dim| {
+                dim.parse()
+                    .map_err(|_| invalid(format!("bad shape dimension {dim}")))
+            })
+            .collect::<io::Result<_>>()?;
+        let (rows, columns) = match shape.as_slice() {
+            [rows, columns] => (*rows, *columns),
+            [columns] => (1, *columns),
+            _ => {
+                return Err(invalid(
+                    "only one- and two-dimensional arrays are supported",
+                ));
+            }
+        };
+
+        Ok(Self {
+            reader,
+            descr,
+            rows,
+            columns,
+            rows_read: 0,
+            element_type: PhantomData,
+        })
+    }
+
+    pub fn rows(&self) -> usize {
+        self.rows
+    }
+
+    pub fn columns(&self) -> usize {
+        self.columns
+    }
+
+    pub fn dtype(&self) -> ElementType {
+        self.descr
+    }
+}
+
+/// Extracts the raw text of `key`'s value from the header dictionary.
+fn header_value<'a>(header: &'a str, key: &str) -> io::Result<&'a str> {
+    let pattern = format!("'{key}':");
+    let start = header
+        .find(&pattern)
+        .ok_or_else(|| invalid(format!("header is missing {key}")))?
+        + pattern.len();
+    let rest = header[start..].trim_start();
+    let end = if rest.starts_with('(') {
+        rest.find(')').map(|i| i + 1)
+    } else {
+        rest.find([',', '}'])
+    }
+    .ok_or_else(|| invalid(format!("unterminated {key}")))?;
+    Ok(rest[..end].trim())
+}
+
+fn decode(descr: ElementType, bytes: &[u8]) -> f64 {
+    match descr {
+        ElementType::F64 => f64::read_le(bytes),
+        ElementType::F32 => f32::read_le(bytes).to_f64(),
+        ElementType::F16 => f16::read_le(bytes).to_f64(),
+        ElementType::I8 => i8::read_le(bytes).to_f64(),
+    }
+}
+
+impl<T: Element, R: Read> Iterator for NpyReader<T, R> {
+    type Item = io::Result<Vector<T>>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.rows_read >= self.rows {
+            return None;
+        }
+        self.rows_read += 1;
+
+        let size = self.descr.size();
+        let mut buffer = vec![0; self.columns * size];
+        if let Err(err) = self.reader.read_exact(&mut buffer) {
+            self.rows_read = self.rows;
+            return Some(Err(err));
+        }
+        let data: Vec<f64> = buffer
+            .chunks_exact(size)
+            .map(|chunk| decode(self.descr, chunk))
+            .collect();
+        Some(Ok(Vector::from_f64(&data)))
+    }
+}
+
+/// Writes `vectors` as a two-dimensional array whose dtype matches `T`.
+pub fn write_npy<T: Element>(path: &Path, vectors: &[Vector<T>]) -> io::Result<()> {
+    let columns = vectors.first().map_or(0, |vector| vector.len());
+    if vectors.iter().any(|vector| vector.len() != columns) {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            "all vectors must have the same length",
+        ));
+    }
+    let descr = match T::ELEMENT_TYPE {
+        ElementType::F64 => "<f8",
+        ElementType::F32 => "<f4",
+        ElementType::F16 => "<f2",
+        ElementType::I8 => "|i1",
+    };
+
+    let mut header = format!(
+        "{{'descr': '{descr}', 'fortran_order': False, 'shape': ({}, {}), }}",
+        vectors.len(),
+        columns
+    );
+    // The preamble plus header must be a multiple of 64 bytes and end in a newline.
+    let total = MAGIC.len() + 4 + header.len() + 1;
+    header.push_str(&" ".repeat(total.next_multiple_of(64) - total));
+    header.push('\n');
+
+    let mut writer = BufWriter::new(File::create(path)?);
+    writer.write_all(MAGIC)?;
+    writer.write_all(&[1, 0])?;
+    writer.write_all(&(header.len() as u16).to_le_bytes())?;
+    writer.write_all(header.as_bytes())?;
+    let mut row = Vec::new();
+    for vector in vectors {
+        row.clear();
+        for val in vector.data() {
+            val.write_le(&mut row);
+        }
+        writer.write_all(&row)?;
+    }
+    writer.flush()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::tempdir;
+
+    #[test]
+    fn test_npy_round_trip() -> io::Result<()> {
+        let dir = tempdir()?;
+        let path = dir.path().join("embeddings.npy");
+        let vectors: Vec<Vector<f32>> = vec![
+            Vector::new(vec![1.0, 2.0, 3.0]),
+            Vector::new(vec![-4.5, 0.0, 6.25]),
+        ];
+
+        write_npy(&path, &vectors)?;
+        let reader: NpyReader<f32> = NpyReader::open(&path)?;
+
+        assert_eq!((reader.rows(), reader.columns()), (2, 3));
+        assert_eq!(reader.dtype(), ElementType::F32);
+        assert_eq!(reader.collect::<io::Result<Vec<_>>>()?, vectors);
+        Ok(())
+    }
+
+    #[test]
+    fn test_header_is_aligned() -> io::Result<()> {
+        let dir = tempdir()?;
+        let path = dir.path().join("embeddings.npy");
+        write_npy(&path, &[Vector::new(vec![1.0])])?;
+
+        let bytes = std::fs::read(&path)?;
+        let header_len = u16::from_le_bytes([bytes[8], bytes[9]]) as usize;
+
+        assert_eq!((10 + header_len) % 64, 0);
+        assert_eq!(bytes[10 + header_len - 1], b'\n');
+        Ok(())
+    }
+
+    #[test]
+    fn test_reads_numpy_written_header() -> io::Result<()> {
+        let header = "{'descr': '<f8', 'fortran_order': False, 'shape': (2, 2), }";
+        let mut bytes = MAGIC.to_vec();
+        bytes.extend_from_slice(&[1, 0]);
+        bytes.extend_from_slice(&(header.len() as u16).to_le_bytes());
+        bytes.extend_from_slice(header.as_bytes());
+        for val in [1.0f64, 2.0, 3.0, 4.0] {
+            bytes.extend_from_slice(&val.to_le_bytes());
+        }
+
+        let rows: Vec<Vector<f16>> = NpyReader::new(&bytes[..])?.collect::<io::Result<_>>()?;
+
+        assert_eq!(
+            rows,
+            vec![Vector::from_f64(&[1.0, 2.0]), Vector::from_f64(&[3.0, 4.0])]
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_rejects_fortran_order() {
+        let header = "{'descr': '<f4', 'fortran_order': True, 'shape': (1, 1), }";
+        let mut bytes = MAGIC.to_vec();
+        bytes.extend_from_slice(&[1, 0]);
+        bytes.extend_from_slice(&(header.len() as u16).to_le_bytes());
+        bytes.extend_from_slice(header.as_bytes());
+
+        assert!(NpyReader::<f64, &[u8]>::new(&bytes[..]).is_err());
+    }
+}
@@ -0,0 +1,264 @@
+use crate::linalg::element::Element;
+use crate::linalg::vector::Vector;
+use crate::storage::node::NodeId;
+use std::fs::File;
+use std::io::{self, BufReader, BufWriter, Read, Write};
+use std::marker::PhantomData;
+use std::path::Path;
+
+/// Component encodings of the TEXMEX formats. Every record is a little-endian
+/// `i32` dimension followed by that many components.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum VecsFormat {
+    Fvecs,
+    Ivecs,
+    Bvecs,
+}
+
+impl VecsFormat {
+    pub fn from_path(path: &Path) -> Option<Self> {
+        match path.extension()?.to_str()? {
+            "fvecs" => Some(VecsFormat::Fvecs),
+            "ivecs" => Some(VecsFormat::Ivecs),
+            "bvecs" => Some(VecsFormat::Bvecs),
+            _ => None,
+        }
+    }
+
+    fn component_size(&self) -> usize {
+        match self {
+            VecsFormat::Fvecs | VecsFormat::Ivecs => 4,
+            VecsFormat::Bvecs => 1,
+        }
+    }
+
+    fn decode(&self, bytes: &[u8]) -> f64 {
+        match self {
+            VecsFormat::Fvecs => f32::from_le_bytes(bytes.try_into().unwrap()) as f64,
+            VecsFormat::Ivecs => i32::from_le_bytes(bytes.try_into().unwrap()) as f64,
+            VecsFormat::Bvecs => bytes[0] as f64,
+        }
+    }
+
+    fn encode(&self, value: f64, out: &mut Vec<u8>) {
+        match self {
+            VecsFormat::Fvecs => out.extend_from_slice(&(value as f32).to_le_bytes()),
+            VecsFormat::Ivecs => out.extend_from_slice(&(value.round() as i32).to_le_bytes()),
+            VecsFormat::Bvecs => out.push(value.round().clamp(0.0, u8::MAX as f64) as u8),
+        }
+    }
+}
+
+/// Streams records from a `.fvecs`, `.ivecs` or `.bvecs` file, converting
+/// each component to `T`.
+pub struct VecsReader<T: Element = f64, R: Read = BufReader<File>> {
+    reader: R,
+    format: VecsFormat,
+    element_type: PhantomData<T>,
+}
+
+impl<T: Element> VecsReader<T> {
+    pub fn open(path: &Path, format: VecsFormat) -> io::Result<Self> {
+        Ok(Self::new(BufReader::new(File::open(path)?), format))
+    }
+}
+
+impl<T: Element, R: Read> VecsReader<T, R> {
+    pub fn new(reader: R, format: VecsFormat) -> Self {
+        Self {
+            reader,
+            format,
+            element_type: PhantomData,
+        }
+    }
+
+    /// Reads the next record, or `None` at a clean end of input. The
+    /// buffer grows with the bytes actually read, so a corrupt dimension
+    /// cannot force a huge allocation up front.
+    fn read_record(&mut self) -> io::Result<Option<Vec<f64>>> {
+        let mut len_buffer = Vec::with_capacity(4);
+        (&mut self.reader).take(4).read_to_end(&mut len_buffer)?;
+        let len_buffer: [u8; 4] = match len_buffer.len() {
+            0 => return Ok(None),
+            4 => len_buffer.try_into().unwrap(),
+            _ => {
+                return Err(io::Error::new(
+                    io::ErrorKind::UnexpectedEof,
+                    "truncated record dimension",
+                ));
+            }
+        };
+        let dimension = i32::from_le_bytes(len_buffer);
+        if dimension < 0 {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!("negative record dimension {dimension}"),
+            ));
+        }
+
+        let size = self.format.component_size();
+        let record_len = dimension as usize * size;
+        let mut buffer = Vec::new();
+        (&mut self.reader)
+            .take(record_len as u64)
+            .read_to_end(&mut buffer)?;
+        if buffer.len() != record_len {
+            return Err(io::Error::new(
+                io::ErrorKind::UnexpectedEof,
+                "truncated record",
+            ));
+        }
+        Ok(Some(
+            buffer
+                .chunks_exact(size)
+                .map(|chunk| self.format.decode(chunk))
+                .collect(),
+        ))
+    }
+}
+
+impl<T: Element, R: Read> Iterator for VecsReader<T, R> {
+    type Item = io::Result<Vector<T>>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.read_record()
+            .transpose()
+            .map(|record| record.map(|data| Vector::from_f64(&data)))
+    }
+}
+
+pub fn write_vecs<'a, T: Element>(
+    path: &Path,
+    format: VecsFormat,
+    vectors: impl IntoIterator<Item = &'a Vector<T>>,
+) -> io::Result<()> {
+    let mut writer = BufWriter::new(File::create(path)?);
+    let mut record = Vec::new();
+    for vector in vectors {
+        record.clear();
+        record.extend_from_slice(&(vector.len() as i32).to_le_bytes());
+        for val in vector.data() {
+            format.encode(val.to_f64(), &mut record);
+        }
+        writer.write_all(&record)?;
+    }
+    writer.flush()
+}
+
+/// Reads an `.ivecs` ground-truth file: one row of neighbor ids per query.
+pub fn read_ground_truth(path: &Path) -> io::Result<Vec<Vec<NodeId>>> {
+    let mut reader: VecsReader<f64> = VecsReader::open(path, VecsFormat::Ivecs)?;
+    let mut rows = Vec::new();
+    while let Some(record) = reader.read_record()? {
+        let row = record
+            .into_iter()
+            .map(|id| {
+                if id < 0.0 {
+                    return Err(io::Error::new(
+                        io::ErrorKind::InvalidData,
+                        "negative neighbor id",
+                    ));
+                }
+                Ok(id as NodeId)
+            })
+            .collect::<io::Result<Vec<NodeId>>>()?;
+        rows.push(row);
+    }
+    Ok(rows)
+}
+
+pub fn write_ground_truth(path: &Path, rows: &[Vec<NodeId>]) -> io::Result<()> {
+    let rows: Vec<Vector> = rows
+        .iter()
+        .map(|row| Vector::new(row.iter().map(|id| *id as f64).collect()))
+        .collect();
+    write_vecs(path, VecsFormat::Ivecs, &rows)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::tempdir;
+
+    #[test]
+    fn test_fvecs_round_trip() -> io::Result<()> {
+        let dir = tempdir()?;
+        let path = dir.path().join("base.fvecs");
+        let vectors: Vec<Vector<f32>> = vec![
+            Vector::new(vec![1.5, -2.0, 3.25]),
+            Vector::new(vec![0.0, 0.5, -0.125]),
+        ];
+
+        write_vecs(&path, VecsFormat::Fvecs, &vectors)?;
+        let read: Vec<Vector<f32>> =
+            VecsReader::open(&path, VecsFormat::Fvecs)?.collect::<io::Result<_>>()?;
+
+        assert_eq!(read, vectors);
+        assert_eq!(std::fs::metadata(&path)?.len(), 2 * (4 + 3 * 4));
+        Ok(())
+    }
+
+    #[test]
+    fn test_bvecs_converts_to_element_type() -> io::Result<()> {
+        let dir = tempdir()?;
+        let path = dir.path().join("base.bvecs");
+        let vectors = vec![Vector::new(vec![0.0, 128.0, 255.0])];
+
+        write_vecs(&path, VecsFormat::Bvecs, &vectors)?;
+        let read: Vec<Vector<f32>> =
+            VecsReader::open(&path, VecsFormat::Bvecs)?.collect::<io::Result<_>>()?;
+
+        assert_eq!(read, vec![Vector::new(vec![0.0, 128.0, 255.0])]);
+        Ok(())
+    }
+
+    #[test]
+    fn test_ground_truth_round_trip() -> io::Result<()> {
+        let dir = tempdir()?;
+        let path = dir.path().join("groundtruth.ivecs");
+        let rows = vec![vec![3, 1, 2], vec![0, 7, 1_000_000]];
+
+        write_ground_truth(&path, &rows)?;
+
+        assert_eq!(read_ground_truth(&path)?, rows);
+        Ok(())
+    }
+
+    #[test]
+    fn test_truncated_record_is_an_error() {
+        let mut bytes = 3i32.to_le_bytes().to_vec();
+        bytes.extend_from_slice(&1.0f32.to_le_bytes());
+        let mut reader: VecsReader<f64, &[u8]> = VecsReader::new(&bytes[..], VecsFormat::Fvecs);
+
+        assert!(reader.next().unwrap().is_err());
+
+        let mut huge = i32::MAX.to_le_bytes().to_vec();
+        huge.extend_from_slice(&1.0f32.to_le_bytes());
+        let mut reader: VecsReader<f64, &[u8]> = VecsReader::new(&huge[..], VecsFormat::Bvecs);
+        assert_eq!(
+            reader.next().unwrap().unwrap_err().kind(),
+            io::ErrorKind::UnexpectedEof
+        );
+
+        let mut reader: VecsReader<f64, &[u8]> = VecsReader::new(&[1, 0][..], VecsFormat::Fvecs);
+        assert_eq!(
+            reader.next().unwrap().unwrap_err().kind(),
+            io::ErrorKind::UnexpectedEof,
+            "A partial dimension prefix should not read as the end of input."
+        );
+        assert!(
+            VecsReader::<f64, &[u8]>::new(&[][..], VecsFormat::Fvecs)
+                .next()
+                .is_none()
+        );
+    }
+
+    #[test]
+    fn test_format_from_path() {
+        assert_eq!(
+            VecsFormat::from_path(Path::new("sift_base.fvecs")),
+            Some(VecsFormat::Fvecs)
+        );
+        assert_eq!(VecsFormat::from_path(Path::new("data.csv")), None);
+    }
+}
@@ -1,3 +1,8 @@
-mod linalg;
-mod numeric;
-mod storage;
+pub mod application;
+pub mod error;
+pub mod evaluation;
+pub mod formats;
+pub mod linalg;
+pub mod numeric;
+pub mod quantization;
+pub mod storage;
@@ -0,0 +1,211 @@
+use super::simd;
+use half::f16;
+use std::fmt::Debug;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum ElementType {
+    F64,
+    F32,
+    F16,
+    I8,
+}
+
+impl ElementType {
+    pub fn tag(&self) -> u8 {
+        match self {
+            ElementType::F64 => 0,
+            ElementType::F32 => 1,
+            ElementType::F16 => 2,
+            ElementType::I8 => 3,
+        }
+    }
+
+    pub fn from_tag(tag: u8) -> Option<Self> {
+        match tag {
+            0 => Some(ElementType::F64),
+            1 => Some(ElementType::F32),
+            2 => Some(ElementType::F16),
+            3 => Some(ElementType::I8),
+            _ => None,
+        }
+    }
+
+    pub fn size(&self) -> usize {
+        match self {
+            ElementType::F64 => 8,
+            ElementType::F32 => 4,
+            ElementType::F16 => 2,
+            ElementType::I8 => 1,
+        }
+    }
+}
+
+/// Storage type of a vector component. Arithmetic is always done on the
+/// widened `f64` value so narrow types don't lose precision when summed.
+pub trait Element: Copy + Debug + PartialEq + Send + Sync + 'static {
+    const ELEMENT_TYPE: ElementType;
+
+    fn to_f64(self) -> f64;
+
+    fn from_f64(value: f64) -> Self;
+
+    fn write_le(self, out: &mut Vec<u8>);
+
+    fn read_le(bytes: &[u8]) -> Self;
+
+    fn squared_l2(a: &[Self], b: &[Self]) -> f64 {
+        simd::squared_l2_scalar(a, b)
+    }
+
+    fn dot(a: &[Self], b: &[Self]) -> f64 {
+        simd::dot_scalar(a, b)
+    }
+}
+
+impl Element for f64 {
+    const ELEMENT_TYPE: ElementType = ElementType::F64;
+
+    fn to_f64(self) -> f64 {
+        self
+    }
+
+    fn from_f64(value: f64) -> Self {
+        value
+    }
+
+    fn write_le(self, out: &mut Vec<u8>) {
+        out.extend_from_slice(&self.to_le_bytes());
+    }
+
+    fn read_le(bytes: &[u8]) -> Self {
+        f64::from_le_bytes(bytes.try_into().unwrap())
+    }
+
+    fn squared_l2(a: &[Self], b: &[Self]) -> f64 {
+        simd::squared_l2_f64(a, b)
+    }
+
+    fn dot(a: &[Self], b: &[Self]) -> f64 {
+        simd::dot_f64(a, b)
+    }
+}
+
+impl Element for f32 {
+    const ELEMENT_TYPE: ElementType = ElementType::F32;
+
+    fn to_f64(self) -> f64 {
+        self as f64
+    }
+
+    fn from_f64(value: f64) -> Self {
+        value as f32
+    }
+
+    fn write_le(self, out: &mut Vec<u8>) {
+        out.extend_from_slice(&self.to_le_bytes());
+    }
+
+    fn read_le(bytes: &[u8]) -> Self {
+        f32::from_le_bytes(bytes.try_into().unwrap())
+    }
+
+    fn squared_l2(a: &[Self], b: &[Self]) -> f64 {
+        simd::squared_l2_f32(a, b)
+    }
+
+    fn dot(a: &[Self], b: &[Self]) -> f64 {
+        simd::dot_f32(a, b)
+    }
+}
+
+impl Element for f16 {
+    const ELEMENT_TYPE: ElementType = ElementType::F16;
+
+    fn to_f64(self) -> f64 {
+        self.to_f64()
+    }
+
+    fn from_f64(value: f64) -> Self {
+        f16::from_f64(value)
+    }
+
+    fn write_le(self, out: &mut Vec<u8>) {
+        out.extend_from_slice(&self.to_le_bytes());
+    }
+
+    fn read_le(bytes: &[u8]) -> Self {
+        f16::from_le_bytes(bytes.try_into().unwrap())
+    }
+
+    fn squared_l2(a: &[Self], b: &[Self]) -> f64 {
+        simd::squared_l2_f16(a, b)
+    }
+
+    fn dot(a: &[Self], b: &[Self]) -> f64 {
+        simd::dot_f16(a, b)
+    }
+}
+
+impl Element for i8 {
+    const ELEMENT_TYPE: ElementType = ElementType::I8;
+
+    fn to_f64(self) -> f64 {
+        self as f64
+    }
+
+    fn from_f64(value: f64) -> Self {
+        value.round().clamp(i8::MIN as f64, i8::MAX as f64) as i8
+    }
+
+    fn write_le(self, out: &mut Vec<u8>) {
+        out.push(self as u8);
+    }
+
+    fn read_le(bytes: &[u8]) -> Self {
+        bytes[0] as i8
+    }
+
+    fn squared_l2(a: &[Self], b: &[Self]) -> f64 {
+        simd::squared_l2_i8(a, b)
+    }
+
+    fn dot(a: &[Self], b: &[Self]) -> f64 {
+        simd::dot_i8(a, b)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_element_type_tag_round_trip() {
+        for element_type in [
+            ElementType::F64,
+            ElementType::F32,
+            ElementType::F16,
+            ElementType::I8,
+        ] {
+            assert_eq!(
+                ElementType::from_tag(element_type.tag()),
+                Some(element_type)
+            );
+        }
+        assert_eq!(ElementType::from_tag(42), None);
+    }
+
+    #[test]
+    fn test_i8_from_f64_saturates() {
+        assert_eq!(i8::from_f64(300.0), i8::MAX);
+        assert_eq!(i8::from_f64(-300.0), i8::MIN);
+        assert_eq!(i8::from_f64(1.6), 2);
+    }
+
+    #[test]
+    fn test_write_and_read_le() {
+        let mut buf = Vec::new();
+        f16::from_f64(1.5).write_le(&mut buf);
+        assert_eq!(buf.len(), ElementType::F16.size());
+        assert_eq!(f16::read_le(&buf).to_f64(), 1.5);
+    }
+}
@@ -0,0 +1,103 @@
+use super::simd::squared_l2_f64;
+use rand::Rng;
+use rand::seq::index::sample;
+
+/// Lloyd's k-means over `points`, initialised from a random sample of
+/// distinct points. Returns at most `min(k, points.len())` centroids.
+pub fn kmeans(
+    points: &[Vec<f64>],
+    k: usize,
+    iterations: usize,
+    rng: &mut impl Rng,
+) -> Vec<Vec<f64>> {
+    let k = k.min(points.len());
+    if k == 0 {
+        return Vec::new();
+    }
+    let dimension = points[0].len();
+
+    let mut centroids: Vec<Vec<f64>> = sample(rng, points.len(), k)
+        .into_iter()
+        .map(|i| points[i].clone())
+        .collect();
+
+    for _ in 0..iterations {
+        let mut sums = vec![vec![0.0; dimension]; k];
+        let mut counts = vec![0usize; k];
+
+        for point in points {
+            let cluster = nearest_centroid(&centroids, point);
+            counts[cluster] += 1;
+            for (sum, val) in sums[cluster].iter_mut().zip(point) {
+                *sum += val;
+            }
+        }
+
+        let mut changed = false;
+        for (cluster, (sum, count)) in sums.into_iter().zip(counts).enumerate() {
+            let centroid = if count == 0 {
+                points[rng.gen_range(0..points.len())].clone()
+            } else {
+                sum.into_iter().map(|val| val / count as f64).collect()
+            };
+            if centroid != centroids[cluster] {
+                centroids[cluster] = centroid;
+                changed = true;
+            }
+        }
+
+        if !changed {
+            break;
+        }
+    }
+
+    centroids
+}
+
+pub fn nearest_centroid(centroids: &[Vec<f64>], point: &[f64]) -> usize {
+    centroids
+        .iter()
+        .map(|centroid| squared_l2_f64(centroid, point))
+        .enumerate()
+        .min_by(|(_, a), (_, b)| a.total_cmp(b))
+        .map(|(i, _)| i)
+        .unwrap()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rand::SeedableRng;
+    use rand::rngs::StdRng;
+
+    #[test]
+    fn test_kmeans_separates_clusters() {
+        let mut rng = StdRng::seed_from_u64(7);
+        let mut points = Vec::new();
+        for center in [[0.0, 0.0], [100.0, 100.0], [-100.0, 50.0]] {
+            for _ in 0..50 {
+                points.push(vec![
+                    center[0] + rng.gen_range(-1.0..1.0),
+                    center[1] + rng.gen_range(-1.0..1.0),
+                ]);
+            }
+        }
+
+        let centroids = kmeans(&points, 3, 25, &mut rng);
+
+        assert_eq!(centroids.len(), 3);
+        for center in [[0.0, 0.0], [100.0, 100.0], [-100.0, 50.0]] {
+            let nearest = &centroids[nearest_centroid(&centroids, &center)];
+            assert!(squared_l2_f64(nearest, &center) < 1.0);
+        }
+    }
+
+    #[test]
+    fn test_kmeans_caps_k_at_point_count() {
+        let mut rng = StdRng::seed_from_u64(1);
+        let points = vec![vec![1.0], vec![2.0]];
+
+        assert_eq!(kmeans(&points, 10, 5, &mut rng).len(), 2);
+        assert!(kmeans(&[], 10, 5, &mut rng).is_empty());
+    }
+}
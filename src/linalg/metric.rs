@@ -0,0 +1,73 @@
+use super::element::Element;
+use super::simd;
+
+/// Distance function of an index. Smaller is always closer, so `Dot` is
+/// reported as the negated dot product.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Metric {
+    /// Squared Euclidean distance.
+    #[default]
+    L2,
+    /// `1 - cos(a, b)`.
+    Cosine,
+    /// `-(a · b)`, for maximum inner product search.
+    Dot,
+}
+
+impl Metric {
+    pub fn distance<T: Element>(&self, a: &[T], b: &[T]) -> f64 {
+        match self {
+            Metric::L2 => T::squared_l2(a, b),
+            Metric::Cosine => simd::cosine_distance(a, b),
+            Metric::Dot => -T::dot(a, b),
+        }
+    }
+
+    /// Maps a distance to a similarity that grows as vectors get closer:
+    /// `1 / (1 + d)` for L2, `1 - d` (the cosine) for Cosine and the dot
+    /// product itself for Dot.
+    pub fn similarity(&self, distance: f64) -> f64 {
+        match self {
+            Metric::L2 => 1.0 / (1.0 + distance),
+            Metric::Cosine => 1.0 - distance,
+            Metric::Dot => -distance,
+        }
+    }
+
+    pub fn tag(&self) -> u8 {
+        match self {
+            Metric::L2 => 0,
+            Metric::Cosine => 1,
+            Metric::Dot => 2,
+        }
+    }
+
+    pub fn from_tag(tag: u8) -> Option<Self> {
+        match tag {
+            0 => Some(Metric::L2),
+            1 => Some(Metric::Cosine),
+            2 => Some(Metric::Dot),
+            _ => None,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_metric_distances() {
+        let a = [1.0, 0.0];
+        let b = [3.0, 4.0];
+
+        assert_eq!(Metric::L2.distance(&a, &b), 20.0);
+        assert!((Metric::Cosine.distance(&a, &b) - 0.4).abs() < 1e-12);
+        assert_eq!(Metric::Dot.distance(&a, &b), -3.0);
+        assert_eq!(Metric::Dot.similarity(-3.0), 3.0);
+        for metric in [Metric::L2, Metric::Cosine, Metric::Dot] {
+            assert_eq!(Metric::from_tag(metric.tag()), Some(metric));
+        }
+        assert_eq!(Metric::from_tag(9), None);
+    }
+}
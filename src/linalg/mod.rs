@@ -1 +1,6 @@
+pub mod element;
+pub mod kmeans;
+pub mod metric;
+pub mod simd;
+pub mod sparse;
 pub mod vector;
@@ -0,0 +1,537 @@
+use super::element::Element;
+use half::f16;
+use std::iter::zip;
+use std::sync::OnceLock;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum SimdLevel {
+    Scalar,
+    Sse2,
+    Avx2,
+    Avx512,
+}
+
+impl SimdLevel {
+    /// Highest instruction set supported by the running CPU, detected once.
+    pub fn detect() -> SimdLevel {
+        static LEVEL: OnceLock<SimdLevel> = OnceLock::new();
+        *LEVEL.get_or_init(|| {
+            #[cfg(target_arch = "x86_64")]
+            {
+                let avx2 = is_x86_feature_detected!("avx2")
+                    && is_x86_feature_detected!("fma")
+                    && is_x86_feature_detected!("f16c");
+                if avx2
+                    && is_x86_feature_detected!("avx512f")
+                    && is_x86_feature_detected!("avx512bw")
+                {
+                    return SimdLevel::Avx512;
+                }
+                if avx2 {
+                    return SimdLevel::Avx2;
+                }
+                if is_x86_feature_detected!("sse2") {
+                    return SimdLevel::Sse2;
+                }
+            }
+            SimdLevel::Scalar
+        })
+    }
+}
+
+pub fn squared_l2_scalar<T: Element>(a: &[T], b: &[T]) -> f64 {
+    zip(a, b)
+        .map(|(x, y)| (x.to_f64() - y.to_f64()).powi(2))
+        .sum::<f64>()
+}
+
+pub fn dot_scalar<T: Element>(a: &[T], b: &[T]) -> f64 {
+    zip(a, b).map(|(x, y)| x.to_f64() * y.to_f64()).sum::<f64>()
+}
+
+/// Cosine distance `1 - cos(a, b)`, built on the element's dot product
+/// kernel. A zero vector is treated as orthogonal to everything.
+pub fn cosine_distance<T: Element>(a: &[T], b: &[T]) -> f64 {
+    let norm_product = (T::dot(a, a) * T::dot(b, b)).sqrt();
+    if norm_product == 0.0 {
+        return 1.0;
+    }
+    1.0 - T::dot(a, b) / norm_product
+}
+
+macro_rules! dispatch {
+    ($public:ident, $at:ident, $t:ty, $scalar:ident, [$($level:ident => $kernel:ident),*]) => {
+        pub fn $public(a: &[$t], b: &[$t]) -> f64 {
+            $at(SimdLevel::detect(), a, b)
+        }
+
+        // Only x86_64 has kernels to pick between; elsewhere `level` is unused.
+        #[cfg_attr(not(target_arch = "x86_64"), allow(unused_variables))]
+        fn $at(level: SimdLevel, a: &[$t], b: &[$t]) -> f64 {
+            assert_eq!(a.len(), b.len(), "Vectors must be of the same length");
+            #[cfg(target_arch = "x86_64")]
+            {
+                let level = level.min(SimdLevel::detect());
+                $(
+                    if level >= SimdLevel::$level {
+                        // SAFETY: `level` is capped at the detected CPU level.
+                        return unsafe { x86::$kernel(a, b) };
+                    }
+                )*
+            }
+            $scalar(a, b)
+        }
+    };
+}
+
+dispatch!(squared_l2_f64, squared_l2_f64_at, f64, squared_l2_scalar, [
+    Avx512 => squared_l2_f64_avx512,
+    Avx2 => squared_l2_f64_avx2,
+    Sse2 => squared_l2_f64_sse2
+]);
+dispatch!(dot_f64, dot_f64_at, f64, dot_scalar, [
+    Avx512 => dot_f64_avx512,
+    Avx2 => dot_f64_avx2,
+    Sse2 => dot_f64_sse2
+]);
+dispatch!(squared_l2_f32, squared_l2_f32_at, f32, squared_l2_scalar, [
+    Avx512 => squared_l2_f32_avx512,
+    Avx2 => squared_l2_f32_avx2,
+    Sse2 => squared_l2_f32_sse2
+]);
+dispatch!(dot_f32, dot_f32_at, f32, dot_scalar, [
+    Avx512 => dot_f32_avx512,
+    Avx2 => dot_f32_avx2,
+    Sse2 => dot_f32_sse2
+]);
+dispatch!(squared_l2_f16, squared_l2_f16_at, f16, squared_l2_scalar, [
+    Avx512 => squared_l2_f16_avx512,
+    Avx2 => squared_l2_f16_avx2
+]);
+dispatch!(dot_f16, dot_f16_at, f16, dot_scalar, [
+    Avx512 => dot_f16_avx512,
+    Avx2 => dot_f16_avx2
+]);
+dispatch!(squared_l2_i8, squared_l2_i8_at, i8, squared_l2_scalar, [
+    Avx512 => squared_l2_i8_avx512,
+    Avx2 => squared_l2_i8_avx2
+]);
+dispatch!(dot_i8, dot_i8_at, i8, dot_scalar, [
+    Avx512 => dot_i8_avx512,
+    Avx2 => dot_i8_avx2
+]);
+
+/// Floating point kernels widen every lane to `f64` before accumulating, so
+/// they agree with the scalar path up to summation order. The `i8` kernels
+/// are exact: products are formed in `i16`/`i32` and summed in `i64`.
+#[cfg(target_arch = "x86_64")]
+mod x86 {
+    use super::{dot_scalar, squared_l2_scalar};
+    use half::f16;
+    use std::arch::x86_64::*;
+
+    macro_rules! float_kernels {
+        (
+            $features:literal, $lanes:expr, $t:ty, $reg:ty,
+            $zero:ident, $sub:ident, $fmadd:ident, $reduce:ident, $load:ident,
+            $l2:ident, $dot:ident
+        ) => {
+            #[target_feature(enable = $features)]
+            pub unsafe fn $l2(a: &[$t], b: &[$t]) -> f64 {
+                let chunks = a.len() / $lanes;
+                let mut acc: $reg = $zero();
+                for i in 0..chunks {
+                    // SAFETY: `i * $lanes + $lanes <= a.len() == b.len()`.
+                    let (va, vb) = unsafe {
+                        (
+                            $load(a.as_ptr().add(i * $lanes)),
+                            $load(b.as_ptr().add(i * $lanes)),
+                        )
+                    };
+                    let diff = $sub(va, vb);
+                    acc = $fmadd(diff, diff, acc);
+                }
+                let tail = chunks * $lanes;
+                $reduce(acc) + squared_l2_scalar(&a[tail..], &b[tail..])
+            }
+
+            #[target_feature(enable = $features)]
+            pub unsafe fn $dot(a: &[$t], b: &[$t]) -> f64 {
+                let chunks = a.len() / $lanes;
+                let mut acc: $reg = $zero();
+                for i in 0..chunks {
+                    // SAFETY: `i * $lanes + $lanes <= a.len() == b.len()`.
+                    let (va, vb) = unsafe {
+                        (
+                            $load(a.as_ptr().add(i * $lanes)),
+                            $load(b.as_ptr().add(i * $lanes)),
+                        )
+                    };
+                    acc = $fmadd(va, vb, acc);
+                }
+                let tail = chunks * $lanes;
+                $reduce(acc) + dot_scalar(&a[tail..], &b[tail..])
+            }
+        };
+    }
+
+    #[target_feature(enable = "sse2")]
+    fn sse2_fmadd(a: __m128d, b: __m128d, acc: __m128d) -> __m128d {
+        _mm_add_pd(_mm_mul_pd(a, b), acc)
+    }
+
+    #[target_feature(enable = "sse2")]
+    fn sse2_reduce(acc: __m128d) -> f64 {
+        let mut lanes = [0.0; 2];
+        // SAFETY: `lanes` holds exactly one `__m128d`.
+        unsafe { _mm_storeu_pd(lanes.as_mut_ptr(), acc) };
+        lanes[0] + lanes[1]
+    }
+
+    #[target_feature(enable = "sse2")]
+    unsafe fn sse2_load_f64(ptr: *const f64) -> __m128d {
+        unsafe { _mm_loadu_pd(ptr) }
+    }
+
+    #[target_feature(enable = "sse2")]
+    unsafe fn sse2_load_f32(ptr: *const f32) -> __m128d {
+        unsafe { _mm_cvtps_pd(_mm_castsi128_ps(_mm_loadl_epi64(ptr as *const __m128i))) }
+    }
+
+    #[target_feature(enable = "avx2,fma,f16c")]
+    fn avx2_reduce(acc: __m256d) -> f64 {
+        let sum = _mm_add_pd(_mm256_castpd256_pd128(acc), _mm256_extractf128_pd(acc, 1));
+        sse2_reduce(sum)
+    }
+
+    #[target_feature(enable = "avx2,fma,f16c")]
+    unsafe fn avx2_load_f64(ptr: *const f64) -> __m256d {
+        unsafe { _mm256_loadu_pd(ptr) }
+    }
+
+    #[target_feature(enable = "avx2,fma,f16c")]
+    unsafe fn avx2_load_f32(ptr: *const f32) -> __m256d {
+        unsafe { _mm256_cvtps_pd(_mm_loadu_ps(ptr)) }
+    }
+
+    #[target_feature(enable = "avx2,fma,f16c")]
+    unsafe fn avx2_load_f16(ptr: *const f16) -> __m256d {
+        unsafe { _mm256_cvtps_pd(_mm_cvtph_ps(_mm_loadl_epi64(ptr as *const __m128i))) }
+    }
+
+    #[target_feature(enable = "avx512f,avx512bw,f16c")]
+    unsafe fn avx512_load_f64(ptr: *const f64) -> __m512d {
+        unsafe { _mm512_loadu_pd(ptr) }
+    }
+
+    #[target_feature(enable = "avx512f,avx512bw,f16c")]
+    unsafe fn avx512_load_f32(ptr: *const f32) -> __m512d {
+        unsafe { _mm512_cvtps_pd(_mm256_loadu_ps(ptr)) }
+    }
+
+    #[target_feature(enable = "avx512f,avx512bw,f16c")]
+    unsafe fn avx512_load_f16(ptr: *const f16) -> __m512d {
+        unsafe { _mm512_cvtps_pd(_mm256_cvtph_ps(_mm_loadu_si128(ptr as *const __m128i))) }
+    }
+
+    float_kernels!(
+        "sse2",
+        2,
+        f64,
+        __m128d,
+        _mm_setzero_pd,
+        _mm_sub_pd,
+        sse2_fmadd,
+        sse2_reduce,
+        sse2_load_f64,
+        squared_l2_f64_sse2,
+        dot_f64_sse2
+    );
+    float_kernels!(
+        "sse2",
+        2,
+        f32,
+        __m128d,
+        _mm_setzero_pd,
+        _mm_sub_pd,
+        sse2_fmadd,
+        sse2_reduce,
+        sse2_load_f32,
+        squared_l2_f32_sse2,
+        dot_f32_sse2
+    );
+    float_kernels!(
+        "avx2,fma,f16c",
+        4,
+        f64,
+        __m256d,
+        _mm256_setzero_pd,
+        _mm256_sub_pd,
+        _mm256_fmadd_pd,
+        avx2_reduce,
+        avx2_load_f64,
+        squared_l2_f64_avx2,
+        dot_f64_avx2
+    );
+    float_kernels!(
+        "avx2,fma,f16c",
+        4,
+        f32,
+        __m256d,
+        _mm256_setzero_pd,
+        _mm256_sub_pd,
+        _mm256_fmadd_pd,
+        avx2_reduce,
+        avx2_load_f32,
+        squared_l2_f32_avx2,
+        dot_f32_avx2
+    );
+    float_kernels!(
+        "avx2,fma,f16c",
+        4,
+        f16,
+        __m256d,
+        _mm256_setzero_pd,
+        _mm256_sub_pd,
+        _mm256_fmadd_pd,
+        avx2_reduce,
+        avx2_load_f16,
+        squared_l2_f16_avx2,
+        dot_f16_avx2
+    );
+    float_kernels!(
+        "avx512f,avx512bw,f16c",
+        8,
+        f64,
+        __m512d,
+        _mm512_setzero_pd,
+        _mm512_sub_pd,
+        _mm512_fmadd_pd,
+        _mm512_reduce_add_pd,
+        avx512_load_f64,
+        squared_l2_f64_avx512,
+        dot_f64_avx512
+    );
+    float_kernels!(
+        "avx512f,avx512bw,f16c",
+        8,
+        f32,
+        __m512d,
+        _mm512_setzero_pd,
+        _mm512_sub_pd,
+        _mm512_fmadd_pd,
+        _mm512_reduce_add_pd,
+        avx512_load_f32,
+        squared_l2_f32_avx512,
+        dot_f32_avx512
+    );
+    float_kernels!(
+        "avx512f,avx512bw,f16c",
+        8,
+        f16,
+        __m512d,
+        _mm512_setzero_pd,
+        _mm512_sub_pd,
+        _mm512_fmadd_pd,
+        _mm512_reduce_add_pd,
+        avx512_load_f16,
+        squared_l2_f16_avx512,
+        dot_f16_avx512
+    );
+
+    #[target_feature(enable = "avx2,fma,f16c")]
+    fn avx2_widen_i32(acc: __m256i, products: __m256i) -> __m256i {
+        let low = _mm256_cvtepi32_epi64(_mm256_castsi256_si128(products));
+        let high = _mm256_cvtepi32_epi64(_mm256_extracti128_si256(products, 1));
+        _mm256_add_epi64(acc, _mm256_add_epi64(low, high))
+    }
+
+    #[target_feature(enable = "avx2,fma,f16c")]
+    fn avx2_reduce_i64(acc: __m256i) -> i64 {
+        let mut lanes = [0i64; 4];
+        // SAFETY: `lanes` holds exactly one `__m256i`.
+        unsafe { _mm256_storeu_si256(lanes.as_mut_ptr() as *mut __m256i, acc) };
+        lanes.iter().sum()
+    }
+
+    #[target_feature(enable = "avx2,fma,f16c")]
+    unsafe fn avx2_load_i8(ptr: *const i8) -> __m256i {
+        unsafe { _mm256_cvtepi8_epi16(_mm_loadu_si128(ptr as *const __m128i)) }
+    }
+
+    #[target_feature(enable = "avx2,fma,f16c")]
+    pub unsafe fn squared_l2_i8_avx2(a: &[i8], b: &[i8]) -> f64 {
+        let chunks = a.len() / 16;
+        let mut acc = _mm256_setzero_si256();
+        for i in 0..chunks {
+            // SAFETY: `i * 16 + 16 <= a.len() == b.len()`.
+            let (va, vb) = unsafe {
+                (
+                    avx2_load_i8(a.as_ptr().add(i * 16)),
+                    avx2_load_i8(b.as_ptr().add(i * 16)),
+                )
+            };
+            let diff = _mm256_sub_epi16(va, vb);
+            acc = avx2_widen_i32(acc, _mm256_madd_epi16(diff, diff));
+        }
+        let tail = chunks * 16;
+        avx2_reduce_i64(acc) as f64 + squared_l2_scalar(&a[tail..], &b[tail..])
+    }
+
+    #[target_feature(enable = "avx2,fma,f16c")]
+    pub unsafe fn dot_i8_avx2(a: &[i8], b: &[i8]) -> f64 {
+        let chunks = a.len() / 16;
+        let mut acc = _mm256_setzero_si256();
+        for i in 0..chunks {
+            // SAFETY: `i * 16 + 16 <= a.len() == b.len()`.
+            let (va, vb) = unsafe {
+                (
+                    avx2_load_i8(a.as_ptr().add(i * 16)),
+                    avx2_load_i8(b.as_ptr().add(i * 16)),
+                )
+            };
+            acc = avx2_widen_i32(acc, _mm256_madd_epi16(va, vb));
+        }
+        let tail = chunks * 16;
+        avx2_reduce_i64(acc) as f64 + dot_scalar(&a[tail..], &b[tail..])
+    }
+
+    #[target_feature(enable = "avx512f,avx512bw,f16c")]
+    unsafe fn avx512_load_i8(ptr: *const i8) -> __m512i {
+        unsafe { _mm512_cvtepi8_epi16(_mm256_loadu_si256(ptr as *const __m256i)) }
+    }
+
+    // A single iteration sums at most 32 products of 255², which fits in
+    // `i32`, so the per-iteration reduction can't overflow.
+    #[target_feature(enable = "avx512f,avx512bw,f16c")]
+    pub unsafe fn squared_l2_i8_avx512(a: &[i8], b: &[i8]) -> f64 {
+        let chunks = a.len() / 32;
+        let mut sum = 0i64;
+        for i in 0..chunks {
+            // SAFETY: `i * 32 + 32 <= a.len() == b.len()`.
+            let (va, vb) = unsafe {
+                (
+                    avx512_load_i8(a.as_ptr().add(i * 32)),
+                    avx512_load_i8(b.as_ptr().add(i * 32)),
+                )
+            };
+            let diff = _mm512_sub_epi16(va, vb);
+            sum += _mm512_reduce_add_epi32(_mm512_madd_epi16(diff, diff)) as i64;
+        }
+        let tail = chunks * 32;
+        sum as f64 + squared_l2_scalar(&a[tail..], &b[tail..])
+    }
+
+    #[target_feature(enable = "avx512f,avx512bw,f16c")]
+    pub unsafe fn dot_i8_avx512(a: &[i8], b: &[i8]) -> f64 {
+        let chunks = a.len() / 32;
+        let mut sum = 0i64;
+        for i in 0..chunks {
+            // SAFETY: `i * 32 + 32 <= a.len() == b.len()`.
+            let (va, vb) = unsafe {
+                (
+                    avx512_load_i8(a.as_ptr().add(i * 32)),
+                    avx512_load_i8(b.as_ptr().add(i * 32)),
+                )
+            };
+            sum += _mm512_reduce_add_epi32(_mm512_madd_epi16(va, vb)) as i64;
+        }
+        let tail = chunks * 32;
+        sum as f64 + dot_scalar(&a[tail..], &b[tail..])
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rand::Rng;
+
+    const LEVELS: [SimdLevel; 4] = [
+        SimdLevel::Scalar,
+        SimdLevel::Sse2,
+        SimdLevel::Avx2,
+        SimdLevel::Avx512,
+    ];
+
+    // Lengths straddle every lane width so the scalar tail is exercised.
+    const LENGTHS: [usize; 9] = [0, 1, 3, 7, 16, 31, 33, 128, 1023];
+
+    fn random_data<T: Element>(len: usize, scale: f64) -> Vec<T> {
+        let mut rng = rand::thread_rng();
+        (0..len)
+            .map(|_| T::from_f64(rng.gen_range(-scale..scale)))
+            .collect()
+    }
+
+    fn assert_close(actual: f64, expected: f64, context: &str) {
+        let tolerance = 1e-9 * expected.abs().max(1.0);
+        assert!(
+            (actual - expected).abs() <= tolerance,
+            "{context}: got {actual}, expected {expected}"
+        );
+    }
+
+    fn check_equivalence<T: Element>(
+        name: &str,
+        scale: f64,
+        l2_at: fn(SimdLevel, &[T], &[T]) -> f64,
+        dot_at: fn(SimdLevel, &[T], &[T]) -> f64,
+    ) {
+        for len in LENGTHS {
+            let a = random_data::<T>(len, scale);
+            let b = random_data::<T>(len, scale);
+            for level in LEVELS {
+                let context = format!("{name} len={len} level={level:?}");
+                assert_close(l2_at(level, &a, &b), squared_l2_scalar(&a, &b), &context);
+                assert_close(dot_at(level, &a, &b), dot_scalar(&a, &b), &context);
+            }
+        }
+    }
+
+    #[test]
+    fn test_f64_kernels_match_scalar() {
+        check_equivalence::<f64>("f64", 10.0, squared_l2_f64_at, dot_f64_at);
+    }
+
+    #[test]
+    fn test_f32_kernels_match_scalar() {
+        check_equivalence::<f32>("f32", 10.0, squared_l2_f32_at, dot_f32_at);
+    }
+
+    #[test]
+    fn test_f16_kernels_match_scalar() {
+        check_equivalence::<f16>("f16", 10.0, squared_l2_f16_at, dot_f16_at);
+    }
+
+    #[test]
+    fn test_i8_kernels_match_scalar() {
+        check_equivalence::<i8>("i8", 128.0, squared_l2_i8_at, dot_i8_at);
+    }
+
+    #[test]
+    fn test_i8_kernels_at_extremes() {
+        let a = vec![i8::MIN; 100];
+        let b = vec![i8::MAX; 100];
+        for level in LEVELS {
+            assert_eq!(squared_l2_i8_at(level, &a, &b), 100.0 * 255.0 * 255.0);
+            assert_eq!(dot_i8_at(level, &a, &a), 100.0 * 128.0 * 128.0);
+        }
+    }
+
+    #[test]
+    fn test_cosine_distance() {
+        let a = [1.0, 0.0];
+        let b = [0.0, 2.0];
+        let c = [3.0, 0.0];
+
+        assert_close(cosine_distance::<f64>(&a, &b), 1.0, "orthogonal");
+        assert_close(cosine_distance::<f64>(&a, &c), 0.0, "parallel");
+        assert_close(cosine_distance::<f64>(&a, &[0.0, 0.0]), 1.0, "zero vector");
+    }
+
+    #[test]
+    #[should_panic(expected = "Vectors must be of the same length")]
+    fn test_kernel_panics_on_mismatched_lengths() {
+        squared_l2_f64(&[1.0, 2.0], &[1.0]);
+    }
+}
@@ -0,0 +1,146 @@
+/// Vector stored as sorted `(index, value)` pairs, for high-dimensional
+/// vectors that are mostly zero such as SPLADE term weights.
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct SparseVector {
+    indices: Vec<u32>,
+    values: Vec<f64>,
+}
+
+impl SparseVector {
+    /// Builds a vector from `(index, value)` pairs in any order. Repeated
+    /// indices are summed and zeros are dropped.
+    pub fn from_pairs(pairs: impl IntoIterator<Item = (u32, f64)>) -> Self {
+        let mut pairs: Vec<(u32, f64)> = pairs.into_iter().collect();
+        pairs.sort_by_key(|(index, _)| *index);
+
+        let mut merged: Vec<(u32, f64)> = Vec::with_capacity(pairs.len());
+        for (index, value) in pairs {
+            match merged.last_mut() {
+                Some((last, sum)) if *last == index => *sum += value,
+                _ => merged.push((index, value)),
+            }
+        }
+        let (indices, values) = merged
+            .into_iter()
+            .filter(|(_, value)| *value != 0.0)
+            .unzip();
+        Self { indices, values }
+    }
+
+    pub fn new(indices: Vec<u32>, values: Vec<f64>) -> Self {
+        assert_eq!(
+            indices.len(),
+            values.len(),
+            "Sparse vectors need one value per index"
+        );
+        Self::from_pairs(indices.into_iter().zip(values))
+    }
+
+    pub fn indices(&self) -> &[u32] {
+        &self.indices
+    }
+
+    pub fn values(&self) -> &[f64] {
+        &self.values
+    }
+
+    /// Number of stored (non-zero) entries.
+    pub fn nnz(&self) -> usize {
+        self.indices.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.indices.is_empty()
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = (u32, f64)> + '_ {
+        self.indices
+            .iter()
+            .copied()
+            .zip(self.values.iter().copied())
+    }
+
+    pub fn get(&self, index: u32) -> f64 {
+        self.indices
+            .binary_search(&index)
+            .map_or(0.0, |position| self.values[position])
+    }
+
+    pub fn dot(&self, other: &SparseVector) -> f64 {
+        let (mut i, mut j) = (0, 0);
+        let mut sum = 0.0;
+        while i < self.indices.len() && j < other.indices.len() {
+            match self.indices[i].cmp(&other.indices[j]) {
+                std::cmp::Ordering::Less => i += 1,
+                std::cmp::Ordering::Greater => j += 1,
+                std::cmp::Ordering::Equal => {
+                    sum += self.values[i] * other.values[j];
+                    i += 1;
+                    j += 1;
+                }
+            }
+        }
+        sum
+    }
+
+    /// Encodes as `[nnz u64][(index u32, value f64) * nnz]`, little-endian.
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut bytes = Vec::with_capacity(8 + self.nnz() * 12);
+        bytes.extend_from_slice(&(self.nnz() as u64).to_le_bytes());
+        for (index, value) in self.iter() {
+            bytes.extend_from_slice(&index.to_le_bytes());
+            bytes.extend_from_slice(&value.to_le_bytes());
+        }
+        bytes
+    }
+
+    pub fn from_bytes(bytes: &[u8]) -> Option<Self> {
+        let nnz = u64::from_le_bytes(bytes.get(..8)?.try_into().ok()?) as usize;
+        let body = &bytes[8..];
+        if body.len() != nnz.checked_mul(12)? {
+            return None;
+        }
+        let mut indices = Vec::with_capacity(nnz);
+        let mut values = Vec::with_capacity(nnz);
+        for pair in body.chunks_exact(12) {
+            indices.push(u32::from_le_bytes(pair[..4].try_into().ok()?));
+            values.push(f64::from_le_bytes(pair[4..].try_into().ok()?));
+        }
+        if indices.windows(2).any(|pair| pair[0] >= pair[1]) {
+            return None;
+        }
+        Some(Self { indices, values })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_from_pairs_sorts_merges_and_drops_zeros() {
+        let vector = SparseVector::from_pairs([(7, 1.0), (2, 0.5), (7, 2.0), (4, 0.0)]);
+
+        assert_eq!(vector.indices(), &[2, 7]);
+        assert_eq!(vector.values(), &[0.5, 3.0]);
+        assert_eq!(vector.get(7), 3.0);
+        assert_eq!(vector.get(4), 0.0);
+    }
+
+    #[test]
+    fn test_sparse_dot() {
+        let a = SparseVector::new(vec![1, 5, 9, 29_999], vec![1.0, 2.0, 3.0, 4.0]);
+        let b = SparseVector::new(vec![0, 5, 29_999], vec![10.0, 0.5, -1.0]);
+
+        assert_eq!(a.dot(&b), 2.0 * 0.5 - 4.0);
+        assert_eq!(a.dot(&SparseVector::default()), 0.0);
+    }
+
+    #[test]
+    fn test_sparse_bytes_round_trip() {
+        let vector = SparseVector::new(vec![3, 1], vec![-0.25, 8.0]);
+
+        assert_eq!(SparseVector::from_bytes(&vector.to_bytes()), Some(vector));
+        assert_eq!(SparseVector::from_bytes(&[1, 0, 0]), None);
+    }
+}
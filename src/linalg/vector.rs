@@ -1,42 +1,127 @@
-use std::hash::{Hash, Hasher};
-use std::iter::zip;
+use super::element::{Element, ElementType};
+use super::simd;
+use crate::error::{Error, Result};
+use std::hash::Hash;
 
 #[derive(Clone, Debug)]
-pub struct Vector {
-    data: Vec<f64>,
+pub struct Vector<T: Element = f64> {
+    data: Vec<T>,
 }
 
-impl Vector {
-    pub fn new(data: Vec<f64>) -> Self {
+impl<T: Element> Vector<T> {
+    pub fn new(data: Vec<T>) -> Self {
         Vector { data }
     }
 
-    pub fn data(&self) -> &Vec<f64> {
+    pub fn from_f64(data: &[f64]) -> Self {
+        Vector {
+            data: data.iter().map(|val| T::from_f64(*val)).collect(),
+        }
+    }
+
+    pub fn data(&self) -> &Vec<T> {
         &self.data
     }
 
+    pub fn len(&self) -> usize {
+        self.data.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.data.is_empty()
+    }
+
+    pub fn element_type(&self) -> ElementType {
+        T::ELEMENT_TYPE
+    }
+
+    pub fn to_f64(&self) -> Vec<f64> {
+        self.data.iter().map(|val| val.to_f64()).collect()
+    }
+
+    /// Checks that every component is finite and, if `dimension` is given,
+    /// that the vector has that length.
+    pub fn validate(&self, dimension: Option<usize>) -> Result<()> {
+        if let Some(expected) = dimension
+            && expected != self.data.len()
+        {
+            return Err(Error::DimensionMismatch {
+                expected,
+                actual: self.data.len(),
+            });
+        }
+        match self.data.iter().position(|val| !val.to_f64().is_finite()) {
+            Some(position) => Err(Error::NonFiniteValue { position }),
+            None => Ok(()),
+        }
+    }
+
     pub fn squared_distance(&self, other: &Self) -> f64 {
         if self.data.len() != other.data.len() {
             panic!("Vectors must be of the same length to compute squared distance");
         }
-        zip(self.data(), other.data())
-            .map(|(cur, other)| (cur - other).powi(2))
-            .sum::<f64>()
+        T::squared_l2(&self.data, &other.data)
     }
+
+    pub fn dot(&self, other: &Self) -> f64 {
+        if self.data.len() != other.data.len() {
+            panic!("Vectors must be of the same length to compute dot product");
+        }
+        T::dot(&self.data, &other.data)
+    }
+
+    pub fn cosine_distance(&self, other: &Self) -> f64 {
+        if self.data.len() != other.data.len() {
+            panic!("Vectors must be of the same length to compute cosine distance");
+        }
+        simd::cosine_distance(&self.data, &other.data)
+    }
+
+    /// Encodes the vector as `[element tag: u8][len: u64 LE][elements LE]` so
+    /// the element type travels with the value through the WAL.
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut bytes = Vec::with_capacity(9 + self.data.len() * T::ELEMENT_TYPE.size());
+        bytes.push(T::ELEMENT_TYPE.tag());
+        bytes.extend_from_slice(&(self.data.len() as u64).to_le_bytes());
+        for val in &self.data {
+            val.write_le(&mut bytes);
+        }
+        bytes
+    }
+
+    pub fn from_bytes(bytes: &[u8]) -> Option<Self> {
+        let (&tag, rest) = bytes.split_first()?;
+        if ElementType::from_tag(tag)? != T::ELEMENT_TYPE || rest.len() < 8 {
+            return None;
+        }
+        let (len_bytes, rest) = rest.split_at(8);
+        let len = u64::from_le_bytes(len_bytes.try_into().unwrap()) as usize;
+        let size = T::ELEMENT_TYPE.size();
+        if rest.len() != len.checked_mul(size)? {
+            return None;
+        }
+        let data = rest.chunks_exact(size).map(T::read_le).collect();
+        Some(Vector { data })
+    }
+}
+
+/// Reads the element type recorded in an encoded vector without decoding it.
+pub fn encoded_element_type(bytes: &[u8]) -> Option<ElementType> {
+    bytes.first().and_then(|tag| ElementType::from_tag(*tag))
 }
 
-impl PartialEq for Vector {
+impl<T: Element> PartialEq for Vector<T> {
     fn eq(&self, other: &Self) -> bool {
         self.data == other.data
     }
 }
 
-impl Eq for Vector {}
+impl<T: Element> Eq for Vector<T> {}
 
-impl Hash for Vector {
+impl<T: Element> Hash for Vector<T> {
     fn hash<H: std::hash::Hasher>(&self, state: &mut H) {
         for val in &self.data {
-            let bits = val.to_bits();
+            let bits = val.to_f64().to_bits();
             bits.hash(state);
         }
     }
@@ -45,6 +130,7 @@ impl Hash for Vector {
 #[cfg(test)]
 mod tests {
     use super::*;
+    use half::f16;
 
     #[test]
     fn test_new_and_data() {
@@ -90,8 +176,8 @@ mod tests {
 
     #[test]
     fn test_squared_distance_with_empty_vectors() {
-        let vec1 = Vector::new(vec![]);
-        let vec2 = Vector::new(vec![]);
+        let vec1: Vector = Vector::new(vec![]);
+        let vec2: Vector = Vector::new(vec![]);
 
         let expected_distance = 0.0;
         assert_eq!(
@@ -100,4 +186,73 @@ mod tests {
             "The distance between two empty vectors should be zero."
         );
     }
+
+    #[test]
+    fn test_squared_distance_narrow_types() {
+        let vec1: Vector<f32> = Vector::new(vec![1.0, 2.0, 3.0]);
+        let vec2: Vector<f32> = Vector::new(vec![4.0, 5.0, 6.0]);
+        assert_eq!(vec1.squared_distance(&vec2), 27.0);
+
+        let vec1: Vector<f16> = Vector::from_f64(&[1.0, 2.0, 3.0]);
+        let vec2: Vector<f16> = Vector::from_f64(&[4.0, 5.0, 6.0]);
+        assert_eq!(vec1.squared_distance(&vec2), 27.0);
+
+        let vec1: Vector<i8> = Vector::new(vec![-128, 127]);
+        let vec2: Vector<i8> = Vector::new(vec![127, -128]);
+        assert_eq!(
+            vec1.squared_distance(&vec2),
+            2.0 * 255.0 * 255.0,
+            "i8 distances should be accumulated without overflow."
+        );
+    }
+
+    #[test]
+    fn test_validate() {
+        let vector = Vector::new(vec![1.0, 2.0]);
+
+        assert!(vector.validate(None).is_ok());
+        assert!(vector.validate(Some(2)).is_ok());
+        assert!(matches!(
+            vector.validate(Some(3)),
+            Err(Error::DimensionMismatch {
+                expected: 3,
+                actual: 2
+            })
+        ));
+        assert!(matches!(
+            Vector::new(vec![0.0, f64::NAN]).validate(None),
+            Err(Error::NonFiniteValue { position: 1 })
+        ));
+        assert!(
+            Vector::new(vec![f64::INFINITY]).validate(Some(1)).is_err(),
+            "Infinite components should be rejected."
+        );
+    }
+
+    #[test]
+    fn test_dot_and_cosine_distance() {
+        let vec1 = Vector::new(vec![1.0, 2.0, 3.0]);
+        let vec2 = Vector::new(vec![2.0, 4.0, 6.0]);
+
+        assert_eq!(vec1.dot(&vec2), 28.0);
+        assert!(vec1.cosine_distance(&vec2).abs() < 1e-12);
+    }
+
+    #[test]
+    fn test_bytes_round_trip() {
+        let vector: Vector<f32> = Vector::new(vec![1.5, -2.0, 3.25]);
+        let bytes = vector.to_bytes();
+
+        assert_eq!(encoded_element_type(&bytes), Some(ElementType::F32));
+        assert_eq!(Vector::<f32>::from_bytes(&bytes), Some(vector));
+    }
+
+    #[test]
+    fn test_from_bytes_rejects_other_element_type() {
+        let vector: Vector<i8> = Vector::new(vec![1, 2, 3]);
+        let bytes = vector.to_bytes();
+
+        assert!(Vector::<f64>::from_bytes(&bytes).is_none());
+        assert!(Vector::<i8>::from_bytes(&bytes[..bytes.len() - 1]).is_none());
+    }
 }
@@ -1,22 +1,83 @@
+use std::cmp::Ordering;
+
+/// `f64` with a total order, for use as a heap priority. Numbers compare as
+/// `f64::total_cmp` does, so `-0.0 < 0.0`. Every NaN, whatever its sign or
+/// payload, is equal to every other NaN and greater than any number. A NaN
+/// distance therefore sorts after every real distance.
 #[derive(Debug, Clone, Copy)]
 pub struct OrderedFloat(pub f64);
 
 impl PartialEq for OrderedFloat {
     fn eq(&self, other: &Self) -> bool {
-        self.0 == other.0
+        self.cmp(other) == Ordering::Equal
     }
 }
 
 impl Eq for OrderedFloat {}
 
 impl Ord for OrderedFloat {
-    fn cmp(&self, other: &Self) -> std::cmp::Ordering {
-        self.0.total_cmp(&other.0)
+    fn cmp(&self, other: &Self) -> Ordering {
+        match (self.0.is_nan(), other.0.is_nan()) {
+            (true, true) => Ordering::Equal,
+            (true, false) => Ordering::Greater,
+            (false, true) => Ordering::Less,
+            (false, false) => self.0.total_cmp(&other.0),
+        }
     }
 }
 
 impl PartialOrd for OrderedFloat {
-    fn partial_cmp(&self, other: &Self) -> Option<std::cmp::Ordering> {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
         Some(self.cmp(other))
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_eq_agrees_with_ord() {
+        let values = [
+            f64::NAN,
+            -f64::NAN,
+            f64::NEG_INFINITY,
+            -1.0,
+            -0.0,
+            0.0,
+            1.0,
+            f64::INFINITY,
+        ];
+
+        for a in values {
+            for b in values {
+                let (a, b) = (OrderedFloat(a), OrderedFloat(b));
+                assert_eq!(
+                    a == b,
+                    a.cmp(&b) == Ordering::Equal,
+                    "Eq and Ord should agree for {a:?} and {b:?}."
+                );
+            }
+        }
+        assert_eq!(OrderedFloat(f64::NAN), OrderedFloat(-f64::NAN));
+        assert_ne!(OrderedFloat(-0.0), OrderedFloat(0.0));
+    }
+
+    #[test]
+    fn test_nan_sorts_last() {
+        let mut values: Vec<OrderedFloat> = [-f64::NAN, 2.0, f64::INFINITY, f64::NAN, -3.0]
+            .into_iter()
+            .map(OrderedFloat)
+            .collect();
+
+        values.sort();
+
+        assert_eq!(values[0].0, -3.0);
+        assert_eq!(values[1].0, 2.0);
+        assert_eq!(values[2].0, f64::INFINITY);
+        assert!(
+            values[3].0.is_nan() && values[4].0.is_nan(),
+            "NaNs of either sign should sort after infinity."
+        );
+    }
+}
@@ -0,0 +1,97 @@
+use crate::linalg::element::Element;
+use crate::linalg::vector::Vector;
+
+/// Sign-bit codec: bit `i` of the code is set when component `i` is
+/// positive. Bits are packed little-endian into `u64` words.
+#[derive(Debug, Clone, PartialEq)]
+pub struct BinaryQuantizer {
+    dimension: usize,
+}
+
+impl BinaryQuantizer {
+    pub fn new(dimension: usize) -> Self {
+        Self { dimension }
+    }
+
+    pub fn dimension(&self) -> usize {
+        self.dimension
+    }
+
+    pub fn num_words(&self) -> usize {
+        self.dimension.div_ceil(64)
+    }
+
+    pub fn encode<T: Element>(&self, vector: &Vector<T>) -> Vec<u64> {
+        let mut words = vec![0u64; self.num_words()];
+        for (i, val) in vector.data().iter().enumerate() {
+            if val.to_f64() > 0.0 {
+                words[i / 64] |= 1 << (i % 64);
+            }
+        }
+        words
+    }
+
+    pub fn encode_bytes<T: Element>(&self, vector: &Vector<T>) -> Vec<u8> {
+        self.encode(vector)
+            .iter()
+            .flat_map(|word| word.to_le_bytes())
+            .collect()
+    }
+}
+
+pub fn hamming_distance(a: &[u64], b: &[u64]) -> u32 {
+    a.iter().zip(b).map(|(x, y)| (x ^ y).count_ones()).sum()
+}
+
+/// Hamming distance between a word-packed query and a code stored as the
+/// little-endian bytes of its words.
+pub fn hamming_distance_bytes(query: &[u64], code: &[u8]) -> u32 {
+    query
+        .iter()
+        .zip(code.chunks_exact(8))
+        .map(|(word, chunk)| (word ^ u64::from_le_bytes(chunk.try_into().unwrap())).count_ones())
+        .sum()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_encode_packs_sign_bits() {
+        let quantizer = BinaryQuantizer::new(3);
+        let code = quantizer.encode(&Vector::new(vec![1.0, -2.0, 0.5]));
+
+        assert_eq!(code, vec![0b101]);
+    }
+
+    #[test]
+    fn test_encode_spans_multiple_words() {
+        let mut data = vec![-1.0; 130];
+        data[0] = 1.0;
+        data[64] = 1.0;
+        data[129] = 1.0;
+        let quantizer = BinaryQuantizer::new(130);
+
+        let code = quantizer.encode(&Vector::new(data));
+
+        assert_eq!(quantizer.num_words(), 3);
+        assert_eq!(code, vec![1, 1, 0b10]);
+    }
+
+    #[test]
+    fn test_hamming_distance() {
+        let quantizer = BinaryQuantizer::new(4);
+        let a = Vector::new(vec![1.0, 1.0, -1.0, -1.0]);
+        let b = Vector::new(vec![1.0, -1.0, 1.0, -1.0]);
+
+        let (code_a, code_b) = (quantizer.encode(&a), quantizer.encode(&b));
+
+        assert_eq!(hamming_distance(&code_a, &code_b), 2);
+        assert_eq!(hamming_distance(&code_a, &code_a), 0);
+        assert_eq!(
+            hamming_distance_bytes(&code_a, &quantizer.encode_bytes(&b)),
+            2
+        );
+    }
+}
@@ -0,0 +1,102 @@
+pub mod binary;
+pub mod product;
+pub mod scalar;
+
+use crate::linalg::element::Element;
+use crate::linalg::metric::Metric;
+use crate::linalg::vector::Vector;
+use binary::{BinaryQuantizer, hamming_distance_bytes};
+use product::{DistanceTable, ProductQuantizer};
+use scalar::ScalarQuantizer;
+
+/// Compressed representation an index can traverse on instead of the
+/// full-precision vectors.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Quantizer {
+    Scalar(ScalarQuantizer),
+    Product(ProductQuantizer),
+    Binary(BinaryQuantizer),
+}
+
+/// A query prepared once against a quantizer so each code can be scored
+/// cheaply.
+pub enum QueryDistance<'a> {
+    Scalar(&'a ScalarQuantizer, Metric, Vec<f64>),
+    Product(DistanceTable),
+    Binary(Vec<u64>),
+}
+
+impl Quantizer {
+    pub fn encode<T: Element>(&self, vector: &Vector<T>) -> Vec<u8> {
+        match self {
+            Quantizer::Scalar(quantizer) => quantizer.encode(vector),
+            Quantizer::Product(quantizer) => quantizer.encode_vector(vector),
+            Quantizer::Binary(quantizer) => quantizer.encode_bytes(vector),
+        }
+    }
+
+    /// Length in bytes of every code `encode` returns.
+    pub fn code_len(&self) -> usize {
+        match self {
+            Quantizer::Scalar(quantizer) => quantizer.dimension(),
+            Quantizer::Product(quantizer) => quantizer.num_subspaces(),
+            Quantizer::Binary(quantizer) => quantizer.num_words() * 8,
+        }
+    }
+
+    /// `[kind u8][codec]`: kind 0 is SQ8, 1 is PQ and 2 is binary, whose
+    /// codec is just its dimension as `u64` LE.
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let (kind, codec) = match self {
+            Quantizer::Scalar(quantizer) => (0, quantizer.to_bytes()),
+            Quantizer::Product(quantizer) => (1, quantizer.to_bytes()),
+            Quantizer::Binary(quantizer) => {
+                (2, (quantizer.dimension() as u64).to_le_bytes().to_vec())
+            }
+        };
+        let mut bytes = vec![kind];
+        bytes.extend_from_slice(&codec);
+        bytes
+    }
+
+    pub fn from_bytes(bytes: &[u8]) -> Option<Self> {
+        let (kind, codec) = bytes.split_first()?;
+        match kind {
+            0 => ScalarQuantizer::from_bytes(codec).map(Quantizer::Scalar),
+            1 => ProductQuantizer::from_bytes(codec).map(Quantizer::Product),
+            2 => {
+                let dimension = u64::from_le_bytes(codec.try_into().ok()?) as usize;
+                Some(Quantizer::Binary(BinaryQuantizer::new(dimension)))
+            }
+            _ => None,
+        }
+    }
+
+    /// Prepares `query` for scoring codes under `metric`. SQ8 and PQ
+    /// estimate the metric's distance to the decoded vector; binary codes
+    /// are always compared by Hamming distance, which tracks the angle
+    /// between vectors rather than any one metric.
+    pub fn prepare<T: Element>(&self, query: &Vector<T>, metric: Metric) -> QueryDistance<'_> {
+        match self {
+            Quantizer::Scalar(quantizer) => {
+                QueryDistance::Scalar(quantizer, metric, query.to_f64())
+            }
+            Quantizer::Product(quantizer) => {
+                QueryDistance::Product(quantizer.metric_distance_table(&query.to_f64(), metric))
+            }
+            Quantizer::Binary(quantizer) => QueryDistance::Binary(quantizer.encode(query)),
+        }
+    }
+}
+
+impl QueryDistance<'_> {
+    pub fn distance(&self, code: &[u8]) -> f64 {
+        match self {
+            QueryDistance::Scalar(quantizer, metric, query) => {
+                quantizer.distance(*metric, query, code)
+            }
+            QueryDistance::Product(table) => table.distance(code),
+            QueryDistance::Binary(query) => hamming_distance_bytes(query, code) as f64,
+        }
+    }
+}
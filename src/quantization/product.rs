@@ -0,0 +1,327 @@
+use crate::linalg::element::Element;
+use crate::linalg::kmeans::{kmeans, nearest_centroid};
+use crate::linalg::metric::Metric;
+use crate::linalg::simd::{dot_f64, squared_l2_f64};
+use crate::linalg::vector::Vector;
+use rand::Rng;
+use std::ops::Range;
+
+/// PQ codec: the vector is split into `num_subspaces` contiguous slices and
+/// each slice is replaced by the index of its nearest codebook centroid.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ProductQuantizer {
+    dimension: usize,
+    num_centroids: usize,
+    codebooks: Vec<Vec<Vec<f64>>>,
+}
+
+/// Per-query asymmetric distance table: `table[s * num_centroids + c]` is the
+/// squared distance from the query's `s`-th slice to centroid `c` for L2, and
+/// their inner product for Cosine and Dot. Cosine also keeps the squared norm
+/// of every centroid, since the norm of a decoded vector is the sum of those
+/// of its slices.
+#[derive(Debug, Clone)]
+pub struct DistanceTable {
+    metric: Metric,
+    num_centroids: usize,
+    table: Vec<f64>,
+    norms: Vec<f64>,
+    query_norm: f64,
+}
+
+impl DistanceTable {
+    fn sum(&self, values: &[f64], code: &[u8]) -> f64 {
+        code.iter()
+            .enumerate()
+            .map(|(subspace, centroid)| values[subspace * self.num_centroids + *centroid as usize])
+            .sum::<f64>()
+    }
+
+    /// Distance to the decoded `code` under the table's metric, with the
+    /// same conventions as `Metric::distance`.
+    pub fn distance(&self, code: &[u8]) -> f64 {
+        let sum = self.sum(&self.table, code);
+        match self.metric {
+            Metric::L2 => sum,
+            Metric::Dot => -sum,
+            Metric::Cosine => {
+                let norm_product = (self.query_norm * self.sum(&self.norms, code)).sqrt();
+                if norm_product == 0.0 {
+                    return 1.0;
+                }
+                1.0 - sum / norm_product
+            }
+        }
+    }
+}
+
+impl ProductQuantizer {
+    /// Trains one codebook of up to `num_centroids` (at most 256) entries per
+    /// subspace. Returns `None` if there is no training data or the
+    /// dimension can't be split into `num_subspaces` non-empty slices.
+    pub fn train(
+        points: &[Vec<f64>],
+        num_subspaces: usize,
+        num_centroids: usize,
+        iterations: usize,
+        rng: &mut impl Rng,
+    ) -> Option<Self> {
+        let dimension = points.first()?.len();
+        if num_subspaces == 0 || num_subspaces > dimension {
+            return None;
+        }
+        let num_centroids = num_centroids.clamp(1, u8::MAX as usize + 1);
+
+        let mut quantizer = Self {
+            dimension,
+            num_centroids: 0,
+            codebooks: Vec::with_capacity(num_subspaces),
+        };
+        for subspace in 0..num_subspaces {
+            let range = quantizer.subspace_range(subspace, num_subspaces);
+            let slices: Vec<Vec<f64>> = points.iter().map(|p| p[range.clone()].to_vec()).collect();
+            quantizer
+                .codebooks
+                .push(kmeans(&slices, num_centroids, iterations, rng));
+        }
+        quantizer.num_centroids = quantizer.codebooks[0].len();
+        Some(quantizer)
+    }
+
+    pub fn train_vectors<T: Element>(
+        vectors: &[Vector<T>],
+        num_subspaces: usize,
+        num_centroids: usize,
+        iterations: usize,
+        rng: &mut impl Rng,
+    ) -> Option<Self> {
+        let points: Vec<Vec<f64>> = vectors.iter().map(|v| v.to_f64()).collect();
+        Self::train(&points, num_subspaces, num_centroids, iterations, rng)
+    }
+
+    fn subspace_range(&self, subspace: usize, num_subspaces: usize) -> Range<usize> {
+        (subspace * self.dimension / num_subspaces)
+            ..((subspace + 1) * self.dimension / num_subspaces)
+    }
+
+    pub fn dimension(&self) -> usize {
+        self.dimension
+    }
+
+    pub fn num_subspaces(&self) -> usize {
+        self.codebooks.len()
+    }
+
+    pub fn num_centroids(&self) -> usize {
+        self.num_centroids
+    }
+
+    pub fn encode(&self, point: &[f64]) -> Vec<u8> {
+        let num_subspaces = self.num_subspaces();
+        self.codebooks
+            .iter()
+            .enumerate()
+            .map(|(subspace, codebook)| {
+                let range = self.subspace_range(subspace, num_subspaces);
+                nearest_centroid(codebook, &point[range]) as u8
+            })
+            .collect()
+    }
+
+    pub fn encode_vector<T: Element>(&self, vector: &Vector<T>) -> Vec<u8> {
+        self.encode(&vector.to_f64())
+    }
+
+    pub fn decode(&self, code: &[u8]) -> Vec<f64> {
+        code.iter()
+            .zip(&self.codebooks)
+            .flat_map(|(centroid, codebook)| codebook[*centroid as usize].iter().copied())
+            .collect()
+    }
+
+    pub fn decode_vector<T: Element>(&self, code: &[u8]) -> Vector<T> {
+        Vector::from_f64(&self.decode(code))
+    }
+
+    /// Squared L2 distance table for `query`.
+    pub fn distance_table(&self, query: &[f64]) -> DistanceTable {
+        self.metric_distance_table(query, Metric::L2)
+    }
+
+    pub fn metric_distance_table(&self, query: &[f64], metric: Metric) -> DistanceTable {
+        let num_subspaces = self.num_subspaces();
+        let mut table = Vec::with_capacity(num_subspaces * self.num_centroids);
+        for (subspace, codebook) in self.codebooks.iter().enumerate() {
+            let slice = &query[self.subspace_range(subspace, num_subspaces)];
+            table.extend(codebook.iter().map(|centroid| match metric {
+                Metric::L2 => squared_l2_f64(centroid, slice),
+                Metric::Cosine | Metric::Dot => dot_f64(centroid, slice),
+            }));
+        }
+        let norms = match metric {
+            Metric::Cosine => self
+                .codebooks
+                .iter()
+                .flatten()
+                .map(|centroid| dot_f64(centroid, centroid))
+                .collect(),
+            Metric::L2 | Metric::Dot => Vec::new(),
+        };
+        DistanceTable {
+            metric,
+            num_centroids: self.num_centroids,
+            table,
+            norms,
+            query_norm: dot_f64(query, query),
+        }
+    }
+
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut bytes = Vec::new();
+        for val in [self.dimension, self.num_subspaces(), self.num_centroids] {
+            bytes.extend_from_slice(&(val as u64).to_le_bytes());
+        }
+        for val in self.codebooks.iter().flatten().flatten() {
+            bytes.extend_from_slice(&val.to_le_bytes());
+        }
+        bytes
+    }
+
+    pub fn from_bytes(bytes: &[u8]) -> Option<Self> {
+        if bytes.len() < 24 {
+            return None;
+        }
+        let (header, rest) = bytes.split_at(24);
+        let mut header = header
+            .chunks_exact(8)
+            .map(|chunk| u64::from_le_bytes(chunk.try_into().unwrap()) as usize);
+        let (dimension, num_subspaces, num_centroids) =
+            (header.next()?, header.next()?, header.next()?);
+        if num_subspaces == 0
+            || num_subspaces > dimension
+            || num_centroids == 0
+            || num_centroids > u8::MAX as usize + 1
+        {
+            return None;
+        }
+        if rest.len() != dimension.checked_mul(num_centroids)?.checked_mul(8)? {
+            return None;
+        }
+
+        let mut values = rest
+            .chunks_exact(8)
+            .map(|chunk| f64::from_le_bytes(chunk.try_into().unwrap()));
+        let mut quantizer = Self {
+            dimension,
+            num_centroids,
+            codebooks: Vec::with_capacity(num_subspaces),
+        };
+        for subspace in 0..num_subspaces {
+            let sub_dimension = quantizer.subspace_range(subspace, num_subspaces).len();
+            let codebook = (0..num_centroids)
+                .map(|_| values.by_ref().take(sub_dimension).collect())
+                .collect();
+            quantizer.codebooks.push(codebook);
+        }
+        Some(quantizer)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rand::SeedableRng;
+    use rand::rngs::StdRng;
+
+    fn random_points(rng: &mut StdRng, count: usize, dimension: usize) -> Vec<Vec<f64>> {
+        (0..count)
+            .map(|_| (0..dimension).map(|_| rng.gen_range(-1.0..1.0)).collect())
+            .collect()
+    }
+
+    #[test]
+    fn test_train_rejects_bad_subspace_counts() {
+        let mut rng = StdRng::seed_from_u64(0);
+        let points = random_points(&mut rng, 10, 4);
+
+        assert!(ProductQuantizer::train(&points, 0, 16, 5, &mut rng).is_none());
+        assert!(ProductQuantizer::train(&points, 5, 16, 5, &mut rng).is_none());
+        assert!(ProductQuantizer::train(&[], 2, 16, 5, &mut rng).is_none());
+    }
+
+    #[test]
+    fn test_encode_decode_with_uneven_subspaces() {
+        let mut rng = StdRng::seed_from_u64(1);
+        let points = random_points(&mut rng, 200, 7);
+        let quantizer = ProductQuantizer::train(&points, 3, 16, 10, &mut rng).unwrap();
+
+        let code = quantizer.encode(&points[0]);
+        assert_eq!(code.len(), 3);
+        assert_eq!(quantizer.decode(&code).len(), 7);
+    }
+
+    #[test]
+    fn test_distance_table_matches_decoded_distance() {
+        let mut rng = StdRng::seed_from_u64(2);
+        let points = random_points(&mut rng, 300, 8);
+        let quantizer = ProductQuantizer::train(&points, 4, 32, 10, &mut rng).unwrap();
+        let query = random_points(&mut rng, 1, 8).remove(0);
+        let table = quantizer.distance_table(&query);
+
+        for point in points.iter().take(20) {
+            let code = quantizer.encode(point);
+            let expected = squared_l2_f64(&query, &quantizer.decode(&code));
+            assert!((table.distance(&code) - expected).abs() < 1e-9);
+        }
+    }
+
+    #[test]
+    fn test_metric_distance_table_matches_decoded_distance() {
+        let mut rng = StdRng::seed_from_u64(5);
+        let points = random_points(&mut rng, 300, 7);
+        let quantizer = ProductQuantizer::train(&points, 3, 32, 10, &mut rng).unwrap();
+        let query = random_points(&mut rng, 1, 7).remove(0);
+
+        for metric in [Metric::L2, Metric::Cosine, Metric::Dot] {
+            let table = quantizer.metric_distance_table(&query, metric);
+            for point in points.iter().take(20) {
+                let code = quantizer.encode(point);
+                let expected = metric.distance(&query, &quantizer.decode(&code));
+                assert!(
+                    (table.distance(&code) - expected).abs() < 1e-9,
+                    "{metric:?}"
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn test_exactly_representable_points_have_zero_error() {
+        let mut rng = StdRng::seed_from_u64(3);
+        let points = vec![vec![1.0, 2.0, 3.0, 4.0], vec![5.0, 6.0, 7.0, 8.0]];
+        let quantizer = ProductQuantizer::train(&points, 2, 256, 10, &mut rng).unwrap();
+
+        assert_eq!(quantizer.num_centroids(), 2);
+        for point in &points {
+            assert_eq!(&quantizer.decode(&quantizer.encode(point)), point);
+        }
+    }
+
+    #[test]
+    fn test_bytes_round_trip() {
+        let mut rng = StdRng::seed_from_u64(4);
+        let points = random_points(&mut rng, 50, 6);
+        let quantizer = ProductQuantizer::train(&points, 4, 8, 5, &mut rng).unwrap();
+        let bytes = quantizer.to_bytes();
+
+        assert_eq!(ProductQuantizer::from_bytes(&bytes), Some(quantizer));
+        assert!(ProductQuantizer::from_bytes(&bytes[..bytes.len() - 8]).is_none());
+
+        // No centroids means no codebook bytes, whatever the dimension.
+        let no_centroids: Vec<u8> = [u64::MAX, u64::MAX, 0]
+            .iter()
+            .flat_map(|val| val.to_le_bytes())
+            .collect();
+        assert!(ProductQuantizer::from_bytes(&no_centroids).is_none());
+    }
+}
@@ -0,0 +1,206 @@
+use crate::linalg::element::Element;
+use crate::linalg::metric::Metric;
+use crate::linalg::vector::Vector;
+
+/// SQ8 codec: every dimension is mapped linearly from its trained
+/// `[min, max]` range onto `0..=255`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ScalarQuantizer {
+    mins: Vec<f64>,
+    steps: Vec<f64>,
+}
+
+impl ScalarQuantizer {
+    pub fn train<'a, T: Element>(vectors: impl IntoIterator<Item = &'a Vector<T>>) -> Option<Self> {
+        let mut vectors = vectors.into_iter();
+        let first = vectors.next()?.to_f64();
+        let mut mins = first.clone();
+        let mut maxs = first;
+
+        for vector in vectors {
+            for (i, val) in vector.data().iter().enumerate() {
+                let val = val.to_f64();
+                mins[i] = mins[i].min(val);
+                maxs[i] = maxs[i].max(val);
+            }
+        }
+
+        let steps = mins
+            .iter()
+            .zip(&maxs)
+            .map(|(min, max)| (max - min) / u8::MAX as f64)
+            .collect();
+        Some(Self { mins, steps })
+    }
+
+    pub fn dimension(&self) -> usize {
+        self.mins.len()
+    }
+
+    pub fn encode<T: Element>(&self, vector: &Vector<T>) -> Vec<u8> {
+        vector
+            .data()
+            .iter()
+            .zip(self.mins.iter().zip(&self.steps))
+            .map(|(val, (min, step))| {
+                if *step == 0.0 {
+                    return 0;
+                }
+                ((val.to_f64() - min) / step)
+                    .round()
+                    .clamp(0.0, u8::MAX as f64) as u8
+            })
+            .collect()
+    }
+
+    pub fn decode<T: Element>(&self, code: &[u8]) -> Vector<T> {
+        let data: Vec<f64> = code
+            .iter()
+            .zip(self.mins.iter().zip(&self.steps))
+            .map(|(code, (min, step))| min + *code as f64 * step)
+            .collect();
+        Vector::from_f64(&data)
+    }
+
+    /// Asymmetric distance between a full-precision query and a code.
+    pub fn squared_distance(&self, query: &[f64], code: &[u8]) -> f64 {
+        query
+            .iter()
+            .zip(code)
+            .zip(self.mins.iter().zip(&self.steps))
+            .map(|((q, code), (min, step))| (q - (min + *code as f64 * step)).powi(2))
+            .sum::<f64>()
+    }
+
+    /// Asymmetric distance under `metric`, with the same conventions as
+    /// `Metric::distance` applied to the decoded code.
+    pub fn distance(&self, metric: Metric, query: &[f64], code: &[u8]) -> f64 {
+        if metric == Metric::L2 {
+            return self.squared_distance(query, code);
+        }
+        let (mut dot, mut code_norm) = (0.0, 0.0);
+        for ((q, code), (min, step)) in query
+            .iter()
+            .zip(code)
+            .zip(self.mins.iter().zip(&self.steps))
+        {
+            let val = min + *code as f64 * step;
+            dot += q * val;
+            code_norm += val * val;
+        }
+        match metric {
+            Metric::Dot => -dot,
+            _ => {
+                let norm_product = (query.iter().map(|q| q * q).sum::<f64>() * code_norm).sqrt();
+                if norm_product == 0.0 {
+                    return 1.0;
+                }
+                1.0 - dot / norm_product
+            }
+        }
+    }
+
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut bytes = Vec::with_capacity(8 + self.mins.len() * 16);
+        bytes.extend_from_slice(&(self.mins.len() as u64).to_le_bytes());
+        for val in self.mins.iter().chain(&self.steps) {
+            bytes.extend_from_slice(&val.to_le_bytes());
+        }
+        bytes
+    }
+
+    pub fn from_bytes(bytes: &[u8]) -> Option<Self> {
+        if bytes.len() < 8 {
+            return None;
+        }
+        let (len_bytes, rest) = bytes.split_at(8);
+        let dimension = u64::from_le_bytes(len_bytes.try_into().unwrap()) as usize;
+        if rest.len() != dimension.checked_mul(16)? {
+            return None;
+        }
+        let values: Vec<f64> = rest
+            .chunks_exact(8)
+            .map(|chunk| f64::from_le_bytes(chunk.try_into().unwrap()))
+            .collect();
+        let (mins, steps) = values.split_at(dimension);
+        Some(Self {
+            mins: mins.to_vec(),
+            steps: steps.to_vec(),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn training_set() -> Vec<Vector> {
+        vec![
+            Vector::new(vec![0.0, -1.0, 5.0]),
+            Vector::new(vec![10.0, 1.0, 5.0]),
+            Vector::new(vec![5.0, 0.0, 5.0]),
+        ]
+    }
+
+    #[test]
+    fn test_train_on_empty_set() {
+        let vectors: Vec<Vector> = Vec::new();
+        assert!(ScalarQuantizer::train(&vectors).is_none());
+    }
+
+    #[test]
+    fn test_encode_uses_full_code_range() {
+        let quantizer = ScalarQuantizer::train(&training_set()).unwrap();
+
+        assert_eq!(quantizer.dimension(), 3);
+        assert_eq!(quantizer.encode(&training_set()[0]), vec![0, 0, 0]);
+        assert_eq!(quantizer.encode(&training_set()[1]), vec![255, 255, 0]);
+    }
+
+    #[test]
+    fn test_decode_error_is_bounded_by_half_a_step() {
+        let quantizer = ScalarQuantizer::train(&training_set()).unwrap();
+        let vector = Vector::new(vec![3.3, 0.42, 5.0]);
+
+        let decoded: Vector = quantizer.decode(&quantizer.encode(&vector));
+
+        for (original, restored) in vector.data().iter().zip(decoded.data()) {
+            assert!((original - restored).abs() <= 10.0 / 255.0 / 2.0 + 1e-12);
+        }
+    }
+
+    #[test]
+    fn test_asymmetric_distance_matches_decoded_distance() {
+        let quantizer = ScalarQuantizer::train(&training_set()).unwrap();
+        let query = Vector::new(vec![1.0, 2.0, 3.0]);
+        let code = quantizer.encode(&Vector::new(vec![7.0, -0.5, 5.0]));
+
+        let decoded: Vector = quantizer.decode(&code);
+        let expected = query.squared_distance(&decoded);
+
+        assert!((quantizer.squared_distance(query.data(), &code) - expected).abs() < 1e-9);
+    }
+
+    #[test]
+    fn test_metric_distance_matches_decoded_distance() {
+        let quantizer = ScalarQuantizer::train(&training_set()).unwrap();
+        let query = Vector::new(vec![1.0, 2.0, 3.0]);
+        let code = quantizer.encode(&Vector::new(vec![7.0, -0.5, 5.0]));
+        let decoded: Vector = quantizer.decode(&code);
+
+        for metric in [Metric::L2, Metric::Cosine, Metric::Dot] {
+            let expected = metric.distance(query.data(), decoded.data());
+            let distance = quantizer.distance(metric, query.data(), &code);
+            assert!((distance - expected).abs() < 1e-9, "{metric:?}");
+        }
+    }
+
+    #[test]
+    fn test_bytes_round_trip() {
+        let quantizer = ScalarQuantizer::train(&training_set()).unwrap();
+        let bytes = quantizer.to_bytes();
+
+        assert_eq!(ScalarQuantizer::from_bytes(&bytes), Some(quantizer));
+        assert!(ScalarQuantizer::from_bytes(&bytes[..bytes.len() - 1]).is_none());
+    }
+}
@@ -0,0 +1,208 @@
+use super::node::{LayerNum, NodeId};
+use dashmap::DashMap;
+use std::sync::atomic::{AtomicU32, Ordering};
+use std::sync::{Mutex, OnceLock, RwLock};
+
+const SEGMENT_NODES: usize = 1024;
+const LOCK_STRIPES: usize = 64;
+
+/// Most nodes an `Adjacency` can link: ids are stored as `u32`. Callers
+/// reject inserts past it before linking.
+pub const MAX_NODES: usize = u32::MAX as usize + 1;
+
+fn compact_id(node_id: NodeId) -> u32 {
+    u32::try_from(node_id).expect("Callers keep node ids below MAX_NODES")
+}
+
+/// Neighbor lists of a layered graph, stored as `u32` ids.
+///
+/// Every node has a layer-0 list, so layer 0 is one flat array with
+/// `capacity + 1` slots per node: the length, then the ids. It grows in
+/// segments of `SEGMENT_NODES` nodes, so slots never move and readers only
+/// take a shared lock on the segment table. Few nodes reach the upper
+/// layers, so those lists sit in a map keyed by node. Every list holds at
+/// most `capacity` ids; the capacity is fixed by the first `fix_capacity`.
+///
+/// Writes to one node are serialized through a striped lock. Reads take no
+/// per-node lock: a read racing a write may see a mix of the old and new
+/// list, but every id in either is a linked node.
+pub struct Adjacency {
+    capacity: OnceLock<usize>,
+    segments: RwLock<Vec<Box<[AtomicU32]>>>,
+    upper: DashMap<NodeId, Vec<Box<[u32]>>>,
+    locks: Box<[Mutex<()>]>,
+}
+
+impl Default for Adjacency {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Adjacency {
+    pub fn new() -> Self {
+        Self {
+            capacity: OnceLock::new(),
+            segments: RwLock::new(Vec::new()),
+            upper: DashMap::new(),
+            locks: (0..LOCK_STRIPES).map(|_| Mutex::new(())).collect(),
+        }
+    }
+
+    pub fn capacity(&self) -> Option<usize> {
+        self.capacity.get().copied()
+    }
+
+    /// Fixes the per-list capacity to `max_degree` if it is not fixed yet,
+    /// and returns the capacity in effect. A list never holds more ids than
+    /// there are nodes, so larger degrees are capped at `MAX_NODES - 1`.
+    pub fn fix_capacity(&self, max_degree: usize) -> usize {
+        *self.capacity.get_or_init(|| max_degree.min(MAX_NODES - 1))
+    }
+
+    fn stride(&self) -> Option<usize> {
+        self.capacity().map(|capacity| capacity + 1)
+    }
+
+    pub fn neighbor_ids(&self, node_id: NodeId, layer: LayerNum) -> Vec<NodeId> {
+        if layer > 0 {
+            return self
+                .upper
+                .get(&node_id)
+                .and_then(|layers| {
+                    let ids = layers.get(layer - 1)?;
+                    Some(ids.iter().map(|id| *id as NodeId).collect())
+                })
+                .unwrap_or_default();
+        }
+
+        let Some(stride) = self.stride() else {
+            return Vec::new();
+        };
+        let segments = self.segments.read().unwrap();
+        let Some(segment) = segments.get(node_id / SEGMENT_NODES) else {
+            return Vec::new();
+        };
+        let slots = &segment[(node_id % SEGMENT_NODES) * stride..][..stride];
+        let len = slots[0].load(Ordering::Acquire) as usize;
+        slots[1..=len]
+            .iter()
+            .map(|id| id.load(Ordering::Relaxed) as NodeId)
+            .collect()
+    }
+
+    /// Replaces the list of `node_id` at `layer` with the result of `f`
+    /// while no other write to the node runs. `f` must leave at most
+    /// `capacity` ids, choosing which to drop itself, and must not touch
+    /// this `Adjacency`. Panics if the capacity is not fixed yet.
+    pub fn update<R>(
+        &self,
+        node_id: NodeId,
+        layer: LayerNum,
+        f: impl FnOnce(&mut Vec<NodeId>) -> R,
+    ) -> R {
+        let capacity = self
+            .capacity()
+            .expect("The adjacency capacity is fixed before linking");
+        if layer > 0 {
+            let mut layers = self.upper.entry(node_id).or_default();
+            if layers.len() < layer {
+                layers.resize_with(layer, Box::default);
+            }
+            let list = &mut layers[layer - 1];
+            let mut ids = list.iter().map(|id| *id as NodeId).collect();
+            let result = f(&mut ids);
+            debug_assert!(ids.len() <= capacity, "Lists must fit the capacity");
+            ids.truncate(capacity);
+            *list = ids.into_iter().map(compact_id).collect();
+            return result;
+        }
+
+        let _guard = self.locks[node_id % LOCK_STRIPES].lock().unwrap();
+        self.reserve_segment(node_id / SEGMENT_NODES);
+        let segments = self.segments.read().unwrap();
+        let stride = capacity + 1;
+        let slots =
+            &segments[node_id / SEGMENT_NODES][(node_id % SEGMENT_NODES) * stride..][..stride];
+        let len = slots[0].load(Ordering::Relaxed) as usize;
+        let mut ids = slots[1..=len]
+            .iter()
+            .map(|id| id.load(Ordering::Relaxed) as NodeId)
+            .collect();
+        let result = f(&mut ids);
+        // Only a guard for the slots; callers prune lists themselves.
+        debug_assert!(ids.len() <= capacity, "Lists must fit the capacity");
+        ids.truncate(capacity);
+        for (slot, id) in slots[1..].iter().zip(&ids) {
+            slot.store(compact_id(*id), Ordering::Relaxed);
+        }
+        // Publishing the length last means a reader that sees it also sees
+        // the ids written before it.
+        slots[0].store(ids.len() as u32, Ordering::Release);
+        result
+    }
+
+    fn reserve_segment(&self, segment: usize) {
+        if segment < self.segments.read().unwrap().len() {
+            return;
+        }
+        let slots = self
+            .stride()
+            .and_then(|stride| stride.checked_mul(SEGMENT_NODES))
+            .expect("The capacity is fixed and below MAX_NODES");
+        let mut segments = self.segments.write().unwrap();
+        while segments.len() <= segment {
+            segments.push((0..slots).map(|_| AtomicU32::new(0)).collect());
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::thread;
+
+    #[test]
+    fn test_layers_are_independent() {
+        let adjacency = Adjacency::new();
+        assert_eq!(adjacency.fix_capacity(3), 3);
+        assert_eq!(
+            adjacency.fix_capacity(8),
+            3,
+            "The first capacity should stay fixed."
+        );
+
+        adjacency.update(5000, 0, |ids| ids.extend([1, 2]));
+        adjacency.update(5000, 2, |ids| ids.push(7));
+        adjacency.update(5000, 0, |ids| ids.push(3));
+
+        assert_eq!(adjacency.neighbor_ids(5000, 0), vec![1, 2, 3]);
+        assert_eq!(adjacency.neighbor_ids(5000, 1), Vec::<NodeId>::new());
+        assert_eq!(adjacency.neighbor_ids(5000, 2), vec![7]);
+        assert_eq!(adjacency.neighbor_ids(4999, 0), Vec::<NodeId>::new());
+        assert_eq!(adjacency.neighbor_ids(9000, 0), Vec::<NodeId>::new());
+    }
+
+    #[test]
+    fn test_concurrent_updates_are_not_lost() {
+        let adjacency = Adjacency::new();
+        adjacency.fix_capacity(64);
+
+        thread::scope(|scope| {
+            for worker in 0..4 {
+                let adjacency = &adjacency;
+                scope.spawn(move || {
+                    for i in 0..16 {
+                        adjacency.update(0, 0, |ids| ids.push(worker * 16 + i));
+                        adjacency.update((worker + 1) * SEGMENT_NODES, 0, |ids| ids.push(i));
+                    }
+                });
+            }
+        });
+
+        let mut ids = adjacency.neighbor_ids(0, 0);
+        ids.sort();
+        assert_eq!(ids, (0..64).collect::<Vec<_>>());
+        assert_eq!(adjacency.neighbor_ids(4 * SEGMENT_NODES, 0).len(), 16);
+    }
+}
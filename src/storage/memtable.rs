@@ -1,35 +1,77 @@
-use super::node::{Node, NodeId};
-use std::sync::atomic::{AtomicUsize, Ordering};
-use std::sync::RwLock;
-use std::sync::Arc;
 use dashmap::DashMap;
-use crate::linalg::vector::Vector;
 
+#[derive(Debug, Clone)]
+pub struct MemTableEntry {
+    pub key: Vec<u8>,
+    pub value: Option<Vec<u8>>,
+    pub timestamp: u128,
+    pub deleted: bool,
+}
 
 pub struct MemTable {
-    nodes: DashMap<NodeId, Arc<RwLock<Node>>>,
-    next_node_id: AtomicUsize
+    entries: DashMap<Vec<u8>, MemTableEntry>,
 }
 
 impl MemTable {
     pub fn new() -> Self {
         Self {
-            nodes: DashMap::new(),
-            next_node_id: AtomicUsize::new(0),
+            entries: DashMap::new(),
         }
     }
 
-    pub fn insert(&self, vector: Vector) -> NodeId {
-        let node_id = self.next_node_id.fetch_add(1, Ordering::SeqCst);
+    pub fn set(&self, key: &[u8], value: Option<&[u8]>, timestamp: u128) {
+        self.entries.insert(
+            key.to_vec(),
+            MemTableEntry {
+                key: key.to_vec(),
+                value: value.map(|value| value.to_vec()),
+                timestamp,
+                deleted: false,
+            },
+        );
+    }
 
-        let new_node = Node::new(node_id, vector);
+    pub fn delete(&self, key: &[u8], timestamp: u128) {
+        self.entries.insert(
+            key.to_vec(),
+            MemTableEntry {
+                key: key.to_vec(),
+                value: None,
+                timestamp,
+                deleted: true,
+            },
+        );
+    }
 
-        self.nodes.insert(node_id, Arc::new(RwLock::new(new_node)));
+    /// Returns the latest entry for `key`, including tombstones, so callers
+    /// reading through to older tables know the key was deleted.
+    pub fn latest_entry(&self, key: &[u8]) -> Option<MemTableEntry> {
+        self.entries.get(key).map(|entry| entry.clone())
+    }
 
-        node_id
+    /// All key/value entries, tombstones included, sorted by key.
+    pub fn sorted_entries(&self) -> Vec<MemTableEntry> {
+        let mut entries: Vec<MemTableEntry> =
+            self.entries.iter().map(|entry| entry.clone()).collect();
+        entries.sort_by(|a, b| a.key.cmp(&b.key));
+        entries
     }
 
-    pub fn get(&self, node_id: &NodeId) -> Option<Arc<RwLock<Node>>> {
-        self.nodes.get(node_id).map(|node_ref| node_ref.clone())
+    pub fn clear_entries(&self) {
+        self.entries.clear();
+    }
+
+    /// Returns the live entry for `key`; tombstones are reported as absent.
+    pub fn entry(&self, key: &[u8]) -> Option<MemTableEntry> {
+        self.entries
+            .get(key)
+            .filter(|entry| !entry.deleted)
+            .map(|entry| entry.clone())
+    }
+}
+
+impl Default for MemTable {
+    fn default() -> Self {
+        Self::new()
     }
 }
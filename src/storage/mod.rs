@@ -1,16 +1,265 @@
-mod sstable;
-mod wal;
-mod hnsw;
-mod memtable;
-mod node;
+pub mod adjacency;
+pub mod memtable;
+pub mod node;
+pub mod sstable;
+pub mod vector_file;
+pub mod wal;
+use memtable::{MemTable, MemTableEntry};
+use sstable::SSTable;
+use std::collections::BTreeMap;
+use std::fs::remove_file;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::time::{SystemTime, UNIX_EPOCH};
 use wal::WAL;
-use memtable::MemTable;
-
 
 pub struct Storage {
-   wal: WAL,
-   mem_table: MemTable
+    dir: PathBuf,
+    wal: WAL,
+    mem_table: MemTable,
+    /// Flushed tables, oldest first.
+    sstables: Vec<SSTable>,
+}
+
+impl Storage {
+    pub fn open(dir: &Path) -> io::Result<Self> {
+        let (wal, mem_table) = wal::load_from_dir(dir)?;
+        let mut sstable_files = wal::files_with_ext(dir, "sst")?;
+        sstable_files.sort();
+        let sstables = sstable_files
+            .iter()
+            .map(|path| SSTable::open(path))
+            .collect::<io::Result<Vec<_>>>()?;
+        Ok(Self {
+            dir: dir.to_owned(),
+            wal,
+            mem_table,
+            sstables,
+        })
+    }
+
+    pub fn set(&mut self, key: &[u8], value: &[u8], timestamp: u128) -> io::Result<()> {
+        self.wal.set(key, value, timestamp)?;
+        self.mem_table.set(key, Some(value), timestamp);
+        Ok(())
+    }
+
+    pub fn delete(&mut self, key: &[u8], timestamp: u128) -> io::Result<()> {
+        self.wal.delete(key, timestamp)?;
+        self.mem_table.delete(key, timestamp);
+        Ok(())
+    }
+
+    /// Looks `key` up in the memtable, then in the SSTables from newest to
+    /// oldest. The first entry found wins, so a tombstone hides older values.
+    pub fn get(&self, key: &[u8]) -> io::Result<Option<MemTableEntry>> {
+        if let Some(entry) = self.mem_table.latest_entry(key) {
+            return Ok(Some(entry).filter(|entry| !entry.deleted));
+        }
+        for sstable in self.sstables.iter().rev() {
+            if let Some(entry) = sstable.get(key)? {
+                return Ok(Some(entry).filter(|entry| !entry.deleted));
+            }
+        }
+        Ok(None)
+    }
+
+    /// Returns the live entries whose key starts with `prefix`, in key order.
+    pub fn scan_prefix(&self, prefix: &[u8]) -> io::Result<Vec<MemTableEntry>> {
+        let mut merged = BTreeMap::new();
+        for sstable in &self.sstables {
+            for entry in sstable.scan_prefix(prefix)? {
+                merged.insert(entry.key.clone(), entry);
+            }
+        }
+        for entry in self.mem_table.sorted_entries() {
+            if entry.key.starts_with(prefix) {
+                merged.insert(entry.key.clone(), entry);
+            }
+        }
+        Ok(merged
+            .into_values()
+            .filter(|entry| !entry.deleted)
+            .collect())
+    }
+
+    pub fn flush(&mut self) -> io::Result<()> {
+        self.wal.flush()
+    }
+
+    /// Writes the memtable to a new SSTable and starts an empty WAL, so
+    /// reopening no longer has to replay those writes.
+    pub fn checkpoint(&mut self) -> io::Result<()> {
+        let entries = self.mem_table.sorted_entries();
+        if entries.is_empty() {
+            return Ok(());
+        }
+        let path = self.next_sstable_path();
+        self.sstables.push(SSTable::write(&path, &entries)?);
+
+        self.mem_table.clear_entries();
+        let old_wal = self.wal.path().to_owned();
+        remove_file(&old_wal)?;
+        self.wal = WAL::new(&self.dir)?;
+        Ok(())
+    }
+
+    /// Path for a table newer than every existing one.
+    fn next_sstable_path(&self) -> PathBuf {
+        let sequence = self.sstables.last().map_or(0, |sstable| {
+            sstable
+                .path()
+                .file_stem()
+                .and_then(|stem| stem.to_str())
+                .and_then(|stem| stem.parse::<u64>().ok())
+                .map_or(0, |sequence| sequence + 1)
+        });
+        self.dir.join(format!("{sequence:020}.sst"))
+    }
+
+    /// Merges all SSTables into one, dropping overwritten values and
+    /// tombstones. The merged table is renamed into place under a new
+    /// sequence number before the old tables are removed, so a crash in
+    /// between leaves it as the newest table and reads still agree.
+    pub fn compact(&mut self) -> io::Result<()> {
+        if self.sstables.len() < 2 {
+            return Ok(());
+        }
+        let mut merged = BTreeMap::new();
+        for sstable in &self.sstables {
+            for entry in sstable.scan_prefix(b"")? {
+                merged.insert(entry.key.clone(), entry);
+            }
+        }
+        let live: Vec<MemTableEntry> = merged
+            .into_values()
+            .filter(|entry| !entry.deleted)
+            .collect();
+
+        let path = self.next_sstable_path();
+        let staging = path.with_extension("sst.tmp");
+        SSTable::write(&staging, &live)?;
+        std::fs::rename(&staging, &path)?;
+        let compacted = SSTable::open(&path)?;
+        for sstable in self.sstables.drain(..) {
+            sstable.remove()?;
+        }
+        self.sstables.push(compacted);
+        Ok(())
+    }
 }
 
+/// Nanoseconds since the Unix epoch, used to timestamp writes.
+pub fn timestamp() -> u128 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |duration| duration.as_nanos())
+}
 
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::tempdir;
 
+    #[test]
+    fn test_storage_reads_through_sstables() -> io::Result<()> {
+        let dir = tempdir()?;
+        let mut storage = Storage::open(dir.path())?;
+        storage.set(b"a", b"1", 1)?;
+        storage.set(b"b", b"2", 2)?;
+        storage.checkpoint()?;
+        storage.set(b"a", b"3", 3)?;
+        storage.delete(b"b", 4)?;
+        storage.checkpoint()?;
+        storage.set(b"c", b"5", 5)?;
+        storage.flush()?;
+
+        let storage = Storage::open(dir.path())?;
+
+        assert_eq!(
+            storage.get(b"a")?.unwrap().value.as_deref(),
+            Some(&b"3"[..])
+        );
+        assert!(storage.get(b"b")?.is_none(), "The tombstone should hide b.");
+        assert_eq!(storage.get(b"c")?.unwrap().timestamp, 5);
+        assert_eq!(wal::files_with_ext(dir.path(), "sst")?.len(), 2);
+        assert_eq!(wal::files_with_ext(dir.path(), "wal")?.len(), 1);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_storage_scan_prefix_and_compact() -> io::Result<()> {
+        let dir = tempdir()?;
+        let mut storage = Storage::open(dir.path())?;
+        storage.set(b"k/1", b"old", 1)?;
+        storage.set(b"k/2", b"two", 2)?;
+        storage.set(b"x/1", b"other", 3)?;
+        storage.checkpoint()?;
+        storage.set(b"k/1", b"new", 4)?;
+        storage.delete(b"k/2", 5)?;
+        storage.checkpoint()?;
+        storage.set(b"k/3", b"three", 6)?;
+
+        let scan = |storage: &Storage| -> io::Result<Vec<(Vec<u8>, Vec<u8>)>> {
+            Ok(storage
+                .scan_prefix(b"k/")?
+                .into_iter()
+                .map(|entry| (entry.key, entry.value.unwrap()))
+                .collect())
+        };
+        let expected = vec![
+            (b"k/1".to_vec(), b"new".to_vec()),
+            (b"k/3".to_vec(), b"three".to_vec()),
+        ];
+        assert_eq!(scan(&storage)?, expected);
+
+        storage.compact()?;
+
+        assert_eq!(scan(&storage)?, expected);
+        assert_eq!(wal::files_with_ext(dir.path(), "sst")?.len(), 1);
+        assert_eq!(
+            storage.get(b"x/1")?.unwrap().value.as_deref(),
+            Some(&b"other"[..])
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_compaction_interrupted_before_cleanup_keeps_data() -> io::Result<()> {
+        let dir = tempdir()?;
+        let mut storage = Storage::open(dir.path())?;
+        storage.set(b"a", b"old", 1)?;
+        storage.set(b"b", b"gone", 2)?;
+        storage.checkpoint()?;
+        storage.set(b"a", b"new", 3)?;
+        storage.delete(b"b", 4)?;
+        storage.checkpoint()?;
+        let old_tables: Vec<(PathBuf, Vec<u8>)> = wal::files_with_ext(dir.path(), "sst")?
+            .into_iter()
+            .map(|path| Ok((path.clone(), std::fs::read(&path)?)))
+            .collect::<io::Result<_>>()?;
+
+        storage.compact()?;
+        drop(storage);
+        // Put the superseded tables back, as if the process died right after
+        // the merged table was renamed into place.
+        for (path, bytes) in &old_tables {
+            std::fs::write(path, bytes)?;
+        }
+        let storage = Storage::open(dir.path())?;
+
+        assert_eq!(wal::files_with_ext(dir.path(), "sst")?.len(), 3);
+        assert_eq!(
+            storage.get(b"a")?.unwrap().value.as_deref(),
+            Some(&b"new"[..])
+        );
+        assert!(
+            storage.get(b"b")?.is_none(),
+            "The tombstone should still win."
+        );
+
+        Ok(())
+    }
+}
@@ -1 +1,168 @@
+use super::memtable::MemTableEntry;
+use super::wal::{WAL, WALEntry, WALIterator};
+use std::collections::BTreeMap;
+use std::fs::remove_file;
+use std::io;
+use std::ops::Bound;
+use std::path::{Path, PathBuf};
 
+/// Immutable, key-sorted table of entries written when the memtable is
+/// flushed. Records use the WAL encoding; only the key-to-offset index is
+/// kept in memory and values are read from disk on demand.
+pub struct SSTable {
+    path: PathBuf,
+    index: BTreeMap<Vec<u8>, u64>,
+}
+
+impl SSTable {
+    /// Writes `entries` (tombstones included) to `path` in key order.
+    pub fn write(path: &Path, entries: &[MemTableEntry]) -> io::Result<SSTable> {
+        let mut sorted: Vec<&MemTableEntry> = entries.iter().collect();
+        sorted.sort_by(|a, b| a.key.cmp(&b.key));
+
+        let mut file = WAL::from_path(path)?;
+        for entry in sorted {
+            match &entry.value {
+                Some(value) if !entry.deleted => file.set(&entry.key, value, entry.timestamp)?,
+                _ => file.delete(&entry.key, entry.timestamp)?,
+            }
+        }
+        file.flush()?;
+        SSTable::open(path)
+    }
+
+    pub fn open(path: &Path) -> io::Result<SSTable> {
+        let mut index = BTreeMap::new();
+        let mut offset = 0;
+        for entry in WALIterator::new(path.to_owned())? {
+            let len = entry.encoded_len();
+            index.insert(entry.key, offset);
+            offset += len;
+        }
+        Ok(SSTable {
+            path: path.to_owned(),
+            index,
+        })
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    pub fn len(&self) -> usize {
+        self.index.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.index.is_empty()
+    }
+
+    /// Returns the entry stored for `key`, tombstones included.
+    pub fn get(&self, key: &[u8]) -> io::Result<Option<MemTableEntry>> {
+        let Some(offset) = self.index.get(key) else {
+            return Ok(None);
+        };
+        Ok(WALIterator::from_offset(&self.path, *offset)?
+            .next()
+            .map(to_mem_table_entry))
+    }
+
+    /// Returns every entry whose key starts with `prefix`, in key order and
+    /// tombstones included.
+    pub fn scan_prefix(&self, prefix: &[u8]) -> io::Result<Vec<MemTableEntry>> {
+        let mut range = self
+            .index
+            .range::<[u8], _>((Bound::Included(prefix), Bound::Unbounded))
+            .take_while(|(key, _)| key.starts_with(prefix));
+        let Some((_, offset)) = range.next() else {
+            return Ok(Vec::new());
+        };
+        let count = 1 + range.count();
+
+        // Matching keys are contiguous in the file, so one sequential read
+        // covers them all.
+        Ok(WALIterator::from_offset(&self.path, *offset)?
+            .take(count)
+            .map(to_mem_table_entry)
+            .collect())
+    }
+
+    pub fn remove(self) -> io::Result<()> {
+        remove_file(self.path)
+    }
+}
+
+fn to_mem_table_entry(entry: WALEntry) -> MemTableEntry {
+    MemTableEntry {
+        key: entry.key,
+        value: entry.value,
+        timestamp: entry.timestamp,
+        deleted: entry.deleted,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::tempdir;
+
+    fn entry(key: &[u8], value: Option<&[u8]>, timestamp: u128) -> MemTableEntry {
+        MemTableEntry {
+            key: key.to_vec(),
+            value: value.map(|value| value.to_vec()),
+            timestamp,
+            deleted: value.is_none(),
+        }
+    }
+
+    #[test]
+    fn test_sstable_write_and_get() -> io::Result<()> {
+        let dir = tempdir()?;
+        let path = dir.path().join("1.sst");
+        let entries = vec![
+            entry(b"b", Some(b"two"), 2),
+            entry(b"a", Some(b"one"), 1),
+            entry(b"c", None, 3),
+        ];
+
+        SSTable::write(&path, &entries)?;
+        let table = SSTable::open(&path)?;
+
+        assert_eq!(table.len(), 3);
+        assert_eq!(
+            table.get(b"a")?.unwrap().value.as_deref(),
+            Some(&b"one"[..])
+        );
+        assert_eq!(table.get(b"b")?.unwrap().timestamp, 2);
+        assert!(
+            table.get(b"c")?.unwrap().deleted,
+            "Tombstones should be kept."
+        );
+        assert!(table.get(b"d")?.is_none());
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_sstable_scan_prefix() -> io::Result<()> {
+        let dir = tempdir()?;
+        let entries = vec![
+            entry(b"p/apple", Some(b"1"), 1),
+            entry(b"p/apricot", Some(b"2"), 1),
+            entry(b"p/banana", Some(b"3"), 1),
+            entry(b"q/apple", Some(b"4"), 1),
+        ];
+        let table = SSTable::write(&dir.path().join("1.sst"), &entries)?;
+
+        let keys: Vec<Vec<u8>> = table
+            .scan_prefix(b"p/ap")?
+            .into_iter()
+            .map(|entry| entry.key)
+            .collect();
+
+        assert_eq!(keys, vec![b"p/apple".to_vec(), b"p/apricot".to_vec()]);
+        assert!(table.scan_prefix(b"z")?.is_empty());
+
+        Ok(())
+    }
+}
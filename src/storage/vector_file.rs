@@ -0,0 +1,311 @@
+use crate::linalg::element::{Element, ElementType};
+use crate::linalg::vector::Vector;
+use memmap2::{MmapMut, MmapOptions};
+use std::fs::{File, OpenOptions};
+use std::io;
+use std::marker::PhantomData;
+use std::mem;
+use std::path::Path;
+use std::sync::RwLock;
+
+const MAGIC: &[u8; 4] = b"VECF";
+/// `[magic 4][element tag u8][padding 3][dimension u64][len u64][padding 8]`.
+/// 32 bytes keeps every record aligned for its element type.
+const HEADER_LEN: usize = 32;
+const MIN_CAPACITY: usize = 64;
+
+enum Backing {
+    File(File),
+    Anonymous,
+}
+
+struct Inner {
+    backing: Backing,
+    mmap: MmapMut,
+    dimension: usize,
+    len: usize,
+}
+
+impl Inner {
+    fn stride(&self, element_size: usize) -> usize {
+        self.dimension * element_size
+    }
+
+    fn capacity(&self, element_size: usize) -> usize {
+        match self.stride(element_size) {
+            0 => 0,
+            stride => (self.mmap.len() - HEADER_LEN) / stride,
+        }
+    }
+
+    fn write_header(&mut self) {
+        self.mmap[8..16].copy_from_slice(&(self.dimension as u64).to_le_bytes());
+        self.mmap[16..24].copy_from_slice(&(self.len as u64).to_le_bytes());
+    }
+
+    /// Grows the mapping to hold at least `capacity` records. A file is
+    /// extended and remapped; an anonymous mapping is copied into a larger
+    /// one.
+    fn reserve(&mut self, capacity: usize, element_size: usize) -> io::Result<()> {
+        let size = HEADER_LEN + capacity * self.stride(element_size);
+        match &self.backing {
+            Backing::File(file) => {
+                self.mmap.flush()?;
+                file.set_len(size as u64)?;
+                // SAFETY: the file is opened read-write by this process only
+                // through this `VectorFile`, which never truncates it.
+                self.mmap = unsafe { MmapOptions::new().map_mut(file)? };
+            }
+            Backing::Anonymous => {
+                let mut grown = MmapMut::map_anon(size)?;
+                grown[..self.mmap.len()].copy_from_slice(&self.mmap);
+                self.mmap = grown;
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Append-only store of fixed-length vectors, read through a memory map.
+/// Record `i` starts at byte offset `HEADER_LEN + i * dimension * size`, so
+/// vectors are referenced by index alone and only the pages that are read
+/// become resident; the OS page cache decides what stays in memory. The
+/// dimension is fixed by the first push.
+///
+/// A file-backed store survives reopening; an anonymous one lives only in
+/// memory. Records are stored little-endian, as in `Vector::to_bytes`.
+pub struct VectorFile<T: Element = f64> {
+    inner: RwLock<Inner>,
+    element: PhantomData<T>,
+}
+
+impl<T: Element> VectorFile<T> {
+    /// In-memory store backed by an anonymous mapping.
+    pub fn anonymous() -> io::Result<Self> {
+        let mmap = MmapMut::map_anon(HEADER_LEN)?;
+        Ok(Self::from_inner(Backing::Anonymous, mmap))
+    }
+
+    /// Creates a new file at `path`; fails if one already exists.
+    pub fn create(path: &Path) -> io::Result<Self> {
+        let file = OpenOptions::new()
+            .read(true)
+            .write(true)
+            .create_new(true)
+            .open(path)?;
+        file.set_len(HEADER_LEN as u64)?;
+        // SAFETY: the file was just created and is only modified through
+        // this mapping.
+        let mmap = unsafe { MmapOptions::new().map_mut(&file)? };
+        Ok(Self::from_inner(Backing::File(file), mmap))
+    }
+
+    fn from_inner(backing: Backing, mut mmap: MmapMut) -> Self {
+        mmap[..4].copy_from_slice(MAGIC);
+        mmap[4] = T::ELEMENT_TYPE.tag();
+        Self {
+            inner: RwLock::new(Inner {
+                backing,
+                mmap,
+                dimension: 0,
+                len: 0,
+            }),
+            element: PhantomData,
+        }
+    }
+
+    /// Maps an existing file. Only the header is read, so this takes the
+    /// same time whatever the file size.
+    pub fn open(path: &Path) -> io::Result<Self> {
+        let invalid =
+            |message: &str| io::Error::new(io::ErrorKind::InvalidData, message.to_string());
+        let file = OpenOptions::new().read(true).write(true).open(path)?;
+        // SAFETY: see `create`.
+        let mmap = unsafe { MmapOptions::new().map_mut(&file)? };
+        if mmap.len() < HEADER_LEN || &mmap[..4] != MAGIC {
+            return Err(invalid("Not a vector file"));
+        }
+        if ElementType::from_tag(mmap[4]) != Some(T::ELEMENT_TYPE) {
+            return Err(invalid("Vector file holds another element type"));
+        }
+        let dimension = u64::from_le_bytes(mmap[8..16].try_into().unwrap()) as usize;
+        let len = u64::from_le_bytes(mmap[16..24].try_into().unwrap()) as usize;
+        let end = dimension
+            .checked_mul(T::ELEMENT_TYPE.size())
+            .and_then(|stride| stride.checked_mul(len))
+            .and_then(|records| records.checked_add(HEADER_LEN));
+        if end.is_none_or(|end| end > mmap.len()) {
+            return Err(invalid("Vector file is shorter than its header says"));
+        }
+        Ok(Self {
+            inner: RwLock::new(Inner {
+                backing: Backing::File(file),
+                mmap,
+                dimension,
+                len,
+            }),
+            element: PhantomData,
+        })
+    }
+
+    pub fn len(&self) -> usize {
+        self.inner.read().unwrap().len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    pub fn dimension(&self) -> Option<usize> {
+        Some(self.inner.read().unwrap().dimension).filter(|dimension| *dimension > 0)
+    }
+
+    /// Appends `vector` and returns its index. The first push fixes the
+    /// dimension; later pushes of another length are rejected.
+    pub fn push(&self, vector: &Vector<T>) -> io::Result<usize> {
+        let size = T::ELEMENT_TYPE.size();
+        let mut inner = self.inner.write().unwrap();
+        if inner.dimension == 0 {
+            if vector.is_empty() {
+                return Err(io::Error::new(
+                    io::ErrorKind::InvalidInput,
+                    "Cannot store empty vectors",
+                ));
+            }
+            inner.dimension = vector.len();
+        } else if vector.len() != inner.dimension {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!(
+                    "Expected a vector of dimension {}, got {}",
+                    inner.dimension,
+                    vector.len()
+                ),
+            ));
+        }
+        if inner.len == inner.capacity(size) {
+            let capacity = (inner.len * 2).max(MIN_CAPACITY);
+            inner.reserve(capacity, size)?;
+        }
+
+        let index = inner.len;
+        let stride = inner.stride(size);
+        let mut bytes = Vec::with_capacity(stride);
+        for val in vector.data() {
+            val.write_le(&mut bytes);
+        }
+        let start = HEADER_LEN + index * stride;
+        inner.mmap[start..start + stride].copy_from_slice(&bytes);
+        inner.len += 1;
+        inner.write_header();
+        Ok(index)
+    }
+
+    /// Drops the records from `len` on; later pushes overwrite them. Does
+    /// nothing if the store holds `len` records or fewer.
+    pub fn truncate(&self, len: usize) {
+        let mut inner = self.inner.write().unwrap();
+        if len < inner.len {
+            inner.len = len;
+            inner.write_header();
+        }
+    }
+
+    /// Calls `f` on the components of vector `index` while they are mapped,
+    /// without copying them out.
+    pub fn with_data<R>(&self, index: usize, f: impl FnOnce(&[T]) -> R) -> Option<R> {
+        let size = T::ELEMENT_TYPE.size();
+        let inner = self.inner.read().unwrap();
+        if index >= inner.len {
+            return None;
+        }
+        let stride = inner.stride(size);
+        let start = HEADER_LEN + index * stride;
+        let bytes = &inner.mmap[start..start + stride];
+
+        let direct = cfg!(target_endian = "little")
+            && mem::size_of::<T>() == size
+            && bytes.as_ptr().align_offset(mem::align_of::<T>()) == 0;
+        if direct {
+            // SAFETY: the bytes are `dimension` little-endian values of
+            // `T`'s element type written by `push`, the host is
+            // little-endian, and the pointer is aligned for `T`. Every
+            // element type is a plain number valid for any bit pattern.
+            let data =
+                unsafe { std::slice::from_raw_parts(bytes.as_ptr().cast::<T>(), inner.dimension) };
+            Some(f(data))
+        } else {
+            let data: Vec<T> = bytes.chunks_exact(size).map(T::read_le).collect();
+            Some(f(&data))
+        }
+    }
+
+    /// Returns a copy of vector `index`.
+    pub fn get(&self, index: usize) -> Option<Vector<T>> {
+        self.with_data(index, |data| Vector::new(data.to_vec()))
+    }
+
+    /// Writes dirty pages of a file-backed store to disk.
+    pub fn flush(&self) -> io::Result<()> {
+        let inner = self.inner.read().unwrap();
+        match inner.backing {
+            Backing::File(_) => inner.mmap.flush(),
+            Backing::Anonymous => Ok(()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::tempdir;
+
+    #[test]
+    fn test_vector_file_push_and_reopen() -> io::Result<()> {
+        let dir = tempdir()?;
+        let path = dir.path().join("vectors.vec");
+        let vectors: Vec<Vector<f32>> = (0..200)
+            .map(|i| Vector::new(vec![i as f32, -(i as f32), 0.5]))
+            .collect();
+        {
+            let file = VectorFile::create(&path)?;
+            for (index, vector) in vectors.iter().enumerate() {
+                assert_eq!(file.push(vector)?, index);
+            }
+            assert!(file.push(&Vector::new(vec![1.0])).is_err());
+            file.flush()?;
+            assert!(
+                VectorFile::<f32>::create(&path).is_err(),
+                "Creating over an existing file should fail."
+            );
+        }
+
+        let file: VectorFile<f32> = VectorFile::open(&path)?;
+
+        assert_eq!(file.len(), 200);
+        assert_eq!(file.dimension(), Some(3));
+        assert_eq!(file.get(17), Some(vectors[17].clone()));
+        assert_eq!(file.with_data(199, |data| data[1]), Some(-199.0));
+        assert_eq!(file.get(200), None);
+        assert_eq!(file.push(&Vector::new(vec![9.0, 9.0, 9.0]))?, 200);
+        assert!(VectorFile::<f64>::open(&path).is_err());
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_anonymous_vector_file_grows() -> io::Result<()> {
+        let file: VectorFile = VectorFile::anonymous()?;
+        assert_eq!(file.dimension(), None);
+
+        for i in 0..1000 {
+            file.push(&Vector::new(vec![i as f64; 4]))?;
+        }
+
+        assert_eq!(file.len(), 1000);
+        assert_eq!(file.get(999), Some(Vector::new(vec![999.0; 4])));
+        assert_eq!(file.get(0), Some(Vector::new(vec![0.0; 4])));
+
+        Ok(())
+    }
+}
@@ -1,6 +1,9 @@
+use super::memtable::MemTable;
+use crate::linalg::element::Element;
+use crate::linalg::vector::Vector;
 use std::{
     fs::{File, OpenOptions, read_dir, remove_file},
-    io::{self, BufReader, BufWriter, Read, Write},
+    io::{self, BufReader, BufWriter, Read, Seek, SeekFrom, Write},
     path::{Path, PathBuf},
     time::{SystemTime, UNIX_EPOCH},
 };
@@ -23,6 +26,25 @@ impl WALIterator {
         let reader = BufReader::new(file);
         Ok(WALIterator { reader })
     }
+
+    /// Starts reading at byte `offset`, which must be the start of a record.
+    pub fn from_offset(path: &Path, offset: u64) -> io::Result<WALIterator> {
+        let mut file = OpenOptions::new().read(true).open(path)?;
+        file.seek(SeekFrom::Start(offset))?;
+        let reader = BufReader::new(file);
+        Ok(WALIterator { reader })
+    }
+}
+
+impl WALEntry {
+    /// Size of the entry's record in a WAL or SSTable file.
+    pub fn encoded_len(&self) -> u64 {
+        let value_len = match &self.value {
+            Some(value) if !self.deleted => 8 + value.len(),
+            _ => 0,
+        };
+        (8 + 1 + self.key.len() + value_len + 16) as u64
+    }
 }
 
 impl Iterator for WALIterator {
@@ -68,7 +90,7 @@ impl Iterator for WALIterator {
             key,
             value,
             timestamp,
-            deleted: deleted,
+            deleted,
         })
     }
 }
@@ -80,11 +102,16 @@ pub struct WAL {
 
 impl WAL {
     pub fn new(dir: &Path) -> io::Result<WAL> {
-        let timestamp = SystemTime::now()
+        let mut timestamp = SystemTime::now()
             .duration_since(UNIX_EPOCH)
-            .unwrap()
-            .as_millis();
-        let path = Path::new(dir).join(timestamp.to_string() + ".wal");
+            .map_or(0, |duration| duration.as_millis());
+        // Never reuse an existing log: `load_from_dir` replays the old files
+        // into the new one and then deletes them.
+        let mut path = Path::new(dir).join(timestamp.to_string() + ".wal");
+        while path.exists() {
+            timestamp += 1;
+            path = Path::new(dir).join(timestamp.to_string() + ".wal");
+        }
         let file = OpenOptions::new().create(true).append(true).open(&path)?;
         let file = BufWriter::new(file);
         Ok(WAL { path, file })
@@ -109,6 +136,15 @@ impl WAL {
         Ok(())
     }
 
+    pub fn set_vector<T: Element>(
+        &mut self,
+        key: &[u8],
+        vector: &Vector<T>,
+        timestamp: u128,
+    ) -> io::Result<()> {
+        self.set(key, &vector.to_bytes(), timestamp)
+    }
+
     pub fn delete(&mut self, key: &[u8], timestamp: u128) -> io::Result<()> {
         self.file.write_all(&key.len().to_le_bytes())?;
         self.file.write_all(&(true as u8).to_le_bytes())?;
@@ -120,58 +156,54 @@ impl WAL {
     pub fn flush(&mut self) -> io::Result<()> {
         self.file.flush()
     }
-}
 
-impl IntoIterator for WAL {
-    type Item = WALEntry;
-    type IntoIter = WALIterator;
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
 
-    fn into_iter(self) -> Self::IntoIter {
-        WALIterator::new(self.path).unwrap()
+    /// Flushes pending writes and reads the log back from the start.
+    pub fn into_entries(mut self) -> io::Result<WALIterator> {
+        self.file.flush()?;
+        WALIterator::new(self.path)
     }
 }
 
-pub fn files_with_ext(dir: &Path, ext: &str) -> Vec<PathBuf> {
+pub fn files_with_ext(dir: &Path, ext: &str) -> io::Result<Vec<PathBuf>> {
     let mut files = Vec::new();
-    for file in read_dir(dir).unwrap() {
-        let path = file.unwrap().path();
-        if path.extension().unwrap() == ext {
+    for file in read_dir(dir)? {
+        let path = file?.path();
+        if path.extension().is_some_and(|extension| extension == ext) {
             files.push(path);
         }
     }
 
-    files
+    Ok(files)
 }
 
 pub fn load_from_dir(dir: &Path) -> io::Result<(WAL, MemTable)> {
-    let mut wal_files = files_with_ext(dir, "wal");
+    let mut wal_files = files_with_ext(dir, "wal")?;
     wal_files.sort();
 
-    let mut new_mem_table = MemTable::new(10, 0.5);
+    let new_mem_table = MemTable::new();
     let mut new_wal = WAL::new(dir)?;
     for wal_file in wal_files.iter() {
-        if let Ok(wal) = WAL::from_path(wal_file) {
-            for entry in wal.into_iter() {
-                if entry.deleted {
-                    new_mem_table.delete(entry.key.as_slice(), entry.timestamp);
-                    new_wal.delete(entry.key.as_slice(), entry.timestamp)?;
-                } else {
-                    new_mem_table.set(
-                        entry.key.as_slice(),
-                        Some(entry.value.as_ref().unwrap().as_slice()),
-                        entry.timestamp,
-                    );
-                    new_wal.set(
-                        entry.key.as_slice(),
-                        entry.value.unwrap().as_slice(),
-                        entry.timestamp,
-                    )?;
+        for entry in WALIterator::new(wal_file.clone())? {
+            match entry.value {
+                Some(value) if !entry.deleted => {
+                    new_mem_table.set(&entry.key, Some(&value), entry.timestamp);
+                    new_wal.set(&entry.key, &value, entry.timestamp)?;
+                }
+                _ => {
+                    new_mem_table.delete(&entry.key, entry.timestamp);
+                    new_wal.delete(&entry.key, entry.timestamp)?;
                 }
             }
         }
     }
-    new_wal.flush().unwrap();
-    wal_files.into_iter().for_each(|f| remove_file(f).unwrap());
+    new_wal.flush()?;
+    for wal_file in wal_files {
+        remove_file(wal_file)?;
+    }
 
     Ok((new_wal, new_mem_table))
 }
@@ -179,6 +211,8 @@ pub fn load_from_dir(dir: &Path) -> io::Result<(WAL, MemTable)> {
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::linalg::element::ElementType;
+    use crate::linalg::vector::encoded_element_type;
     use tempfile::tempdir;
 
     #[test]
@@ -227,6 +261,27 @@ mod tests {
         Ok(())
     }
 
+    #[test]
+    fn test_wal_set_vector_records_element_type() -> io::Result<()> {
+        let dir = tempdir()?;
+        let mut wal = WAL::new(dir.path())?;
+
+        let vector: Vector<f32> = Vector::new(vec![0.25, -1.0, 8.5]);
+        wal.set_vector(b"vec", &vector, 7)?;
+        wal.flush()?;
+
+        let entry = wal
+            .into_entries()?
+            .next()
+            .expect("Should read the vector entry");
+        let value = entry.value.unwrap();
+
+        assert_eq!(encoded_element_type(&value), Some(ElementType::F32));
+        assert_eq!(Vector::<f32>::from_bytes(&value), Some(vector));
+
+        Ok(())
+    }
+
     #[test]
     fn test_wal_mixed_operations() -> io::Result<()> {
         let dir = tempdir()?;
@@ -237,7 +292,7 @@ mod tests {
         wal.delete(b"key1", 102)?;
         wal.flush()?;
 
-        let entries: Vec<WALEntry> = wal.into_iter().collect();
+        let entries: Vec<WALEntry> = wal.into_entries()?.collect();
 
         assert_eq!(entries.len(), 3);
 
@@ -269,25 +324,25 @@ mod tests {
         wal2.delete(b"key2", 2001)?;
         wal2.flush()?;
 
-        assert_eq!(files_with_ext(dir.path(), "wal").len(), 2);
+        assert_eq!(files_with_ext(dir.path(), "wal")?.len(), 2);
 
         let (new_wal, mem_table) = load_from_dir(dir.path())?;
 
-        let entry = mem_table.get(b"key1").unwrap();
+        let entry = mem_table.entry(b"key1").unwrap();
         let val1 = entry.value;
         let ts1 = entry.timestamp;
         assert_eq!(val1.as_deref(), Some(&b"new_value"[..]));
         assert_eq!(ts1, 2000);
 
-        let entry = mem_table.get(b"key2");
+        let entry = mem_table.entry(b"key2");
         assert!(entry.is_none());
 
-        let remaining_files = files_with_ext(dir.path(), "wal");
+        let remaining_files = files_with_ext(dir.path(), "wal")?;
         assert_eq!(remaining_files.len(), 1);
 
         assert_eq!(remaining_files[0], new_wal.path);
 
-        let new_entries: Vec<WALEntry> = new_wal.into_iter().collect();
+        let new_entries: Vec<WALEntry> = new_wal.into_entries()?.collect();
         assert_eq!(new_entries.len(), 4);
 
         Ok(())